@@ -2,18 +2,18 @@ use std::sync::Arc;
 
 use eframe::{egui::{self, ScrollArea}, NativeOptions};
 use tokio::runtime::Runtime;
-use winter::document::DocumentNode;
+use winter::document::{media::DownloadAndOpen, DocumentNode};
 
 struct App(DocumentNode, Arc<Runtime>);
 
 impl eframe::App for App {
 	fn update(&mut self, ctx: &eframe::egui::Context, frame: &mut eframe::Frame) {
 		egui_extras::install_image_loaders(ctx);
-		self.0.tick(&self.1);
+		self.0.tick(&self.1, &DownloadAndOpen);
 		egui::CentralPanel::default().show(ctx, |ui| {
 			ScrollArea::new([true, true]).show(ui, |ui| {
 				ui.vertical(|ui| {
-					self.0.show(ui, frame);
+					self.0.show(ui, frame, &DownloadAndOpen);
 				})
 			});
 		});
@@ -32,15 +32,19 @@ fn main() {
 			},
 			DocumentNode::Audio {
 				label: "Test Audio".to_string(),
+				mime: "audio/mpeg".to_string(),
 				fetched: winter::document::media::MaybeLoaded::NotStarted(
 					"https://download.samplelib.com/mp3/sample-3s.mp3".to_string(),
 				),
+				requested: false,
 			},
 			DocumentNode::Video {
 				label: "Test Video".to_string(),
+				mime: "video/mp4".to_string(),
 				fetched: winter::document::media::MaybeLoaded::NotStarted(
 					"https://download.samplelib.com/mp4/sample-5s.mp4".to_string(),
 				),
+				requested: false,
 			},
 		]),
 		rt.clone(),
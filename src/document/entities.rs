@@ -0,0 +1,117 @@
+//! Decoding of HTML character references, which feeds leave in titles and which the HTML
+//! parser leaves in text.
+
+/// Named references which show up in feeds often enough to be worth knowing.
+const NAMED: &[(&str, char)] = &[
+	("amp", '&'),
+	("lt", '<'),
+	("gt", '>'),
+	("quot", '"'),
+	("apos", '\''),
+	("nbsp", '\u{a0}'),
+	("lsquo", '‘'),
+	("rsquo", '’'),
+	("ldquo", '“'),
+	("rdquo", '”'),
+	("ndash", '–'),
+	("mdash", '—'),
+	("hellip", '…'),
+	("bull", '•'),
+	("middot", '·'),
+	("copy", '©'),
+	("reg", '®'),
+	("trade", '™'),
+	("deg", '°'),
+	("times", '×'),
+	("laquo", '«'),
+	("raquo", '»'),
+	("eacute", 'é'),
+	("egrave", 'è'),
+	("aacute", 'á'),
+	("agrave", 'à'),
+	("ouml", 'ö'),
+	("uuml", 'ü'),
+	("auml", 'ä'),
+	("szlig", 'ß'),
+	("ccedil", 'ç'),
+	("ntilde", 'ñ'),
+	("euro", '€'),
+	("pound", '£'),
+];
+
+/// Decodes named, decimal and hexadecimal character references in a single pass, so text
+/// which was escaped twice (like `&amp;lt;`) only loses one layer. Anything which isn't a
+/// reference this knows about is left alone.
+#[must_use]
+pub fn decode_entities(text: &str) -> String {
+	if !text.contains('&') {
+		return text.to_string();
+	}
+	let mut out = String::with_capacity(text.len());
+	let mut rest = text;
+	while let Some(start) = rest.find('&') {
+		out.push_str(&rest[..start]);
+		rest = &rest[start..];
+		let decoded = rest
+			.find(';')
+			.filter(|&end| end <= 32)
+			.and_then(|end| Some((decode_reference(&rest[1..end])?, end)));
+		match decoded {
+			Some((c, end)) => {
+				out.push(c);
+				rest = &rest[end + 1..];
+			}
+			None => {
+				out.push('&');
+				rest = &rest[1..];
+			}
+		}
+	}
+	out.push_str(rest);
+	out
+}
+
+/// Decodes the inside of a reference, like `amp`, `#8217` or `#x2019`.
+fn decode_reference(reference: &str) -> Option<char> {
+	let code = if let Some(hex) = reference
+		.strip_prefix("#x")
+		.or_else(|| reference.strip_prefix("#X"))
+	{
+		u32::from_str_radix(hex, 16).ok()?
+	} else if let Some(decimal) = reference.strip_prefix('#') {
+		decimal.parse().ok()?
+	} else {
+		return NAMED
+			.iter()
+			.find(|(name, _)| *name == reference)
+			.map(|(_, c)| *c);
+	};
+	char::from_u32(code)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::decode_entities;
+
+	#[test]
+	fn decodes_entities() {
+		assert_eq!(decode_entities("Tom &amp; Jerry"), "Tom & Jerry");
+		assert_eq!(decode_entities("It&#8217;s"), "It’s");
+		assert_eq!(decode_entities("It&#x2019;s &#X2019;"), "It’s ’");
+		assert_eq!(decode_entities("&lt;b&gt;"), "<b>");
+	}
+
+	#[test]
+	fn leaves_other_ampersands_alone() {
+		assert_eq!(decode_entities("R&D; Q&A"), "R&D; Q&A");
+		assert_eq!(decode_entities("a & b;"), "a & b;");
+		assert_eq!(decode_entities("&#xFFFFFFFF;"), "&#xFFFFFFFF;");
+		assert_eq!(decode_entities("trailing &amp"), "trailing &amp");
+	}
+
+	#[test]
+	fn decodes_only_once() {
+		assert_eq!(decode_entities("&amp;lt;"), "&lt;");
+		assert_eq!(decode_entities("&amp;amp;"), "&amp;");
+	}
+}
@@ -0,0 +1,619 @@
+use super::{classify_link, is_display_math, list_marker, DocumentNode, LinkKind, ListStyle};
+
+/// Escapes characters which Markdown would otherwise interpret as formatting.
+#[must_use]
+pub fn escape_markdown(text: &str) -> String {
+	let mut out = String::with_capacity(text.len());
+	for c in text.chars() {
+		if matches!(
+			c,
+			'\\' | '`' | '*' | '_' | '[' | ']' | '<' | '>' | '#' | '|'
+		) {
+			out.push('\\');
+		}
+		out.push(c);
+	}
+	out
+}
+
+/// Percent-encodes the characters which would end or break a Markdown link destination, so
+/// `[label](url)` links to all of `url`.
+#[must_use]
+pub fn markdown_destination(url: &str) -> String {
+	let mut out = String::with_capacity(url.len());
+	for c in url.chars() {
+		if c.is_whitespace() || c.is_control() || matches!(c, '(' | ')' | '<' | '>' | '\\') {
+			let mut buf = [0; 4];
+			for byte in c.encode_utf8(&mut buf).bytes() {
+				out.push_str(&format!("%{byte:02X}"));
+			}
+		} else {
+			out.push(c);
+		}
+	}
+	out
+}
+
+/// Escapes characters which are significant in HTML text and attribute values.
+#[must_use]
+pub fn escape_html(text: &str) -> String {
+	let mut out = String::with_capacity(text.len());
+	for c in text.chars() {
+		match c {
+			'&' => out.push_str("&amp;"),
+			'<' => out.push_str("&lt;"),
+			'>' => out.push_str("&gt;"),
+			'"' => out.push_str("&quot;"),
+			'\'' => out.push_str("&#39;"),
+			c => out.push(c),
+		}
+	}
+	out
+}
+
+/// Enough backticks, and at least `min`, to fence `text` as Markdown code without any inside
+/// it ending the code early.
+fn code_fence(text: &str, min: usize) -> String {
+	let (mut longest, mut run) = (0, 0);
+	for c in text.chars() {
+		run = if c == '`' { run + 1 } else { 0 };
+		longest = longest.max(run);
+	}
+	"`".repeat((longest + 1).max(min))
+}
+
+/// Collapses runs of blank lines left behind by nested block elements.
+fn tidy_blocks(text: &str) -> String {
+	let mut out = String::with_capacity(text.len());
+	let mut blank_run = 0;
+	for line in text.trim().lines() {
+		if line.trim().is_empty() {
+			blank_run += 1;
+			if blank_run > 1 {
+				continue;
+			}
+			out.push('\n');
+		} else {
+			blank_run = 0;
+			out.push_str(line.trim_end());
+			out.push('\n');
+		}
+	}
+	out.trim_end().to_string()
+}
+
+impl DocumentNode {
+	/// Serializes this tree as Markdown.
+	#[must_use]
+	pub fn to_markdown(&self) -> String {
+		let mut out = String::new();
+		self.write_markdown(&mut out);
+		tidy_blocks(&out)
+	}
+
+	/// Serializes this tree as an HTML fragment.
+	#[must_use]
+	pub fn to_html(&self) -> String {
+		let mut out = String::new();
+		self.write_html(&mut out);
+		out
+	}
+
+	/// Renders this tree as plain text, with a numbered marker like `[1]` after each link and
+	/// piece of media, and their URLs listed by number at the end. Everything pointing at the
+	/// same URL shares a number. Links within the page get no marker.
+	#[must_use]
+	pub fn to_text_with_footnotes(&self) -> String {
+		let mut footnotes = vec![];
+		let mut out = String::new();
+		self.write_text(&mut out, &mut footnotes);
+		let mut out = tidy_blocks(&out);
+		if !footnotes.is_empty() {
+			out.push_str("\n\n");
+			for (n, url) in footnotes.iter().enumerate() {
+				out.push_str(&format!("[{}] {url}\n", n + 1));
+			}
+			out.pop();
+		}
+		out
+	}
+
+	fn write_text(&self, out: &mut String, footnotes: &mut Vec<String>) {
+		match self {
+			// Unknown elements are mostly blocks, as they're shown.
+			DocumentNode::Root(inner)
+			| DocumentNode::Div(inner)
+			| DocumentNode::Unk(inner)
+			| DocumentNode::Paragraph(inner) => {
+				out.push_str("\n\n");
+				inner.iter().for_each(|el| el.write_text(out, footnotes));
+				out.push_str("\n\n");
+			}
+			DocumentNode::Heading { inner, .. } => {
+				out.push_str(&format!("\n\n{}\n\n", Self::many_to_text(inner, footnotes)));
+			}
+			DocumentNode::LineBreak => out.push('\n'),
+			DocumentNode::Span(inner)
+			| DocumentNode::Emph(inner)
+			| DocumentNode::Strong(inner)
+			| DocumentNode::Strike(inner)
+			| DocumentNode::Underline(inner) => {
+				inner.iter().for_each(|el| el.write_text(out, footnotes));
+			}
+			DocumentNode::Superscript(inner) => {
+				out.push_str(&format!("^{}", Self::many_to_text(inner, footnotes)));
+			}
+			DocumentNode::Subscript(inner) => {
+				out.push_str(&format!("_{}", Self::many_to_text(inner, footnotes)));
+			}
+			DocumentNode::UList(inner) => {
+				out.push_str("\n\n");
+				for el in inner {
+					let item = Self::many_to_text(std::slice::from_ref(el), footnotes);
+					if !item.is_empty() {
+						out.push_str(&format!("- {}\n", item.replace('\n', "\n  ")));
+					}
+				}
+				out.push('\n');
+			}
+			DocumentNode::OList { start, style, items } => {
+				out.push_str("\n\n");
+				let mut n = *start;
+				for el in items {
+					let item = Self::many_to_text(std::slice::from_ref(el), footnotes);
+					if !item.is_empty() {
+						let marker = format!("{}. ", list_marker(n, *style));
+						n += 1;
+						let indent = format!("\n{}", " ".repeat(marker.len()));
+						out.push_str(&format!("{marker}{}\n", item.replace('\n', &indent)));
+					}
+				}
+				out.push('\n');
+			}
+			DocumentNode::DefList(pairs) => {
+				out.push_str("\n\n");
+				for (term, definition) in pairs {
+					let term = Self::many_to_text(term, footnotes);
+					if !term.is_empty() {
+						out.push_str(&format!("{term}\n"));
+					}
+					let definition = Self::many_to_text(definition, footnotes);
+					if !definition.is_empty() {
+						out.push_str(&format!("  {}\n", definition.replace('\n', "\n  ")));
+					}
+					out.push('\n');
+				}
+			}
+			DocumentNode::Sep => out.push_str("\n\n---\n\n"),
+			DocumentNode::TextLeaf(text) => out.push_str(text),
+			DocumentNode::Link { url, label, .. } => {
+				out.push_str(&Self::many_to_text(label, footnotes));
+				if classify_link(url) != LinkKind::Fragment {
+					out.push_str(&Self::footnote(url, footnotes));
+				}
+			}
+			DocumentNode::Image { label, .. } => out.push_str(label),
+			DocumentNode::Video { label, fetched, .. } => {
+				Self::media_text(label, fetched.url(), out, footnotes);
+			}
+			DocumentNode::Audio { label, fetched, .. } => {
+				Self::media_text(label, fetched.url(), out, footnotes);
+			}
+			DocumentNode::Figure { image, caption } => {
+				out.push_str("\n\n");
+				image.write_text(out, footnotes);
+				let caption = Self::many_to_text(caption, footnotes);
+				if !caption.is_empty() {
+					out.push_str(&format!("\n{caption}"));
+				}
+				out.push_str("\n\n");
+			}
+			DocumentNode::Math { source, text } if text.is_empty() => out.push_str(source),
+			DocumentNode::Math { text, .. } => out.push_str(text),
+			DocumentNode::Empty => {}
+		}
+	}
+
+	fn many_to_text(inner: &[DocumentNode], footnotes: &mut Vec<String>) -> String {
+		let mut out = String::new();
+		inner.iter().for_each(|el| el.write_text(&mut out, footnotes));
+		tidy_blocks(&out)
+	}
+
+	fn media_text(label: &str, url: Option<&str>, out: &mut String, footnotes: &mut Vec<String>) {
+		out.push_str(&format!("\n\n{label}"));
+		if let Some(url) = url {
+			out.push_str(&Self::footnote(url, footnotes));
+		}
+		out.push_str("\n\n");
+	}
+
+	/// The marker for `url`'s footnote, numbering it if it doesn't have one yet.
+	fn footnote(url: &str, footnotes: &mut Vec<String>) -> String {
+		let n = match footnotes.iter().position(|known| known == url) {
+			Some(index) => index + 1,
+			None => {
+				footnotes.push(url.to_string());
+				footnotes.len()
+			}
+		};
+		format!("[{n}]")
+	}
+
+	fn write_markdown(&self, out: &mut String) {
+		match self {
+			// Unknown elements are mostly blocks, as they're shown.
+			DocumentNode::Root(inner)
+			| DocumentNode::Div(inner)
+			| DocumentNode::Unk(inner)
+			| DocumentNode::Paragraph(inner) => {
+				out.push_str("\n\n");
+				inner.iter().for_each(|el| el.write_markdown(out));
+				out.push_str("\n\n");
+			}
+			DocumentNode::Heading { level, inner } => {
+				let text = Self::many_to_markdown(inner);
+				if !text.is_empty() {
+					let hashes = "#".repeat(usize::from(*level));
+					out.push_str(&format!("\n\n{hashes} {}\n\n", text.replace('\n', " ")));
+				}
+			}
+			// A backslash at the end of a line is a hard break.
+			DocumentNode::LineBreak => out.push_str("\\\n"),
+			DocumentNode::Span(inner) => {
+				inner.iter().for_each(|el| el.write_markdown(out));
+			}
+			DocumentNode::UList(inner) => {
+				out.push_str("\n\n");
+				for el in inner {
+					let item = el.to_markdown();
+					if !item.is_empty() {
+						out.push_str("- ");
+						out.push_str(&item.replace('\n', "\n  "));
+						out.push('\n');
+					}
+				}
+				out.push('\n');
+			}
+			// Markdown lists can only be numbered in decimal.
+			DocumentNode::OList { start, items, .. } => {
+				out.push_str("\n\n");
+				let mut n = *start;
+				for el in items {
+					let item = el.to_markdown();
+					if !item.is_empty() {
+						out.push_str(&format!("{n}. "));
+						n += 1;
+						out.push_str(&item.replace('\n', "\n   "));
+						out.push('\n');
+					}
+				}
+				out.push('\n');
+			}
+			DocumentNode::Emph(inner) => {
+				let text = Self::many_to_markdown(inner);
+				if !text.is_empty() {
+					out.push_str(&format!("*{text}*"));
+				}
+			}
+			DocumentNode::Strong(inner) => {
+				let text = Self::many_to_markdown(inner);
+				if !text.is_empty() {
+					out.push_str(&format!("**{text}**"));
+				}
+			}
+			DocumentNode::Strike(inner) => {
+				let text = Self::many_to_markdown(inner);
+				if !text.is_empty() {
+					out.push_str(&format!("~~{text}~~"));
+				}
+			}
+			// Markdown has no syntax for these, but allows the HTML.
+			DocumentNode::Underline(inner) => Self::wrap_html("u", inner, out),
+			DocumentNode::Superscript(inner) => Self::wrap_html("sup", inner, out),
+			DocumentNode::Subscript(inner) => Self::wrap_html("sub", inner, out),
+			DocumentNode::DefList(pairs) => {
+				out.push_str("\n\n");
+				for (term, definition) in pairs {
+					let term = Self::many_to_markdown(term);
+					if !term.is_empty() {
+						out.push_str(&format!("**{term}**\n"));
+					}
+					for el in definition {
+						let text = el.to_markdown();
+						if !text.is_empty() {
+							out.push_str(&format!(": {}\n", text.replace('\n', "\n  ")));
+						}
+					}
+					out.push('\n');
+				}
+			}
+			DocumentNode::Sep => out.push_str("\n\n---\n\n"),
+			DocumentNode::TextLeaf(text) => out.push_str(&escape_markdown(text)),
+			DocumentNode::Link { url, label, .. } => {
+				let label = Self::many_to_markdown(label);
+				out.push_str(&format!("[{label}]({})", markdown_destination(url)));
+			}
+			DocumentNode::Image { label, url } => {
+				let url = markdown_destination(url);
+				out.push_str(&format!("![{}]({url})", escape_markdown(label)));
+			}
+			DocumentNode::Video { label, fetched, .. } => {
+				let url = markdown_destination(fetched.url().unwrap_or("about:blank"));
+				out.push_str(&format!("\n\n[{}]({url})\n\n", escape_markdown(label)));
+			}
+			DocumentNode::Audio { label, fetched, .. } => {
+				let url = markdown_destination(fetched.url().unwrap_or("about:blank"));
+				out.push_str(&format!("\n\n[{}]({url})\n\n", escape_markdown(label)));
+			}
+			DocumentNode::Figure { image, caption } => {
+				out.push_str("\n\n");
+				image.write_markdown(out);
+				let caption = Self::many_to_markdown(caption);
+				if !caption.is_empty() {
+					out.push_str(&format!("\n\n*{caption}*"));
+				}
+				out.push_str("\n\n");
+			}
+			// The MathML comes straight from the feed, so only its text is trusted to be written.
+			DocumentNode::Math { source, text } if is_display_math(source) => {
+				let fence = code_fence(text, 3);
+				out.push_str(&format!("\n\n{fence}\n{text}\n{fence}\n\n"));
+			}
+			DocumentNode::Math { text, .. } => {
+				let fence = code_fence(text, 1);
+				let pad = if text.starts_with('`') || text.ends_with('`') { " " } else { "" };
+				out.push_str(&format!("{fence}{pad}{text}{pad}{fence}"));
+			}
+			DocumentNode::Empty => {}
+		}
+	}
+
+	fn many_to_markdown(inner: &[DocumentNode]) -> String {
+		let mut out = String::new();
+		inner.iter().for_each(|el| el.write_markdown(&mut out));
+		out.trim().to_string()
+	}
+
+	fn write_html(&self, out: &mut String) {
+		match self {
+			DocumentNode::Root(inner) | DocumentNode::Unk(inner) => {
+				inner.iter().for_each(|el| el.write_html(out));
+			}
+			DocumentNode::Div(inner) => Self::wrap_html("div", inner, out),
+			DocumentNode::Paragraph(inner) => Self::wrap_html("p", inner, out),
+			DocumentNode::Heading { level, inner } => {
+				Self::wrap_html(&format!("h{level}"), inner, out);
+			}
+			DocumentNode::LineBreak => out.push_str("<br>"),
+			DocumentNode::Span(inner) => Self::wrap_html("span", inner, out),
+			DocumentNode::Emph(inner) => Self::wrap_html("em", inner, out),
+			DocumentNode::Strong(inner) => Self::wrap_html("strong", inner, out),
+			DocumentNode::Strike(inner) => Self::wrap_html("del", inner, out),
+			DocumentNode::Underline(inner) => Self::wrap_html("u", inner, out),
+			DocumentNode::Superscript(inner) => Self::wrap_html("sup", inner, out),
+			DocumentNode::Subscript(inner) => Self::wrap_html("sub", inner, out),
+			DocumentNode::UList(inner) => Self::list_html("ul", "", inner, out),
+			DocumentNode::OList { start, style, items } => {
+				let mut attrs = String::new();
+				if *start != 1 {
+					attrs.push_str(&format!(" start=\"{start}\""));
+				}
+				if *style != ListStyle::Decimal {
+					attrs.push_str(&format!(" type=\"{}\"", style.type_attr()));
+				}
+				Self::list_html("ol", &attrs, items, out);
+			}
+			DocumentNode::DefList(pairs) => {
+				out.push_str("<dl>");
+				for (term, definition) in pairs {
+					Self::wrap_html("dt", term, out);
+					Self::wrap_html("dd", definition, out);
+				}
+				out.push_str("</dl>");
+			}
+			DocumentNode::Sep => out.push_str("<hr>"),
+			DocumentNode::TextLeaf(text) => out.push_str(&escape_html(text)),
+			DocumentNode::Link {
+				url,
+				label,
+				rel,
+				title,
+				..
+			} => {
+				out.push_str(&format!("<a href=\"{}\"", escape_html(url)));
+				if !rel.is_empty() {
+					out.push_str(&format!(" rel=\"{}\"", escape_html(&rel.join(" "))));
+				}
+				if let Some(title) = title {
+					out.push_str(&format!(" title=\"{}\"", escape_html(title)));
+				}
+				out.push('>');
+				label.iter().for_each(|el| el.write_html(out));
+				out.push_str("</a>");
+			}
+			DocumentNode::Image { label, url } => {
+				out.push_str(&format!(
+					"<img src=\"{}\" alt=\"{}\">",
+					escape_html(url),
+					escape_html(label)
+				));
+			}
+			DocumentNode::Video { label, fetched, .. } => {
+				out.push_str(&format!(
+					"<video controls src=\"{}\">{}</video>",
+					escape_html(fetched.url().unwrap_or("about:blank")),
+					escape_html(label)
+				));
+			}
+			DocumentNode::Audio { label, fetched, .. } => {
+				out.push_str(&format!(
+					"<audio controls src=\"{}\">{}</audio>",
+					escape_html(fetched.url().unwrap_or("about:blank")),
+					escape_html(label)
+				));
+			}
+			// The MathML comes straight from the feed, so only its text is trusted to be written.
+			DocumentNode::Math { source, text } if is_display_math(source) => {
+				out.push_str(&format!("<pre><code>{}</code></pre>", escape_html(text)));
+			}
+			DocumentNode::Math { text, .. } => {
+				out.push_str(&format!("<code>{}</code>", escape_html(text)));
+			}
+			DocumentNode::Figure { image, caption } => {
+				out.push_str("<figure>");
+				image.write_html(out);
+				if !caption.is_empty() {
+					Self::wrap_html("figcaption", caption, out);
+				}
+				out.push_str("</figure>");
+			}
+			DocumentNode::Empty => {}
+		}
+	}
+
+	fn wrap_html(tag: &str, inner: &[DocumentNode], out: &mut String) {
+		out.push_str(&format!("<{tag}>"));
+		inner.iter().for_each(|el| el.write_html(out));
+		out.push_str(&format!("</{tag}>"));
+	}
+
+	fn list_html(tag: &str, attrs: &str, inner: &[DocumentNode], out: &mut String) {
+		out.push_str(&format!("<{tag}{attrs}>"));
+		for el in inner {
+			if matches!(el, DocumentNode::TextLeaf(t) if t.trim().is_empty())
+				|| matches!(el, DocumentNode::Empty)
+			{
+				continue;
+			}
+			out.push_str("<li>");
+			el.write_html(out);
+			out.push_str("</li>");
+		}
+		out.push_str(&format!("</{tag}>"));
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::super::DocumentNode;
+
+	fn sample() -> DocumentNode {
+		DocumentNode::Root(vec![
+			DocumentNode::Div(vec![
+				DocumentNode::TextLeaf("Hello ".to_string()),
+				DocumentNode::Strong(vec![DocumentNode::TextLeaf("*world*".to_string())]),
+				DocumentNode::TextLeaf(", see ".to_string()),
+				DocumentNode::Link {
+					url: "https://example.com".to_string(),
+					mime: "text/html".to_string(),
+					label: vec![DocumentNode::TextLeaf("here".to_string())],
+					rel: vec![],
+					title: None,
+				},
+			]),
+			DocumentNode::UList(vec![
+				DocumentNode::Unk(vec![DocumentNode::TextLeaf("one".to_string())]),
+				DocumentNode::Unk(vec![DocumentNode::Emph(vec![DocumentNode::TextLeaf(
+					"two".to_string(),
+				)])]),
+			]),
+			DocumentNode::Sep,
+			DocumentNode::Image {
+				label: "A [cat]".to_string(),
+				url: "https://example.com/cat.png".to_string(),
+			},
+		])
+	}
+
+	#[test]
+	fn markdown_from_tree() {
+		assert_eq!(
+			sample().to_markdown(),
+			"Hello **\\*world\\***, see [here](https://example.com)\n\
+			\n\
+			- one\n\
+			- *two*\n\
+			\n\
+			---\n\
+			\n\
+			![A \\[cat\\]](https://example.com/cat.png)"
+		);
+	}
+
+	#[test]
+	fn footnoted_text_from_tree() {
+		let tree = DocumentNode::from_html(concat!(
+			r#"<p>Read <a href="https://example.com/a">the post</a> and "#,
+			r#"<a href="https://example.com/b">its follow-up</a>.</p>"#,
+			r#"<ul><li>Again: <a href="https://example.com/a">the post</a></li>"#,
+			r##"<li><a href="#top">Back to top</a></li></ul>"##,
+		));
+		assert_eq!(
+			tree.to_text_with_footnotes(),
+			"Read the post[1] and its follow-up[2].\n\
+			\n\
+			- Again: the post[1]\n\
+			- Back to top\n\
+			\n\
+			[1] https://example.com/a\n\
+			[2] https://example.com/b"
+		);
+		assert_eq!(
+			DocumentNode::from_html("<h2>Title</h2><p>One para.</p><p>Two<br>lines.</p>")
+				.to_text_with_footnotes(),
+			"Title\n\nOne para.\n\nTwo\nlines."
+		);
+		let sample = sample().to_text_with_footnotes();
+		assert_eq!(sample.lines().last(), Some("[1] https://example.com"));
+		assert_eq!(DocumentNode::from_html("<p>No links</p>").to_text_with_footnotes(), "No links");
+	}
+
+	#[test]
+	fn exports_paragraphs_and_headings() {
+		let tree = DocumentNode::from_html(
+			"<h2>Title</h2><p>One para.</p><p>Two <em>para</em>.<br>Next line.</p>",
+		);
+		assert_eq!(
+			tree.to_markdown(),
+			"## Title\n\nOne para.\n\nTwo *para*.\\\nNext line."
+		);
+		assert_eq!(
+			tree.to_html(),
+			"<h2>Title</h2><p>One para.</p><p>Two <em>para</em>.<br>Next line.</p>"
+		);
+	}
+
+	#[test]
+	fn escapes_link_destinations() {
+		let tree = DocumentNode::from_html(
+			"<p><a href=\"https://en.wikipedia.org/wiki/Rust_(language) x\">Rust</a></p>",
+		);
+		assert_eq!(
+			tree.to_markdown(),
+			"[Rust](https://en.wikipedia.org/wiki/Rust_%28language%29%20x)"
+		);
+	}
+
+	#[test]
+	fn exports_math_as_text() {
+		let tree = DocumentNode::from_html(
+			"<p><math><mi>x</mi><img src=x onerror=alert(1)></math> and \
+			<math display=\"block\"><mi>a</mi><mo>&lt;</mo><mi>b</mi></math></p>",
+		);
+		assert_eq!(tree.to_html(), "<p><code>x</code> and <pre><code>a&lt;b</code></pre></p>");
+		assert_eq!(tree.to_markdown(), "`x` and\n\n```\na<b\n```");
+		assert!(!tree.to_html().contains("onerror"));
+	}
+
+	#[test]
+	fn html_from_tree() {
+		assert_eq!(
+			sample().to_html(),
+			"<div>Hello <strong>*world*</strong>, see <a href=\"https://example.com\">here</a></div>\
+			<ul><li>one</li><li><em>two</em></li></ul>\
+			<hr>\
+			<img src=\"https://example.com/cat.png\" alt=\"A [cat]\">"
+		);
+	}
+}
@@ -0,0 +1,152 @@
+use std::ops::Range;
+
+#[cfg(feature = "gui")]
+use eframe::egui::{text::LayoutJob, Align, Context, FontSelection, Id, Response, RichText, Ui};
+
+/// How many bytes at the start of `text` match `query`, ignoring case, if they do.
+fn match_len(text: &str, query: &str) -> Option<usize> {
+	let mut chars = text.chars();
+	let mut len = 0;
+	for wanted in query.chars() {
+		let found = chars.next()?;
+		if !found.to_lowercase().eq(wanted.to_lowercase()) {
+			return None;
+		}
+		len += found.len_utf8();
+	}
+	Some(len)
+}
+
+/// Every match of `query` in `text`, as byte ranges in order, ignoring case. Matches can
+/// overlap, so `"aa"` is found twice in `"aaa"`. An empty query matches nothing.
+#[must_use]
+pub fn find_matches(text: &str, query: &str) -> Vec<Range<usize>> {
+	if query.is_empty() {
+		return vec![];
+	}
+	text.char_indices()
+		.filter_map(|(start, _)| match_len(&text[start..], query).map(|len| start..start + len))
+		.collect()
+}
+
+/// What's being searched for in the documents shown this frame, and what's been found so far.
+#[cfg(feature = "gui")]
+#[derive(Clone, Default)]
+struct FindState {
+	query: String,
+	/// Which match, counting from 0 in the order they're shown, to pick out.
+	current: usize,
+	/// Whether to scroll the current match into view.
+	scroll: bool,
+	/// How many matches have been shown so far.
+	seen: usize,
+}
+
+#[cfg(feature = "gui")]
+fn find_id() -> Id {
+	Id::new("find in document")
+}
+
+/// Highlights matches of `query` in the documents shown in `ctx` until [`end_find`], picking
+/// out the `current`th one, and scrolling to it if `scroll` is set. Link labels, being buttons,
+/// aren't searched.
+#[cfg(feature = "gui")]
+pub fn begin_find(ctx: &Context, query: &str, current: usize, scroll: bool) {
+	let state = FindState {
+		query: query.to_string(),
+		current,
+		scroll,
+		seen: 0,
+	};
+	ctx.data_mut(|data| data.insert_temp(find_id(), state));
+}
+
+/// Stops highlighting matches, returning how many were shown since [`begin_find`].
+#[cfg(feature = "gui")]
+pub fn end_find(ctx: &Context) -> usize {
+	ctx.data_mut(|data| {
+		let seen = data.get_temp::<FindState>(find_id()).map_or(0, |state| state.seen);
+		data.remove::<FindState>(find_id());
+		seen
+	})
+}
+
+/// Shows `text` as a label, given the look it should have by `style`, with any matches of the
+/// query from [`begin_find`] highlighted.
+#[cfg(feature = "gui")]
+pub(super) fn highlighted_label(
+	ui: &mut Ui,
+	text: &str,
+	style: impl Fn(RichText) -> RichText,
+) -> Response {
+	let mut state = ui.data(|data| data.get_temp::<FindState>(find_id()).unwrap_or_default());
+	let matches = find_matches(text, &state.query);
+	if matches.is_empty() {
+		return ui.label(style(RichText::new(text)));
+	}
+	let first = state.seen;
+	state.seen += matches.len();
+	let current = (first..state.seen)
+		.contains(&state.current)
+		.then(|| matches[state.current - first].clone());
+
+	// Cut the text wherever a match starts or ends, so each piece is either all in one or not.
+	let mut cuts: Vec<usize> = matches.iter().flat_map(|m| [m.start, m.end]).collect();
+	cuts.extend([0, text.len()]);
+	cuts.sort_unstable();
+	cuts.dedup();
+	let visuals = ui.visuals();
+	let (match_fill, current_fill) = (
+		visuals.selection.bg_fill.gamma_multiply(0.4),
+		visuals.selection.bg_fill,
+	);
+	let mut job = LayoutJob::default();
+	for piece in cuts.windows(2) {
+		let (start, end) = (piece[0], piece[1]);
+		let within = |m: &Range<usize>| m.start <= start && end <= m.end;
+		let mut piece = style(RichText::new(&text[start..end]));
+		if current.as_ref().is_some_and(within) {
+			piece = piece.background_color(current_fill);
+		} else if matches.iter().any(within) {
+			piece = piece.background_color(match_fill);
+		}
+		piece.append_to(
+			&mut job,
+			ui.style(),
+			FontSelection::Default,
+			ui.layout().vertical_align(),
+		);
+	}
+	let response = ui.label(job);
+	if current.is_some() && state.scroll {
+		response.scroll_to_me(Some(Align::Center));
+		state.scroll = false;
+	}
+	ui.data_mut(|data| data.insert_temp(find_id(), state));
+	response
+}
+
+#[cfg(test)]
+mod tests {
+	use super::find_matches;
+
+	#[test]
+	fn finds_every_match() {
+		assert_eq!(find_matches("one two one", "one"), vec![0..3, 8..11]);
+		assert_eq!(find_matches("one two one", "three"), vec![]);
+		assert_eq!(find_matches("anything", ""), vec![]);
+	}
+
+	#[test]
+	fn ignores_case() {
+		assert_eq!(find_matches("Rust and RUST", "rust"), vec![0..4, 9..13]);
+		// Byte ranges stay in the original text, even past wider characters.
+		assert_eq!(find_matches("Ärger über ärger", "ÄRGER"), vec![0..6, 13..19]);
+	}
+
+	#[test]
+	fn finds_overlapping_matches() {
+		assert_eq!(find_matches("aaaa", "aa"), vec![0..2, 1..3, 2..4]);
+		assert_eq!(find_matches("abababa", "aba"), vec![0..3, 2..5, 4..7]);
+	}
+}
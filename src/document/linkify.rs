@@ -0,0 +1,190 @@
+use super::DocumentNode;
+
+/// Punctuation which usually ends the sentence a URL is in, rather than the URL.
+const TRAILING_PUNCTUATION: &[char] = &['.', ',', ';', ':', '!', '?', '\'', '"'];
+
+/// Finds the first `http://` or `https://` URL in `text`, as a byte range.
+fn find_url(text: &str) -> Option<(usize, usize)> {
+	let mut from = 0;
+	loop {
+		let start = from + text[from..].find("http")?;
+		let rest = &text[start..];
+		let scheme = ["https://", "http://"]
+			.into_iter()
+			.find(|scheme| rest.starts_with(scheme));
+		// Anything glued to a word before it, like `xhttp://`, isn't a URL on its own.
+		let glued = text[..start]
+			.chars()
+			.next_back()
+			.is_some_and(char::is_alphanumeric);
+		let Some(scheme) = scheme.filter(|_| !glued) else {
+			from = start + "http".len();
+			continue;
+		};
+		let len = rest
+			.find(|c: char| c.is_whitespace() || matches!(c, '<' | '>' | '"'))
+			.unwrap_or(rest.len());
+		let url = trim_url(&rest[..len]);
+		if url.len() > scheme.len() {
+			return Some((start, start + url.len()));
+		}
+		from = start + len.max(scheme.len());
+	}
+}
+
+/// Drops punctuation from the end of `url` which is more likely to belong to the text around
+/// it. Closing brackets are only kept if the URL opens them too, like Wikipedia's often do.
+fn trim_url(mut url: &str) -> &str {
+	loop {
+		let Some(last) = url.chars().next_back() else {
+			return url;
+		};
+		let unbalanced = |open, close| {
+			last == close && url.matches(open).count() < url.matches(close).count()
+		};
+		if TRAILING_PUNCTUATION.contains(&last) || unbalanced('(', ')') || unbalanced('[', ']') {
+			url = &url[..url.len() - last.len_utf8()];
+		} else {
+			return url;
+		}
+	}
+}
+
+/// Splits `text` into text and [`DocumentNode::Link`]s for any bare URLs in it.
+#[must_use]
+pub fn linkify(text: &str) -> Vec<DocumentNode> {
+	let mut out = vec![];
+	let mut rest = text;
+	while let Some((start, end)) = find_url(rest) {
+		if start > 0 {
+			out.push(DocumentNode::TextLeaf(rest[..start].to_string()));
+		}
+		let url = rest[start..end].to_string();
+		out.push(DocumentNode::Link {
+			label: vec![DocumentNode::TextLeaf(url.clone())],
+			url,
+			mime: "text/plain".to_string(),
+			rel: vec![],
+			title: None,
+		});
+		rest = &rest[end..];
+	}
+	if !rest.is_empty() {
+		out.push(DocumentNode::TextLeaf(rest.to_string()));
+	}
+	out
+}
+
+impl DocumentNode {
+	/// Turns bare URLs in this tree's text into links, with [`linkify`]. Text which is already
+	/// inside a link is left alone.
+	pub fn linkify_text(&mut self) {
+		match self {
+			DocumentNode::Root(inner)
+			| DocumentNode::Div(inner)
+			| DocumentNode::Span(inner)
+			| DocumentNode::Unk(inner)
+			| DocumentNode::Paragraph(inner)
+			| DocumentNode::Heading { inner, .. }
+			| DocumentNode::UList(inner)
+			| DocumentNode::OList { items: inner, .. }
+			| DocumentNode::Emph(inner)
+			| DocumentNode::Strong(inner)
+			| DocumentNode::Strike(inner)
+			| DocumentNode::Underline(inner)
+			| DocumentNode::Superscript(inner)
+			| DocumentNode::Subscript(inner) => linkify_all(inner),
+			DocumentNode::DefList(pairs) => {
+				for (term, definition) in pairs {
+					linkify_all(term);
+					linkify_all(definition);
+				}
+			}
+			DocumentNode::Figure { caption, .. } => linkify_all(caption),
+			_ => {}
+		}
+	}
+}
+
+fn linkify_all(nodes: &mut Vec<DocumentNode>) {
+	*nodes = std::mem::take(nodes)
+		.into_iter()
+		.flat_map(|mut node| match node {
+			DocumentNode::TextLeaf(text) => linkify(&text),
+			_ => {
+				node.linkify_text();
+				vec![node]
+			}
+		})
+		.collect();
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{linkify, DocumentNode};
+
+	fn show(nodes: &[DocumentNode]) -> Vec<String> {
+		nodes
+			.iter()
+			.map(|node| match node {
+				DocumentNode::Link { url, .. } => format!("<{url}>"),
+				other => other.to_string(),
+			})
+			.collect()
+	}
+
+	#[test]
+	fn links_urls_mid_sentence() {
+		assert_eq!(
+			show(&linkify("See https://example.com/a?b=c for more")),
+			["See ", "<https://example.com/a?b=c>", " for more"]
+		);
+		assert_eq!(show(&linkify("No links here")), ["No links here"]);
+		assert_eq!(show(&linkify("Not https:// or xhttp://a.b")), ["Not https:// or xhttp://a.b"]);
+	}
+
+	#[test]
+	fn leaves_trailing_punctuation_out() {
+		assert_eq!(
+			show(&linkify("It's at http://example.com.")),
+			["It's at ", "<http://example.com>", "."]
+		);
+		assert_eq!(
+			show(&linkify("(see https://example.com/page)")),
+			["(see ", "<https://example.com/page>", ")"]
+		);
+		assert_eq!(
+			show(&linkify("https://en.wikipedia.org/wiki/Rust_(programming_language)!")),
+			["<https://en.wikipedia.org/wiki/Rust_(programming_language)>", "!"]
+		);
+	}
+
+	#[test]
+	fn links_several_urls_per_line() {
+		assert_eq!(
+			show(&linkify("https://one.example, https://two.example and https://three.example")),
+			[
+				"<https://one.example>",
+				", ",
+				"<https://two.example>",
+				" and ",
+				"<https://three.example>"
+			]
+		);
+	}
+
+	#[test]
+	fn skips_text_already_in_links() {
+		let mut tree = DocumentNode::from_html(
+			r#"<p>Go to https://a.example or <a href="https://b.example">https://b.example</a></p>"#,
+		);
+		tree.linkify_text();
+		let mut urls = vec![];
+		tree.visit(&mut |node| {
+			if let DocumentNode::Link { url, .. } = node {
+				urls.push(url.clone());
+			}
+		});
+		assert_eq!(urls, ["https://a.example", "https://b.example"]);
+	}
+}
@@ -2,7 +2,7 @@
 use eframe::egui::Ui;
 use std::{
 	fmt::Display,
-	path::PathBuf,
+	path::{Path, PathBuf},
 	sync::{
 		atomic::{AtomicU64, Ordering},
 		RwLock,
@@ -10,26 +10,149 @@ use std::{
 };
 use thiserror::Error;
 
-use crate::FETCHER;
+use crate::{
+	fetch::{is_not_cached, read_body, BodyError},
+	FETCHER,
+};
 
 lazy_static::lazy_static! {
 	pub static ref TMP: RwLock<Option<tempdir::TempDir>> = RwLock::new(Some(tempdir::TempDir::new("media_cache").expect("Couldn't make temporary dir")));
 	static ref COUNTER: AtomicU64 = AtomicU64::new(0);
 }
 
+/// Whether a piece of media is audio, video, or an image.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MediaKind {
+	Audio,
+	Video,
+	Image,
+}
+
+/// How a piece of media should be presented.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MediaPresentation {
+	/// Download the whole file up front, then open the downloaded copy in an external app.
+	Download,
+	/// Hand the URL straight to an external app, which can stream it.
+	Stream,
+}
+
+/// Decides how the audio and video in a [`DocumentNode`](super::DocumentNode) are presented.
+pub trait MediaHandler {
+	fn presentation(&self, kind: MediaKind, url: &str, mime: &str) -> MediaPresentation;
+
+	/// Whether media of this kind is loaded as soon as it's shown, rather than waiting for the
+	/// user to ask for it.
+	fn autoload(&self, _kind: MediaKind) -> bool {
+		true
+	}
+}
+
+/// The default [`MediaHandler`], which downloads everything and opens it externally.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct DownloadAndOpen;
+
+impl MediaHandler for DownloadAndOpen {
+	fn presentation(&self, _kind: MediaKind, _url: &str, _mime: &str) -> MediaPresentation {
+		MediaPresentation::Download
+	}
+}
+
+/// Wraps another [`MediaHandler`], only loading the kinds of media it allows up front. Useful
+/// on metered or slow connections.
+#[derive(Clone, Copy, Debug)]
+pub struct Autoload<H> {
+	pub handler: H,
+	/// Whether audio and video are loaded automatically.
+	pub media: bool,
+	pub images: bool,
+}
+
+impl<H: MediaHandler> MediaHandler for Autoload<H> {
+	fn presentation(&self, kind: MediaKind, url: &str, mime: &str) -> MediaPresentation {
+		self.handler.presentation(kind, url, mime)
+	}
+
+	fn autoload(&self, kind: MediaKind) -> bool {
+		match kind {
+			MediaKind::Audio | MediaKind::Video => self.media,
+			MediaKind::Image => self.images,
+		}
+	}
+}
+
 pub enum MaybeLoaded<Inner: TryFrom<Vec<u8>>> {
 	NotStarted(String),
 	Working(String),
 	Done(String, Result<Inner, Inner::Error>),
 	Failed(String, reqwest_middleware::Error),
 	BadStatus(u16),
+	/// The download was given up on for being more than this many bytes.
+	TooLarge(String, u64),
+	/// The download was made offline, and wasn't in the cache.
+	NotCached(String),
+}
+
+/// Only the URL is kept, so a serialized download always comes back as
+/// [`MaybeLoaded::NotStarted`], and one which got a bad status comes back without a URL at all.
+impl<I: TryFrom<Vec<u8>>> serde::Serialize for MaybeLoaded<I> {
+	fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+		self.url().unwrap_or_default().serialize(serializer)
+	}
+}
+
+impl<'de, I: TryFrom<Vec<u8>>> serde::Deserialize<'de> for MaybeLoaded<I> {
+	fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+		String::deserialize(deserializer).map(MaybeLoaded::NotStarted)
+	}
 }
 
 impl<I: TryFrom<Vec<u8>>> MaybeLoaded<I> {
+	/// The URL this value is being loaded from, if it's still known.
+	#[must_use]
+	pub fn url(&self) -> Option<&str> {
+		match self {
+			MaybeLoaded::NotStarted(url)
+			| MaybeLoaded::Working(url)
+			| MaybeLoaded::Done(url, _)
+			| MaybeLoaded::Failed(url, _)
+			| MaybeLoaded::TooLarge(url, _)
+			| MaybeLoaded::NotCached(url) => Some(url),
+			MaybeLoaded::BadStatus(_) => None,
+		}
+	}
+
 	pub async fn tick(&mut self) {
+		self.tick_inner(false, None).await;
+	}
+
+	/// Like [`MaybeLoaded::tick`], but downloads with the longer media timeout.
+	pub async fn tick_media(&mut self) {
+		self.tick_inner(true, FETCHER.max_media_size()).await;
+	}
+
+	/// Like [`MaybeLoaded::tick`], but gives up with [`MaybeLoaded::TooLarge`] once the body is
+	/// over `limit` bytes.
+	pub async fn tick_limited(&mut self, limit: u64) {
+		self.tick_inner(false, Some(limit)).await;
+	}
+
+	/// Stops waiting on the download, if it's still running. Ticking again will restart it.
+	pub async fn cancel(&mut self) {
+		if let MaybeLoaded::Working(url) = self {
+			FETCHER.cancel(url).await;
+			*self = MaybeLoaded::NotStarted(url.clone());
+		}
+	}
+
+	async fn tick_inner(&mut self, media: bool, limit: Option<u64>) {
 		if let MaybeLoaded::NotStarted(url) = &self {
 			let url = url.to_string();
-			FETCHER.start_download(&url).await;
+			if media {
+				FETCHER.start_media_download(&url).await;
+			} else {
+				FETCHER.start_download(&url).await;
+			}
 			*self = MaybeLoaded::Working(url);
 			return;
 		}
@@ -40,6 +163,10 @@ impl<I: TryFrom<Vec<u8>>> MaybeLoaded<I> {
 		let Some(completion) = FETCHER.try_finish(&url).await else {return;};
 		let response = match completion {
 			Ok(r) => r,
+			Err(e) if is_not_cached(&e) => {
+				*self = MaybeLoaded::NotCached(url);
+				return;
+			}
 			Err(e) => {
 				*self = MaybeLoaded::Failed(url.clone(), e);
 				return;
@@ -50,51 +177,68 @@ impl<I: TryFrom<Vec<u8>>> MaybeLoaded<I> {
 			*self = MaybeLoaded::BadStatus(status.as_u16());
 			return;
 		}
-		let body: Vec<u8> = response
-			.bytes()
-			.await
-			.expect("Response body is empty?")
-			.into_iter()
-			.collect();
-		*self = MaybeLoaded::Done(url, TryInto::try_into(body));
+		*self = match read_body(response, limit).await {
+			Ok(body) => MaybeLoaded::Done(url, TryInto::try_into(body)),
+			Err(BodyError::TooLarge(limit)) => MaybeLoaded::TooLarge(url, limit),
+			Err(BodyError::Read(e)) => MaybeLoaded::Failed(url, e.into()),
+		};
 	}
 }
 
+/// Writes downloaded media to its own file in [`TMP`], so it can be opened by another app.
+fn cache_to_disk(value: Vec<u8>) -> std::io::Result<PathBuf> {
+	let path = TMP
+		.read()
+		.unwrap()
+		.as_ref()
+		.unwrap()
+		.path()
+		.to_path_buf()
+		.join(COUNTER.fetch_add(1, Ordering::Relaxed).to_string());
+	std::fs::write(&path, value)?;
+	log::debug!("Cached media at {}", path.display());
+	Ok(path)
+}
+
 pub struct Video {
 	cache_path: PathBuf,
 }
 
 #[derive(Error, Debug)]
 pub enum VideoError {
-	NoGUI,
 	IoError(#[from] std::io::Error),
 }
 impl Display for VideoError {
 	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-		write!(f, "{self:?}")
+		match self {
+			VideoError::IoError(e) => write!(f, "couldn't save it for playing: {e}"),
+		}
 	}
 }
 
 impl TryFrom<Vec<u8>> for Video {
 	type Error = VideoError;
 
-	#[cfg(feature = "gui")]
 	fn try_from(value: Vec<u8>) -> Result<Self, Self::Error> {
-		let path = TMP
-			.read()
-			.unwrap()
-			.as_ref()
-			.unwrap()
-			.path()
-			.to_path_buf()
-			.join(COUNTER.fetch_add(1, Ordering::Relaxed).to_string());
-		std::fs::write(&path, value)?;
-		dbg!(&path);
-		Ok(Video { cache_path: path })
+		Ok(Video {
+			cache_path: cache_to_disk(value)?,
+		})
 	}
-	#[cfg(not(feature = "gui"))]
-	fn try_from(value: Vec<u8>) -> Result<Self, Self::Error> {
-		Err(VideoError::NoGUI)
+}
+
+impl Video {
+	/// Where the downloaded video is kept, until the app exits.
+	#[must_use]
+	pub fn path(&self) -> &Path {
+		&self.cache_path
+	}
+
+	/// Reads the downloaded video back, so it can be saved somewhere that lasts.
+	///
+	/// # Errors
+	/// Fails if the cached copy can't be read.
+	pub fn bytes(&self) -> std::io::Result<Vec<u8>> {
+		std::fs::read(&self.cache_path)
 	}
 }
 
@@ -113,35 +257,39 @@ pub struct Audio {
 
 #[derive(Error, Debug)]
 pub enum AudioError {
-	NoGUI,
 	IoError(#[from] std::io::Error),
 }
 impl Display for AudioError {
 	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-		write!(f, "{self:?}")
+		match self {
+			AudioError::IoError(e) => write!(f, "couldn't save it for playing: {e}"),
+		}
 	}
 }
 
 impl TryFrom<Vec<u8>> for Audio {
 	type Error = AudioError;
 
-	#[cfg(feature = "gui")]
 	fn try_from(value: Vec<u8>) -> Result<Self, Self::Error> {
-		let path = TMP
-			.read()
-			.unwrap()
-			.as_ref()
-			.unwrap()
-			.path()
-			.to_path_buf()
-			.join(COUNTER.fetch_add(1, Ordering::Relaxed).to_string());
-		std::fs::write(&path, value)?;
-		dbg!(&path);
-		Ok(Audio { cache_path: path })
+		Ok(Audio {
+			cache_path: cache_to_disk(value)?,
+		})
 	}
-	#[cfg(not(feature = "gui"))]
-	fn try_from(value: Vec<u8>) -> Result<Self, Self::Error> {
-		Err(AudioError::NoGUI)
+}
+
+impl Audio {
+	/// Where the downloaded audio is kept, until the app exits.
+	#[must_use]
+	pub fn path(&self) -> &Path {
+		&self.cache_path
+	}
+
+	/// Reads the downloaded audio back, so it can be saved somewhere that lasts.
+	///
+	/// # Errors
+	/// Fails if the cached copy can't be read.
+	pub fn bytes(&self) -> std::io::Result<Vec<u8>> {
+		std::fs::read(&self.cache_path)
 	}
 }
 
@@ -296,3 +444,34 @@ impl Audio {
 // 		});
 // 	}
 // }
+
+#[cfg(test)]
+mod test {
+	#[cfg(not(feature = "gui"))]
+	#[test]
+	fn keeps_media_without_a_gui() {
+		use super::Audio;
+
+		let bytes = b"ID3\x04\x00 not really an mp3".to_vec();
+		let audio = Audio::try_from(bytes.clone()).unwrap();
+		assert!(audio.path().exists());
+		assert_eq!(audio.bytes().unwrap(), bytes);
+	}
+
+	#[tokio::test]
+	async fn gives_up_past_the_limit() {
+		use super::MaybeLoaded;
+		use crate::fetch::mock::{MockResponse, MockServer};
+
+		let server = MockServer::start(|_| MockResponse::ok(vec![0; 2048])).await;
+		let mut icon = MaybeLoaded::<Vec<u8>>::NotStarted(server.url("/icon.png"));
+		for _ in 0..500 {
+			icon.tick_limited(1024).await;
+			if !matches!(icon, MaybeLoaded::NotStarted(_) | MaybeLoaded::Working(_)) {
+				break;
+			}
+			tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+		}
+		assert!(matches!(icon, MaybeLoaded::TooLarge(_, 1024)));
+	}
+}
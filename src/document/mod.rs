@@ -1,29 +1,74 @@
+use std::fmt::Display;
 
-use eframe::Frame;
 #[cfg(feature = "gui")]
-use eframe::egui::{Image, RichText};
+use eframe::{
+	egui::{load::TexturePoll, Context, Id, Image, RichText},
+	Frame,
+};
 use html_parser::{Dom, DomVariant, Element, Node};
 use tokio::runtime::Runtime;
 
-use self::media::{Audio, MaybeLoaded, Video};
+use self::{
+	entities::decode_entities,
+	media::{Audio, MaybeLoaded, MediaHandler, MediaKind, MediaPresentation, Video},
+};
 
+pub mod entities;
+pub mod export;
+pub mod find;
+pub mod linkify;
 pub mod media;
+pub mod readable;
 
+/// A parsed article body.
+///
+/// This can be serialized to cache it, rather than parsing the HTML again. Everything survives
+/// that except the state of [`DocumentNode::Video`] and [`DocumentNode::Audio`], which only
+/// keep their URL: they come back as not downloaded and not requested, as if freshly parsed.
+#[derive(serde::Serialize, serde::Deserialize)]
 pub enum DocumentNode {
 	Root(Vec<DocumentNode>),
 	Div(Vec<DocumentNode>),
 	Span(Vec<DocumentNode>),
 	Unk(Vec<DocumentNode>),
+	/// `<p>`.
+	Paragraph(Vec<DocumentNode>),
+	/// `<h1>` to `<h6>`, at `level` 1 to 6.
+	Heading {
+		level: u8,
+		inner: Vec<DocumentNode>,
+	},
+	/// `<br>`.
+	LineBreak,
 	UList(Vec<DocumentNode>),
-	OList(Vec<DocumentNode>),
+	/// An ordered list, numbered from `start` in `style`.
+	OList {
+		start: usize,
+		style: ListStyle,
+		items: Vec<DocumentNode>,
+	},
 	Emph(Vec<DocumentNode>),
 	Strong(Vec<DocumentNode>),
+	/// `<del>` or `<s>`.
+	Strike(Vec<DocumentNode>),
+	/// `<u>` or `<ins>`.
+	Underline(Vec<DocumentNode>),
+	Superscript(Vec<DocumentNode>),
+	Subscript(Vec<DocumentNode>),
+	/// A definition list, as `(term, definition)` pairs.
+	DefList(Vec<(Vec<DocumentNode>, Vec<DocumentNode>)>),
 	Sep,
 	TextLeaf(String),
 	Link {
 		url: String,
 		mime: String,
 		label: Vec<DocumentNode>,
+		/// The link types in its `rel`, lowercased, like `nofollow` or `sponsored`.
+		#[serde(default)]
+		rel: Vec<String>,
+		/// Its `title`, shown when it's hovered.
+		#[serde(default)]
+		title: Option<String>,
 	},
 	Image {
 		label: String,
@@ -31,15 +76,148 @@ pub enum DocumentNode {
 	},
 	Video {
 		label: String,
+		mime: String,
 		fetched: MaybeLoaded<Video>,
+		/// Whether the user asked for this to be loaded, in case it isn't automatically.
+		#[serde(skip)]
+		requested: bool,
 	},
 	Audio {
 		label: String,
+		mime: String,
 		fetched: MaybeLoaded<Audio>,
+		#[serde(skip)]
+		requested: bool,
+	},
+	/// An image (or several, or some other media) with a caption beneath it.
+	Figure {
+		image: Box<DocumentNode>,
+		caption: Vec<DocumentNode>,
+	},
+	/// A MathML `<math>` element, kept as it was written since it can't be rendered yet.
+	Math {
+		source: String,
+		/// The equation as plain text, shown instead: its TeX, if it came with any, or else
+		/// a rough linear version of it, like `e^(iπ)+1=0`.
+		#[serde(default)]
+		text: String,
 	},
 	Empty,
 }
 
+/// The deepest element nesting kept when parsing HTML. Anything nested deeper is flattened
+/// into its parent, since both the HTML parser and rendering recurse once per level and
+/// pathological feeds could otherwise overflow the stack.
+pub const MAX_DEPTH: usize = 128;
+
+const VOID_ELEMENTS: &[&str] = &[
+	"area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "source",
+	"track", "wbr",
+];
+
+/// Cuts `html` down to at most `max_len` bytes, without ending partway through a tag or a
+/// character, and closes any elements that leaves open.
+pub(crate) fn truncate_html(html: &str, max_len: usize) -> String {
+	if html.len() <= max_len {
+		return html.to_string();
+	}
+	let mut end = max_len;
+	while !html.is_char_boundary(end) {
+		end -= 1;
+	}
+	let mut cut = &html[..end];
+	if let Some(open) = cut.rfind('<').filter(|open| !cut[*open..].contains('>')) {
+		cut = &cut[..open];
+	}
+	let mut open = vec![];
+	for tag in cut.split('<').skip(1).filter_map(|rest| rest.split_once('>')).map(|t| t.0) {
+		let name: String = tag
+			.trim_start_matches('/')
+			.chars()
+			.take_while(char::is_ascii_alphanumeric)
+			.collect::<String>()
+			.to_ascii_lowercase();
+		if name.is_empty() || tag.ends_with('/') || VOID_ELEMENTS.contains(&name.as_str()) {
+			continue;
+		}
+		if tag.starts_with('/') {
+			if let Some(at) = open.iter().rposition(|opened| *opened == name) {
+				open.truncate(at);
+			}
+		} else {
+			open.push(name);
+		}
+	}
+	let mut out = cut.to_string();
+	for name in open.iter().rev() {
+		out.push_str(&format!("</{name}>"));
+	}
+	out
+}
+
+/// Drops the tags of any elements nested deeper than [`MAX_DEPTH`], keeping their text.
+pub(crate) fn limit_nesting(html: &str) -> String {
+	let mut out = String::with_capacity(html.len());
+	let mut depth = 0usize;
+	let mut rest = html;
+	while let Some(start) = rest.find('<') {
+		out.push_str(&rest[..start]);
+		rest = &rest[start..];
+		// Find the end of the tag, skipping over any quoted attribute values.
+		let mut quote = None;
+		let end = rest.char_indices().skip(1).find_map(|(i, c)| match (quote, c) {
+			(None, '"' | '\'') => {
+				quote = Some(c);
+				None
+			}
+			(Some(q), c) if q == c => {
+				quote = None;
+				None
+			}
+			(None, '>') => Some(i + 1),
+			_ => None,
+		});
+		let Some(end) = end else {
+			break;
+		};
+		let tag = &rest[..end];
+		rest = &rest[end..];
+		let name: String = tag
+			.trim_start_matches(['<', '/'])
+			.chars()
+			.take_while(char::is_ascii_alphanumeric)
+			.collect::<String>()
+			.to_ascii_lowercase();
+		let keep = if tag.starts_with("<!") || tag.starts_with("<?") || name.is_empty() {
+			true
+		} else if tag.starts_with("</") {
+			let keep = depth <= MAX_DEPTH;
+			depth = depth.saturating_sub(1);
+			keep
+		} else if tag.ends_with("/>") || VOID_ELEMENTS.contains(&name.as_str()) {
+			true
+		} else {
+			depth += 1;
+			depth <= MAX_DEPTH
+		};
+		if keep {
+			out.push_str(tag);
+		}
+	}
+	out.push_str(rest);
+	out
+}
+
+impl DocumentNode {
+	/// Parses an HTML fragment, flattening anything nested deeper than [`MAX_DEPTH`].
+	#[must_use]
+	pub fn from_html(html: &str) -> Self {
+		Dom::parse(&limit_nesting(html))
+			.unwrap_or(Dom::parse("<i>invalid dom</i>").expect("default dom invalid?!"))
+			.into()
+	}
+}
+
 macro_rules! from_iter {
 	($iter:expr) => {
 		$iter.into_iter().map(DocumentNode::from).collect()
@@ -59,7 +237,7 @@ impl From<Dom> for DocumentNode {
 impl From<Node> for DocumentNode {
 	fn from(value: Node) -> Self {
 		match value {
-			Node::Text(s) => Self::TextLeaf(s),
+			Node::Text(s) => Self::TextLeaf(decode_entities(&s)),
 			Node::Element(e) => Self::from(e),
 			Node::Comment(_) => Self::Empty,
 		}
@@ -71,8 +249,34 @@ impl From<Element> for DocumentNode {
 		match value.name.as_str() {
 			"div" => Self::Div(from_iter!(value.children)),
 			"span" => Self::Span(from_iter!(value.children)),
+			// Not styled, but these still flow with the text around them.
+			"code" | "kbd" | "samp" | "var" | "tt" | "abbr" | "cite" | "dfn" | "q" | "small"
+			| "big" | "mark" | "time" | "font" | "label" | "bdi" | "bdo" | "data" => {
+				Self::Span(from_iter!(value.children))
+			}
 			"ul" => Self::UList(from_iter!(value.children)),
-			"ol" => Self::OList(from_iter!(value.children)),
+			"ol" => Self::OList {
+				start: value
+					.attributes
+					.get("start")
+					.cloned()
+					.flatten()
+					.and_then(|start| start.trim().parse().ok())
+					.unwrap_or(1),
+				style: value
+					.attributes
+					.get("type")
+					.cloned()
+					.flatten()
+					.map_or(ListStyle::Decimal, |style| ListStyle::from_type(&style)),
+				items: from_iter!(value.children),
+			},
+			"p" => Self::Paragraph(from_iter!(value.children)),
+			"h1" | "h2" | "h3" | "h4" | "h5" | "h6" => Self::Heading {
+				level: value.name[1..].parse().unwrap_or(1),
+				inner: from_iter!(value.children),
+			},
+			"br" => Self::LineBreak,
 			"hr" => Self::Sep,
 			"a" => match value.attributes.get("type").cloned().flatten() {
 				Some(mime) if mime.starts_with("image/") => Self::Image {
@@ -86,21 +290,25 @@ impl From<Element> for DocumentNode {
 				},
 				Some(mime) if mime.starts_with("audio/") => Self::Audio {
 					label: "Linked audio file".to_string(),
+					mime,
 					fetched: MaybeLoaded::NotStarted(value
 						.attributes
 						.get("href")
 						.cloned()
 						.flatten()
 						.unwrap_or_else(|| "about:blank".to_string())),
+					requested: false,
 				},
 				Some(mime) if mime.starts_with("video/") => Self::Video {
 					label: "Linked video file".to_string(),
+					mime,
 					fetched: MaybeLoaded::NotStarted(value
 						.attributes
 						.get("href")
 						.cloned()
 						.flatten()
 						.unwrap_or_else(|| "about:blank".to_string())),
+					requested: false,
 				},
 				other => Self::Link {
 					url: value
@@ -111,6 +319,24 @@ impl From<Element> for DocumentNode {
 						.unwrap_or_else(|| "about:blank".to_string()),
 					mime: other.unwrap_or_else(|| "text/plain".to_string()),
 					label: from_iter!(value.children),
+					rel: value
+						.attributes
+						.get("rel")
+						.cloned()
+						.flatten()
+						.map(|rel| {
+							rel.split_ascii_whitespace()
+								.map(str::to_ascii_lowercase)
+								.collect()
+						})
+						.unwrap_or_default(),
+					title: value
+						.attributes
+						.get("title")
+						.cloned()
+						.flatten()
+						.map(|title| title.trim().to_string())
+						.filter(|title| !title.is_empty()),
 				},
 			},
 			"img" => Self::Image {
@@ -129,43 +355,562 @@ impl From<Element> for DocumentNode {
 			},
 			"i" | "em" => Self::Emph(from_iter!(value.children)),
 			"b" | "strong" => Self::Strong(from_iter!(value.children)),
+			"del" | "s" | "strike" => Self::Strike(from_iter!(value.children)),
+			"u" | "ins" => Self::Underline(from_iter!(value.children)),
+			"sup" => Self::Superscript(from_iter!(value.children)),
+			"sub" => Self::Subscript(from_iter!(value.children)),
+			"dl" => {
+				let mut pairs = vec![];
+				collect_definitions(value.children, &mut pairs);
+				Self::DefList(pairs)
+			}
+			"figure" => figure(value.children),
+			"iframe" => value
+				.attributes
+				.get("src")
+				.cloned()
+				.flatten()
+				.map_or(Self::Empty, |src| iframe_to_node(&src)),
+			"math" => {
+				let mut text = String::new();
+				math_text(&value.children, &mut text);
+				Self::Math {
+					source: value.source_span.text.trim().to_string(),
+					text: text.trim().to_string(),
+				}
+			}
 			_ => Self::Unk(from_iter!(value.children)),
 		}
 	}
 }
 
+/// Splits a `<figure>`'s children into its captions and everything else. A lone image (or
+/// video, or whatever it is) is kept as-is; several are laid out together.
+fn figure(children: Vec<Node>) -> DocumentNode {
+	let mut content = vec![];
+	let mut caption = vec![];
+	for child in children {
+		match child {
+			Node::Element(e) if e.name == "figcaption" => {
+				caption.extend(e.children.into_iter().map(DocumentNode::from));
+			}
+			Node::Text(t) if t.trim().is_empty() => {}
+			Node::Comment(_) => {}
+			other => content.push(DocumentNode::from(other)),
+		}
+	}
+	let image = match content.len() {
+		0 => DocumentNode::Empty,
+		1 => content.remove(0),
+		_ => DocumentNode::Div(content),
+	};
+	DocumentNode::Figure {
+		image: Box::new(image),
+		caption,
+	}
+}
+
+/// Turns an `<iframe>` into a link, since embedded players can't be shown. YouTube and Vimeo
+/// players link to the video's own page, and anything else links to what was embedded.
+#[must_use]
+pub fn iframe_to_node(src: &str) -> DocumentNode {
+	let src = src.trim();
+	// Embeds are often protocol-relative, which would be useless outside the page.
+	let src = match src.strip_prefix("//") {
+		Some(rest) => format!("https://{rest}"),
+		None => src.to_string(),
+	};
+	video_link(&src).unwrap_or_else(|| DocumentNode::Link {
+		url: src,
+		mime: "text/html".to_string(),
+		label: vec![DocumentNode::TextLeaf("Embedded content (open externally)".to_string())],
+		rel: vec![],
+		title: None,
+	})
+}
+
+/// A link to the page a YouTube or Vimeo video is watched on, labelled with which it is, if
+/// `href` is one of those pages or a player embedding one.
+#[must_use]
+pub fn video_link(href: &str) -> Option<DocumentNode> {
+	let (provider, url) = video_page(href)?;
+	Some(DocumentNode::Link {
+		url,
+		mime: "text/html".to_string(),
+		label: vec![DocumentNode::TextLeaf(format!("{provider} video"))],
+		rel: vec![],
+		title: None,
+	})
+}
+
+/// Which provider a video page or embedded player's from, and the page to watch its video on.
+fn video_page(href: &str) -> Option<(&'static str, String)> {
+	let url = reqwest::Url::parse(href).ok()?;
+	let host = url.host_str()?;
+	let host = host.strip_prefix("www.").unwrap_or(host);
+	let path: Vec<_> = url.path_segments()?.filter(|segment| !segment.is_empty()).collect();
+	let numeric = |id: &str| id.bytes().all(|b| b.is_ascii_digit());
+	let has_video_id = url.query_pairs().any(|(key, _)| key == "v");
+	match (host, path.as_slice()) {
+		("youtube.com" | "m.youtube.com" | "youtube-nocookie.com", ["embed", id]) => {
+			let mut watch = format!("https://www.youtube.com/watch?v={id}");
+			if let Some((_, start)) = url.query_pairs().find(|(key, _)| key == "start") {
+				watch.push_str(&format!("&t={start}"));
+			}
+			Some(("YouTube", watch))
+		}
+		("youtube.com" | "m.youtube.com", ["watch"]) if has_video_id => {
+			Some(("YouTube", href.to_string()))
+		}
+		("youtu.be", [_]) => Some(("YouTube", href.to_string())),
+		("player.vimeo.com", ["video", id]) if numeric(id) => {
+			Some(("Vimeo", format!("https://vimeo.com/{id}")))
+		}
+		("vimeo.com", [id]) if numeric(id) => Some(("Vimeo", href.to_string())),
+		_ => None,
+	}
+}
+
+/// Writes MathML as plain text. The TeX in an `<annotation>` is used if there is any, and
+/// otherwise scripts, fractions and roots are spelled out with `^`, `_`, `/` and `√`.
+fn math_text(nodes: &[Node], out: &mut String) {
+	let tex = nodes.iter().find_map(|node| match node {
+		Node::Element(e) if e.name == "semantics" => e.children.iter().find_map(|node| match node {
+			Node::Element(e)
+				if e.name == "annotation"
+					&& e.attributes.get("encoding").cloned().flatten().as_deref()
+						== Some("application/x-tex") =>
+			{
+				Some(e)
+			}
+			_ => None,
+		}),
+		_ => None,
+	});
+	if let Some(tex) = tex {
+		let mut text = String::new();
+		math_text(&tex.children, &mut text);
+		out.push_str(&text);
+		return;
+	}
+	for node in nodes {
+		let Node::Element(e) = node else {
+			if let Node::Text(text) = node {
+				out.push_str(&decode_entities(text.trim()));
+			}
+			continue;
+		};
+		// Each argument on its own, bracketed if it's more than one character.
+		let args: Vec<String> = e
+			.children
+			.iter()
+			.filter(|child| matches!(child, Node::Element(_)))
+			.map(|child| {
+				let mut arg = String::new();
+				math_text(std::slice::from_ref(child), &mut arg);
+				if arg.chars().count() > 1 {
+					format!("({arg})")
+				} else {
+					arg
+				}
+			})
+			.collect();
+		match (e.name.as_str(), args.as_slice()) {
+			("annotation" | "annotation-xml", _) => {}
+			("msup", [base, sup]) => out.push_str(&format!("{base}^{sup}")),
+			("msub", [base, sub]) => out.push_str(&format!("{base}_{sub}")),
+			("msubsup", [base, sub, sup]) => out.push_str(&format!("{base}_{sub}^{sup}")),
+			("mfrac", [numerator, denominator]) => {
+				out.push_str(&format!("{numerator}/{denominator}"));
+			}
+			("mroot", [base, index]) => out.push_str(&format!("{index}√{base}")),
+			("msqrt", _) => {
+				let mut inner = String::new();
+				math_text(&e.children, &mut inner);
+				out.push_str(&format!("√({inner})"));
+			}
+			_ => math_text(&e.children, out),
+		}
+	}
+}
+
+/// Whether a `<math>` element is shown as its own block, rather than inline with the text.
+fn is_display_math(source: &str) -> bool {
+	let open = source.split('>').next().unwrap_or_default().to_ascii_lowercase();
+	open.contains("display=\"block\"") || open.contains("display='block'")
+}
+
+/// What a link points at, which decides how it's opened.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum LinkKind {
+	/// A web page, to be opened in the browser.
+	Web,
+	/// An email address, to be opened in the mail client.
+	Mail,
+	/// A phone number.
+	Phone,
+	/// An anchor within the current article.
+	Fragment,
+	/// Anything else, like `about:blank` or `javascript:`, which shouldn't be opened.
+	Other,
+}
+
+/// What a left click on a link does, besides opening it. Middle clicks only ever open it.
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LinkClick {
+	#[default]
+	Open,
+	/// Also minimize the window, so the browser ends up in front.
+	OpenAndMinimize,
+}
+
+impl LinkClick {
+	/// Whether clicking a link to `kind` should minimize the window.
+	#[must_use]
+	pub fn minimizes(self, kind: LinkKind, middle_click: bool) -> bool {
+		self == LinkClick::OpenAndMinimize && kind == LinkKind::Web && !middle_click
+	}
+}
+
+#[must_use]
+pub fn classify_link(href: &str) -> LinkKind {
+	let href = href.trim();
+	if href.starts_with('#') {
+		return LinkKind::Fragment;
+	}
+	let scheme = href
+		.split_once(':')
+		.map(|(scheme, _)| scheme.to_ascii_lowercase());
+	match scheme.as_deref() {
+		Some("http" | "https") => LinkKind::Web,
+		Some("mailto") => LinkKind::Mail,
+		Some("tel") => LinkKind::Phone,
+		_ => LinkKind::Other,
+	}
+}
+
+/// How an ordered list is numbered, from its `type` attribute.
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy, serde::Serialize, serde::Deserialize)]
+pub enum ListStyle {
+	/// 1, 2, 3
+	#[default]
+	Decimal,
+	/// a, b, c
+	LowerAlpha,
+	/// A, B, C
+	UpperAlpha,
+	/// i, ii, iii
+	LowerRoman,
+	/// I, II, III
+	UpperRoman,
+}
+
+impl ListStyle {
+	/// Reads an `<ol type>`. Anything unrecognized is decimal.
+	#[must_use]
+	pub fn from_type(attr: &str) -> Self {
+		match attr.trim() {
+			"a" => ListStyle::LowerAlpha,
+			"A" => ListStyle::UpperAlpha,
+			"i" => ListStyle::LowerRoman,
+			"I" => ListStyle::UpperRoman,
+			_ => ListStyle::Decimal,
+		}
+	}
+
+	/// The `<ol type>` for this style.
+	#[must_use]
+	pub fn type_attr(self) -> &'static str {
+		match self {
+			ListStyle::Decimal => "1",
+			ListStyle::LowerAlpha => "a",
+			ListStyle::UpperAlpha => "A",
+			ListStyle::LowerRoman => "i",
+			ListStyle::UpperRoman => "I",
+		}
+	}
+}
+
+/// The marker for the list item numbered `index`. Numbers which can't be written in `style`,
+/// like 0 in letters or numerals, are written in decimal instead.
+#[must_use]
+pub fn list_marker(index: usize, style: ListStyle) -> String {
+	match style {
+		ListStyle::Decimal => index.to_string(),
+		_ if index == 0 => index.to_string(),
+		ListStyle::LowerAlpha | ListStyle::UpperAlpha => {
+			// Bijective base 26: z is followed by aa.
+			let mut letters = vec![];
+			let mut n = index;
+			while n > 0 {
+				n -= 1;
+				letters.push(b'a' + (n % 26) as u8);
+				n /= 26;
+			}
+			letters.reverse();
+			let marker = String::from_utf8(letters).expect("Letters are ASCII");
+			if style == ListStyle::UpperAlpha {
+				marker.to_ascii_uppercase()
+			} else {
+				marker
+			}
+		}
+		ListStyle::LowerRoman | ListStyle::UpperRoman if index < 4000 => {
+			const NUMERALS: [(usize, &str); 13] = [
+				(1000, "M"),
+				(900, "CM"),
+				(500, "D"),
+				(400, "CD"),
+				(100, "C"),
+				(90, "XC"),
+				(50, "L"),
+				(40, "XL"),
+				(10, "X"),
+				(9, "IX"),
+				(5, "V"),
+				(4, "IV"),
+				(1, "I"),
+			];
+			let mut marker = String::new();
+			let mut n = index;
+			for (value, numeral) in NUMERALS {
+				while n >= value {
+					marker.push_str(numeral);
+					n -= value;
+				}
+			}
+			if style == ListStyle::LowerRoman {
+				marker.to_ascii_lowercase()
+			} else {
+				marker
+			}
+		}
+		ListStyle::LowerRoman | ListStyle::UpperRoman => index.to_string(),
+	}
+}
+
+/// Pairs up the `<dt>`s and `<dd>`s in a definition list. Terms without a definition get an
+/// empty one, definitions without a term get an empty term, and consecutive definitions are
+/// merged under the same term.
+fn collect_definitions(
+	children: Vec<Node>,
+	pairs: &mut Vec<(Vec<DocumentNode>, Vec<DocumentNode>)>,
+) {
+	let mut last_was_term = false;
+	for child in children {
+		match child {
+			Node::Element(e) if e.name == "dt" => {
+				pairs.push((from_iter!(e.children), vec![]));
+				last_was_term = true;
+			}
+			Node::Element(e) if e.name == "dd" => {
+				match pairs.last_mut() {
+					Some((_, definition)) if last_was_term || !definition.is_empty() => {
+						definition.push(DocumentNode::Span(from_iter!(e.children)));
+					}
+					_ => pairs.push((vec![], vec![DocumentNode::Span(from_iter!(e.children))])),
+				}
+				last_was_term = false;
+			}
+			// HTML allows wrapping each group in a div.
+			Node::Element(e) if e.name == "div" => collect_definitions(e.children, pairs),
+			Node::Text(t) if t.trim().is_empty() => {}
+			Node::Comment(_) => {}
+			other => match pairs.last_mut() {
+				Some((_, definition)) => definition.push(DocumentNode::from(other)),
+				None => pairs.push((vec![], vec![DocumentNode::from(other)])),
+			},
+		}
+	}
+}
+
+/// Whether a link's `rel` says it was paid for.
+#[cfg(feature = "gui")]
+fn is_sponsored(rel: &[String]) -> bool {
+	rel.iter().any(|rel| rel == "sponsored")
+}
+
+/// Whether links marked `rel="sponsored"` get a badge saying so, in every document shown in
+/// `ctx`. Off unless this is called.
+#[cfg(feature = "gui")]
+pub fn flag_sponsored_links(ctx: &Context, flag: bool) {
+	ctx.data_mut(|data| data.insert_temp(Id::new("flag sponsored links"), flag));
+}
+
+#[cfg(feature = "gui")]
+fn flags_sponsored_links(ctx: &Context) -> bool {
+	ctx.data(|data| data.get_temp(Id::new("flag sponsored links")).unwrap_or(false))
+}
+
+/// What clicking links does, in every document shown in `ctx` from now on.
+#[cfg(feature = "gui")]
+pub fn set_link_click(ctx: &Context, click: LinkClick) {
+	ctx.data_mut(|data| data.insert_temp(Id::new("link click"), click));
+}
+
+#[cfg(feature = "gui")]
+fn link_click(ctx: &Context) -> LinkClick {
+	ctx.data(|data| data.get_temp(Id::new("link click")).unwrap_or_default())
+}
+
+/// Shows `inner` with the text style `key` turned on, like `"strike"`. See [`decorate`].
+#[cfg(feature = "gui")]
+fn show_styled(
+	ui: &mut eframe::egui::Ui,
+	key: &'static str,
+	inner: &mut [DocumentNode],
+	frame: &mut Frame,
+	media: &dyn MediaHandler,
+) {
+	let orig_state = ui.memory(|memory| memory.data.get_temp(key.into()).unwrap_or(false));
+	ui.memory_mut(|memory| memory.data.insert_temp(key.into(), true));
+	show_children(ui, inner, frame, media);
+	ui.memory_mut(|memory| memory.data.insert_temp(key.into(), orig_state));
+}
+
+/// Shows the children of an inline node in whatever line they're already part of, or, if any
+/// of them are blocks, stacks them with [`show_flow`].
+#[cfg(feature = "gui")]
+fn show_children(
+	ui: &mut eframe::egui::Ui,
+	inner: &mut [DocumentNode],
+	frame: &mut Frame,
+	media: &dyn MediaHandler,
+) {
+	if inner.iter().all(DocumentNode::is_inline) {
+		inner.iter_mut().for_each(|el| el.show(ui, frame, media));
+	} else {
+		ui.vertical(|ui| show_flow(ui, inner, frame, media));
+	}
+}
+
+/// Lays `nodes` out top to bottom, with each run of inline nodes wrapped to the width there is,
+/// like a paragraph, and each block node on its own.
+#[cfg(feature = "gui")]
+fn show_flow(
+	ui: &mut eframe::egui::Ui,
+	nodes: &mut [DocumentNode],
+	frame: &mut Frame,
+	media: &dyn MediaHandler,
+) {
+	let mut rest = nodes;
+	while let Some(first) = rest.first() {
+		let run = if first.is_inline() {
+			rest.iter().take_while(|node| node.is_inline()).count()
+		} else {
+			1
+		};
+		let (nodes, after) = rest.split_at_mut(run);
+		if run == 1 && !nodes[0].is_inline() {
+			nodes[0].show(ui, frame, media);
+		} else {
+			ui.horizontal_wrapped(|ui| {
+				ui.set_max_width(ui.available_width());
+				nodes.iter_mut().for_each(|el| el.show(ui, frame, media));
+			});
+		}
+		rest = after;
+	}
+}
+
+/// Applies the text styles turned on by [`show_styled`]. egui can't lower text, so subscripts
+/// are just made small.
+#[cfg(feature = "gui")]
+fn decorate(ui: &eframe::egui::Ui) -> impl Fn(RichText) -> RichText {
+	let on = |key: &'static str| ui.memory(|memory| memory.data.get_temp(key.into()).unwrap_or(false));
+	let (strike, underline, sup, sub) = (on("strike"), on("underline"), on("sup"), on("sub"));
+	let heading = on("heading");
+	move |mut text| {
+		if heading {
+			text = text.heading();
+		}
+		if strike {
+			text = text.strikethrough();
+		}
+		if underline {
+			text = text.underline();
+		}
+		if sup {
+			text = text.small_raised();
+		} else if sub {
+			text = text.small();
+		}
+		text
+	}
+}
+
+/// Asks whatever's showing the document to show the image at `url` enlarged.
+#[cfg(feature = "gui")]
+fn request_enlarged(ctx: &Context, url: &str) {
+	ctx.data_mut(|data| data.insert_temp(Id::new("enlarge image"), url.to_string()));
+}
+
+/// The image which was last clicked to be shown enlarged, if one has been since this was last
+/// called.
+#[cfg(feature = "gui")]
+#[must_use]
+pub fn take_enlarge_request(ctx: &Context) -> Option<String> {
+	let id = Id::new("enlarge image");
+	ctx.data_mut(|data| {
+		let url = data.get_temp(id);
+		data.remove::<String>(id);
+		url
+	})
+}
+
+/// Whether any image loaders have been registered, which is needed for [`DocumentNode::Image`]
+/// to show anything but its alt text. See `egui_extras::install_image_loaders`.
+#[cfg(feature = "gui")]
+#[must_use]
+pub fn image_loaders_installed(ctx: &Context) -> bool {
+	!ctx.loaders().image.lock().is_empty()
+}
+
 #[cfg(feature = "gui")]
 #[allow(clippy::too_many_lines)]
 impl DocumentNode {
-	pub fn show(&mut self, ui: &mut eframe::egui::Ui, frame: &mut Frame) {
+	pub fn show(
+		&mut self,
+		ui: &mut eframe::egui::Ui,
+		frame: &mut Frame,
+		media: &dyn MediaHandler,
+	) {
+		let presentation = self.media_presentation(media);
 		match self {
 			DocumentNode::Sep => {
 				ui.separator();
 			}
-			DocumentNode::Root(inner) => {
-				ui.vertical(|ui| {
-					inner.iter_mut().for_each(|el| el.show(ui, frame));
-				});
-			}
-			DocumentNode::Div(inner) => {
-				ui.horizontal_wrapped(|ui| inner.iter_mut().for_each(|el| el.show(ui, frame)));
+			DocumentNode::Root(inner)
+			| DocumentNode::Div(inner)
+			| DocumentNode::Unk(inner)
+			| DocumentNode::Paragraph(inner) => {
+				ui.vertical(|ui| show_flow(ui, inner, frame, media));
 			}
-			DocumentNode::Span(inner) | DocumentNode::Unk(inner) => {
-				inner.iter_mut().for_each(|el| el.show(ui, frame));
+			DocumentNode::Heading { inner, .. } => {
+				let orig_state =
+					ui.memory(|memory| memory.data.get_temp("heading".into()).unwrap_or(false));
+				ui.memory_mut(|memory| memory.data.insert_temp("heading".into(), true));
+				ui.vertical(|ui| show_flow(ui, inner, frame, media));
+				ui.memory_mut(|memory| memory.data.insert_temp("heading".into(), orig_state));
 			}
+			// It's a block of its own, so whatever's either side of it is already on separate
+			// lines.
+			DocumentNode::LineBreak => {}
+			DocumentNode::Span(inner) => show_children(ui, inner, frame, media),
 			DocumentNode::UList(inner) => {
 				for el in inner.iter_mut() {
 					ui.horizontal(|ui| {
 						ui.label("* ");
-						el.show(ui, frame);
+						ui.vertical(|ui| show_flow(ui, std::slice::from_mut(el), frame, media));
 					});
 				}
 			}
-			DocumentNode::OList(inner) => {
-				for (n, el) in inner.iter_mut().enumerate() {
+			DocumentNode::OList { start, style, items } => {
+				for (n, el) in items.iter_mut().enumerate() {
 					ui.horizontal(|ui| {
-						ui.label(format!("{n}. "));
-						el.show(ui, frame);
+						ui.label(format!("{}. ", list_marker(*start + n, *style)));
+						ui.vertical(|ui| show_flow(ui, std::slice::from_mut(el), frame, media));
 					});
 				}
 			}
@@ -173,7 +918,7 @@ impl DocumentNode {
 				let orig_state =
 					ui.memory(|memory| memory.data.get_temp("italic".into()).unwrap_or(false));
 				ui.memory_mut(|memory| *memory.data.get_temp_mut_or("italic".into(), true) = true);
-				inner.iter_mut().for_each(|el| el.show(ui, frame));
+				show_children(ui, inner, frame, media);
 				ui.memory_mut(|memory| {
 					*memory.data.get_temp_mut_or("italic".into(), orig_state) = orig_state;
 				});
@@ -183,29 +928,62 @@ impl DocumentNode {
 				let orig_state =
 					ui.memory(|memory| memory.data.get_temp("strong".into()).unwrap_or(false));
 				ui.memory_mut(|memory| *memory.data.get_temp_mut_or("strong".into(), true) = true);
-				inner.iter_mut().for_each(|el| el.show(ui, frame));
+				show_children(ui, inner, frame, media);
 				ui.memory_mut(|memory| {
 					*memory.data.get_temp_mut_or("strong".into(), orig_state) = orig_state;
 				});
 				ui.label("/");
 			}
+			DocumentNode::Strike(inner) => show_styled(ui, "strike", inner, frame, media),
+			DocumentNode::Underline(inner) => show_styled(ui, "underline", inner, frame, media),
+			DocumentNode::Superscript(inner) => show_styled(ui, "sup", inner, frame, media),
+			DocumentNode::Subscript(inner) => show_styled(ui, "sub", inner, frame, media),
+			DocumentNode::DefList(pairs) => {
+				for (term, definition) in pairs.iter_mut() {
+					let orig_state =
+						ui.memory(|memory| memory.data.get_temp("strong".into()).unwrap_or(false));
+					ui.memory_mut(|memory| {
+						*memory.data.get_temp_mut_or("strong".into(), true) = true;
+					});
+					show_flow(ui, term, frame, media);
+					ui.memory_mut(|memory| {
+						*memory.data.get_temp_mut_or("strong".into(), orig_state) = orig_state;
+					});
+					ui.indent("definition", |ui| show_flow(ui, definition, frame, media));
+				}
+			}
 			DocumentNode::TextLeaf(text) => {
 				let strong =
 					ui.memory(|memory| memory.data.get_temp("strong".into()).unwrap_or(false));
 				let emph = ui.memory(|memory| memory.data.get_temp("emph".into()).unwrap_or(false));
-				let mut text = RichText::new(text.clone());
-				if strong {
-					text = text.strong();
-				}
-				if emph {
-					text = text.italics();
-				}
-				ui.label(text);
+				let small =
+					ui.memory(|memory| memory.data.get_temp("small".into()).unwrap_or(false));
+				let decorate = decorate(ui);
+				find::highlighted_label(ui, text, |mut text| {
+					if strong {
+						text = text.strong();
+					}
+					if emph {
+						text = text.italics();
+					}
+					if small {
+						text = text.small();
+					}
+					decorate(text)
+				});
 			}
-			DocumentNode::Link { url, mime: _, label } => {
+			DocumentNode::Link {
+				url,
+				label,
+				rel,
+				title,
+				..
+			} => {
 				let strong =
 					ui.memory(|memory| memory.data.get_temp("strong".into()).unwrap_or(false));
 				let emph = ui.memory(|memory| memory.data.get_temp("emph".into()).unwrap_or(false));
+				let small =
+					ui.memory(|memory| memory.data.get_temp("small".into()).unwrap_or(false));
 				let mut text = RichText::new(DocumentNode::many_to_string(label.iter(), " "));
 				if strong {
 					text = text.strong();
@@ -213,42 +991,167 @@ impl DocumentNode {
 				if emph {
 					text = text.italics();
 				}
-				let button = ui.button(text);
-				if button.clicked_by(eframe::egui::PointerButton::Middle) {
-					open::that(url).expect("Failed to open that url");
-				} else if button.clicked() {
-					frame.set_minimized(true);
-					open::that(url).expect("Failed to open that url");
+				if small {
+					text = text.small();
+				}
+				let text = decorate(ui)(text);
+				let hover = title.as_deref().unwrap_or(url);
+				match classify_link(url) {
+					LinkKind::Web => {
+						let mut button = ui.button(text);
+						if title.is_some() {
+							button = button.on_hover_text(hover);
+						}
+						let middle_click = button.clicked_by(eframe::egui::PointerButton::Middle);
+						if middle_click || button.clicked() {
+							if link_click(ui.ctx()).minimizes(LinkKind::Web, middle_click) {
+								frame.set_minimized(true);
+							}
+							open::that(url).expect("Failed to open that url");
+						}
+					}
+					// These open in a small external app, so there's no need to get out of the way.
+					LinkKind::Mail | LinkKind::Phone => {
+						if ui.button(text).on_hover_text(hover).clicked() {
+							open::that(url).expect("Failed to open that url");
+						}
+					}
+					// There's nowhere useful to send these, so just show the text.
+					LinkKind::Fragment | LinkKind::Other => {
+						ui.label(text.underline()).on_hover_text(hover);
+					}
+				}
+				if is_sponsored(rel) && flags_sponsored_links(ui.ctx()) {
+					ui.weak(RichText::new("Sponsored").small())
+						.on_hover_text("The page marked this link as paid for");
 				}
 			}
 			DocumentNode::Image { label, url } => {
 				ui.label(label.as_str());
-				ui.add(Image::new(url.as_str()).max_height(300.0));
+				if !image_loaders_installed(ui.ctx()) {
+					ui.weak(format!("[Image: {label}]"));
+					return;
+				}
+				if !media.autoload(MediaKind::Image) {
+					let id = Id::new(("load image", url.as_str()));
+					if !ui.memory(|memory| memory.data.get_temp(id).unwrap_or(false)) {
+						if ui.button("Click to load").on_hover_text(url.as_str()).clicked() {
+							ui.memory_mut(|memory| memory.data.insert_temp(id, true));
+						}
+						return;
+					}
+				}
+				let image = Image::new(url.as_str()).max_height(300.0);
+				match image.load_for_size(ui.ctx(), ui.available_size()) {
+					Ok(TexturePoll::Ready { .. }) => {
+						let response = ui
+							.add(image.sense(eframe::egui::Sense::click()))
+							.on_hover_text("Click to enlarge");
+						if response.clicked() {
+							request_enlarged(ui.ctx(), url);
+						}
+					}
+					Ok(TexturePoll::Pending { .. }) => {
+						ui.spinner();
+					}
+					Err(e) => {
+						ui.weak(format!("[Image: {label}]")).on_hover_text(e.to_string());
+					}
+				}
 			}
-			DocumentNode::Video { label, fetched } => {
+			DocumentNode::Video { label, mime: _, fetched, requested } => {
 				ui.label(label.as_str());
+				if presentation == Some(MediaPresentation::Stream) {
+					if let Some(url) = fetched.url() {
+						if ui.button("Play Video").on_hover_text(url).clicked() {
+							open::that(url).expect("Failed to open video in system app");
+						}
+					}
+					return;
+				}
 				match fetched {
 					MaybeLoaded::Done(_, Ok(media)) => media.show(ui),
 					MaybeLoaded::Done(_, Err(e)) => {
 						ui.label(format!("Error: {e}"));
 					}
+					MaybeLoaded::NotStarted(_)
+						if !*requested && !media.autoload(MediaKind::Video) =>
+					{
+						if ui.button("Click to load").clicked() {
+							*requested = true;
+						}
+					}
+					MaybeLoaded::TooLarge(_, limit) => {
+						ui.label(format!("Too large to download (over {limit} bytes)"));
+					}
+					MaybeLoaded::NotCached(_) => {
+						ui.label("Not available offline");
+					}
 					_ => {
 						ui.label("Loading video...");
 					}
 				}
 			}
-			DocumentNode::Audio { label, fetched } => {
+			DocumentNode::Audio { label, mime: _, fetched, requested } => {
 				ui.label(label.as_str());
+				if presentation == Some(MediaPresentation::Stream) {
+					if let Some(url) = fetched.url() {
+						if ui.button("Play Audio").on_hover_text(url).clicked() {
+							open::that(url).expect("Failed to open audio in system app");
+						}
+					}
+					return;
+				}
 				match fetched {
 					MaybeLoaded::Done(_, Ok(media)) => media.show(ui),
 					MaybeLoaded::Done(_, Err(e)) => {
 						ui.label(format!("Error: {e}"));
 					}
+					MaybeLoaded::NotStarted(_)
+						if !*requested && !media.autoload(MediaKind::Audio) =>
+					{
+						if ui.button("Click to load").clicked() {
+							*requested = true;
+						}
+					}
+					MaybeLoaded::TooLarge(_, limit) => {
+						ui.label(format!("Too large to download (over {limit} bytes)"));
+					}
+					MaybeLoaded::NotCached(_) => {
+						ui.label("Not available offline");
+					}
 					_ => {
 						ui.label("Loading audio...");
 					}
 				}
 			}
+			DocumentNode::Figure { image, caption } => {
+				ui.vertical(|ui| {
+					image.show(ui, frame, media);
+					if caption.is_empty() {
+						return;
+					}
+					let orig_state = ui.memory(|memory| {
+						(
+							memory.data.get_temp("emph".into()).unwrap_or(false),
+							memory.data.get_temp("small".into()).unwrap_or(false),
+						)
+					});
+					ui.memory_mut(|memory| {
+						memory.data.insert_temp("emph".into(), true);
+						memory.data.insert_temp("small".into(), true);
+					});
+					show_flow(ui, caption, frame, media);
+					ui.memory_mut(|memory| {
+						memory.data.insert_temp("emph".into(), orig_state.0);
+						memory.data.insert_temp("small".into(), orig_state.1);
+					});
+				});
+			}
+			DocumentNode::Math { text, .. } => {
+				ui.label(RichText::new(text.as_str()).monospace())
+					.on_hover_text("MathML can't be shown yet, so this is the equation as text");
+			}
 			DocumentNode::Empty => {}
 		}
 	}
@@ -259,45 +1162,833 @@ impl DocumentNode {
 		iter.map(ToString::to_string).collect::<Vec<_>>().join(join)
 	}
 
-	pub fn tick(&mut self, rt: &Runtime) {
+	/// How `media` wants this node presented, if it's audio or video.
+	#[must_use]
+	pub fn media_presentation(&self, media: &dyn MediaHandler) -> Option<MediaPresentation> {
+		let (kind, mime, url) = match self {
+			DocumentNode::Video { mime, fetched, .. } => (MediaKind::Video, mime, fetched.url()),
+			DocumentNode::Audio { mime, fetched, .. } => (MediaKind::Audio, mime, fetched.url()),
+			_ => return None,
+		};
+		Some(media.presentation(kind, url.unwrap_or("about:blank"), mime))
+	}
+
+	/// Calls `f` on this node and then every node beneath it, depth-first.
+	pub fn visit<F: FnMut(&DocumentNode)>(&self, f: &mut F) {
+		f(self);
 		match self {
 			DocumentNode::Root(inner)
 			| DocumentNode::Div(inner)
 			| DocumentNode::Span(inner)
 			| DocumentNode::Unk(inner)
+			| DocumentNode::Paragraph(inner)
+			| DocumentNode::Heading { inner, .. }
 			| DocumentNode::UList(inner)
-			| DocumentNode::OList(inner)
+			| DocumentNode::OList { items: inner, .. }
 			| DocumentNode::Emph(inner)
-			| DocumentNode::Strong(inner) => {
+			| DocumentNode::Strong(inner)
+			| DocumentNode::Strike(inner)
+			| DocumentNode::Underline(inner)
+			| DocumentNode::Superscript(inner)
+			| DocumentNode::Subscript(inner)
+			| DocumentNode::Link { label: inner, .. } => {
 				for child in inner {
-					child.tick(rt);
+					child.visit(f);
 				}
 			}
-			DocumentNode::Video { label: _, fetched } => {
-				rt.block_on(fetched.tick());
+			DocumentNode::DefList(pairs) => {
+				for child in pairs.iter().flat_map(|(t, d)| t.iter().chain(d.iter())) {
+					child.visit(f);
+				}
 			}
-			DocumentNode::Audio { label: _, fetched } => {
-				rt.block_on(fetched.tick());
+			DocumentNode::Figure { image, caption } => {
+				image.visit(f);
+				for child in caption {
+					child.visit(f);
+				}
 			}
 			_ => {}
 		}
 	}
+
+	/// Like [`DocumentNode::visit`], but allows changing the nodes. `f` sees each node before
+	/// its children, so any children it adds are visited too.
+	pub fn visit_mut<F: FnMut(&mut DocumentNode)>(&mut self, f: &mut F) {
+		f(self);
+		match self {
+			DocumentNode::Root(inner)
+			| DocumentNode::Div(inner)
+			| DocumentNode::Span(inner)
+			| DocumentNode::Unk(inner)
+			| DocumentNode::Paragraph(inner)
+			| DocumentNode::Heading { inner, .. }
+			| DocumentNode::UList(inner)
+			| DocumentNode::OList { items: inner, .. }
+			| DocumentNode::Emph(inner)
+			| DocumentNode::Strong(inner)
+			| DocumentNode::Strike(inner)
+			| DocumentNode::Underline(inner)
+			| DocumentNode::Superscript(inner)
+			| DocumentNode::Subscript(inner)
+			| DocumentNode::Link { label: inner, .. } => {
+				for child in inner {
+					child.visit_mut(f);
+				}
+			}
+			DocumentNode::DefList(pairs) => {
+				for child in pairs.iter_mut().flat_map(|(t, d)| t.iter_mut().chain(d.iter_mut())) {
+					child.visit_mut(f);
+				}
+			}
+			DocumentNode::Figure { image, caption } => {
+				image.visit_mut(f);
+				for child in caption {
+					child.visit_mut(f);
+				}
+			}
+			_ => {}
+		}
+	}
+
+	/// Advances any media downloads in this tree, skipping media which `media` wants streamed.
+	pub fn tick(&mut self, rt: &Runtime, media: &dyn MediaHandler) {
+		self.visit_mut(&mut |node| {
+			let presentation = node.media_presentation(media);
+			match node {
+				DocumentNode::Video { fetched, requested, .. }
+					if presentation == Some(MediaPresentation::Download)
+						&& (*requested || media.autoload(MediaKind::Video)) =>
+				{
+					rt.block_on(fetched.tick_media());
+				}
+				DocumentNode::Audio { fetched, requested, .. }
+					if presentation == Some(MediaPresentation::Download)
+						&& (*requested || media.autoload(MediaKind::Audio)) =>
+				{
+					rt.block_on(fetched.tick_media());
+				}
+				_ => {}
+			}
+		});
+	}
+
+	/// Whether this node flows with the text around it, wrapping onto the next line as
+	/// needed, rather than being laid out as its own block. Unknown elements are blocks, since
+	/// they're mostly paragraphs and headings, and a styled span is only inline if everything
+	/// in it is.
+	#[must_use]
+	pub fn is_inline(&self) -> bool {
+		match self {
+			DocumentNode::TextLeaf(_)
+			| DocumentNode::Link { .. }
+			| DocumentNode::Image { .. }
+			| DocumentNode::Empty => true,
+			DocumentNode::Math { source, .. } => !is_display_math(source),
+			DocumentNode::Span(inner)
+			| DocumentNode::Emph(inner)
+			| DocumentNode::Strong(inner)
+			| DocumentNode::Strike(inner)
+			| DocumentNode::Underline(inner)
+			| DocumentNode::Superscript(inner)
+			| DocumentNode::Subscript(inner) => inner.iter().all(DocumentNode::is_inline),
+			DocumentNode::Root(_)
+			| DocumentNode::Div(_)
+			| DocumentNode::Unk(_)
+			| DocumentNode::Paragraph(_)
+			| DocumentNode::Heading { .. }
+			| DocumentNode::LineBreak
+			| DocumentNode::UList(_)
+			| DocumentNode::OList { .. }
+			| DocumentNode::DefList(_)
+			| DocumentNode::Sep
+			| DocumentNode::Video { .. }
+			| DocumentNode::Audio { .. }
+			| DocumentNode::Figure { .. } => false,
+		}
+	}
+
+	/// Whether this node is laid out as its own block rather than inline with its siblings.
+	#[must_use]
+	pub fn is_block(&self) -> bool {
+		!self.is_inline()
+	}
+
+	fn is_blank_text(&self) -> bool {
+		matches!(self, DocumentNode::TextLeaf(text) if text.trim().is_empty())
+	}
+
+	/// Removes whitespace-only text which would only show up as stray gaps: any next to a block
+	/// node, and any at the start or end of a block. Whitespace between inline nodes is kept.
+	/// Empty nodes and `<div>`s go too, and runs of separators are collapsed into one.
+	pub fn normalize(&mut self) {
+		let is_block = self.is_block();
+		match self {
+			DocumentNode::Root(inner)
+			| DocumentNode::Div(inner)
+			| DocumentNode::Span(inner)
+			| DocumentNode::Unk(inner)
+			| DocumentNode::Paragraph(inner)
+			| DocumentNode::Heading { inner, .. }
+			| DocumentNode::UList(inner)
+			| DocumentNode::OList { items: inner, .. }
+			| DocumentNode::Emph(inner)
+			| DocumentNode::Strong(inner)
+			| DocumentNode::Strike(inner)
+			| DocumentNode::Underline(inner)
+			| DocumentNode::Superscript(inner)
+			| DocumentNode::Subscript(inner)
+			| DocumentNode::Link { label: inner, .. } => {
+				inner.iter_mut().for_each(DocumentNode::normalize);
+				Self::tidy_children(inner, is_block);
+			}
+			DocumentNode::DefList(pairs) => {
+				for (term, definition) in pairs {
+					term.iter_mut().for_each(DocumentNode::normalize);
+					definition.iter_mut().for_each(DocumentNode::normalize);
+					Self::tidy_children(term, true);
+					Self::tidy_children(definition, true);
+				}
+			}
+			DocumentNode::Figure { image, caption } => {
+				image.normalize();
+				caption.iter_mut().for_each(DocumentNode::normalize);
+				Self::tidy_children(caption, true);
+			}
+			_ => {}
+		}
+	}
+
+	fn tidy_children(nodes: &mut Vec<DocumentNode>, strip_ends: bool) {
+		nodes.retain(|node| {
+			!matches!(node, DocumentNode::Empty)
+				&& !matches!(node, DocumentNode::Div(inner) if inner.is_empty())
+		});
+		Self::strip_blank_text(nodes, strip_ends);
+		// Blank text between separators is gone by now, so they're next to each other.
+		nodes.dedup_by(|next, previous| {
+			matches!((previous, next), (DocumentNode::Sep, DocumentNode::Sep))
+		});
+	}
+
+	fn strip_blank_text(nodes: &mut Vec<DocumentNode>, strip_ends: bool) {
+		let len = nodes.len();
+		let keep: Vec<bool> = (0..len)
+			.map(|i| {
+				if !nodes[i].is_blank_text() {
+					return true;
+				}
+				let first = i == 0;
+				let last = i + 1 == len;
+				if strip_ends && (first || last) {
+					return false;
+				}
+				let block_before = !first && nodes[i - 1].is_block();
+				let block_after = !last && nodes[i + 1].is_block();
+				!(block_before || block_after)
+			})
+			.collect();
+		let mut keep = keep.into_iter();
+		nodes.retain(|_| keep.next().unwrap_or(true));
+	}
+
+	/// Cancels any media downloads in this tree which are still in progress.
+	pub fn cancel(&mut self, rt: &Runtime) {
+		self.visit_mut(&mut |node| match node {
+			DocumentNode::Video { fetched, .. } => rt.block_on(fetched.cancel()),
+			DocumentNode::Audio { fetched, .. } => rt.block_on(fetched.cancel()),
+			_ => {}
+		});
+	}
 }
 
-impl ToString for DocumentNode {
-	fn to_string(&self) -> String {
+impl Display for DocumentNode {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
 		match self {
-			DocumentNode::TextLeaf(text) => text.to_string(),
-			DocumentNode::Root(inner) => Self::many_to_string(inner.iter(), "\n"),
+			DocumentNode::TextLeaf(text) => f.write_str(text),
+			DocumentNode::Root(inner) => f.write_str(&Self::many_to_string(inner.iter(), "\n")),
+			DocumentNode::LineBreak => f.write_str("\n"),
 			DocumentNode::Div(inner)
+			| DocumentNode::Paragraph(inner)
+			| DocumentNode::Heading { inner, .. }
 			| DocumentNode::Span(inner)
 			| DocumentNode::Emph(inner)
-			| DocumentNode::Strong(inner) => Self::many_to_string(inner.iter(), " "),
-			DocumentNode::Link { url: _, mime: _, label } => Self::many_to_string(label.iter(), " "),
+			| DocumentNode::Strong(inner)
+			| DocumentNode::Underline(inner) => {
+				f.write_str(&Self::many_to_string(inner.iter(), " "))
+			}
+			DocumentNode::Strike(inner) => {
+				write!(f, "~~{}~~", Self::many_to_string(inner.iter(), " "))
+			}
+			DocumentNode::Superscript(inner) => {
+				write!(f, "^{}", Self::many_to_string(inner.iter(), " "))
+			}
+			DocumentNode::Subscript(inner) => {
+				write!(f, "_{}", Self::many_to_string(inner.iter(), " "))
+			}
+			DocumentNode::Link { label, .. } => {
+				f.write_str(&Self::many_to_string(label.iter(), " "))
+			}
+			DocumentNode::DefList(pairs) => {
+				let lines: Vec<_> = pairs
+					.iter()
+					.map(|(term, definition)| {
+						format!(
+							"{}: {}",
+							Self::many_to_string(term.iter(), " "),
+							Self::many_to_string(definition.iter(), " ")
+						)
+					})
+					.collect();
+				f.write_str(&lines.join("\n"))
+			}
 			DocumentNode::Image { label, url: _ }
-			| DocumentNode::Video { label, fetched: _ }
-			| DocumentNode::Audio { label, fetched: _ } => label.to_string(),
-			_ => "???".to_string(),
+			| DocumentNode::Video { label, .. }
+			| DocumentNode::Audio { label, .. } => f.write_str(label),
+			DocumentNode::Figure { image, caption } if caption.is_empty() => image.fmt(f),
+			DocumentNode::Figure { image, caption } => {
+				write!(f, "{image}\n{}", Self::many_to_string(caption.iter(), " "))
+			}
+			DocumentNode::Math { source, .. } => write!(f, "${source}$"),
+			_ => f.write_str("???"),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{iframe_to_node, DocumentNode};
+	use html_parser::Dom;
+
+	#[cfg(feature = "gui")]
+	#[test]
+	fn detects_image_loaders() {
+		use super::image_loaders_installed;
+		use eframe::egui::Context;
+
+		let ctx = Context::default();
+		assert!(!image_loaders_installed(&ctx));
+		egui_extras::install_image_loaders(&ctx);
+		assert!(image_loaders_installed(&ctx));
+	}
+
+	fn depth(node: &DocumentNode) -> usize {
+		match node {
+			DocumentNode::Root(inner)
+			| DocumentNode::Div(inner)
+			| DocumentNode::Span(inner)
+			| DocumentNode::Unk(inner)
+			| DocumentNode::Paragraph(inner)
+			| DocumentNode::Heading { inner, .. }
+			| DocumentNode::UList(inner)
+			| DocumentNode::OList { items: inner, .. }
+			| DocumentNode::Emph(inner)
+			| DocumentNode::Strong(inner)
+			| DocumentNode::Strike(inner)
+			| DocumentNode::Underline(inner)
+			| DocumentNode::Superscript(inner)
+			| DocumentNode::Subscript(inner) => 1 + inner.iter().map(depth).max().unwrap_or(0),
+			_ => 1,
+		}
+	}
+
+	#[test]
+	fn flattens_deep_nesting() {
+		let html = format!("{}deep{}", "<div>".repeat(10_000), "</div>".repeat(10_000));
+		let tree = DocumentNode::from_html(&html);
+		// Plus one each for the root and the text leaf.
+		assert!(depth(&tree) <= super::MAX_DEPTH + 2);
+		assert!(tree.to_string().contains("deep"));
+	}
+
+	#[test]
+	fn strips_whitespace_between_blocks() {
+		let text = |t: &str| DocumentNode::TextLeaf(t.to_string());
+		let mut tree = DocumentNode::Root(vec![
+			text("\n"),
+			DocumentNode::Div(vec![text("One")]),
+			text("\n  "),
+			DocumentNode::Div(vec![
+				DocumentNode::Span(vec![text("Two")]),
+				text(" "),
+				DocumentNode::Span(vec![text("Three")]),
+			]),
+			text(" "),
+		]);
+		tree.normalize();
+		let DocumentNode::Root(root) = &tree else {
+			unreachable!();
+		};
+		assert_eq!(root.len(), 2);
+		let DocumentNode::Div(second) = &root[1] else {
+			panic!("Expected a div");
+		};
+		assert_eq!(second.len(), 3);
+		assert!(matches!(&second[1], DocumentNode::TextLeaf(t) if t == " "));
+	}
+
+	#[test]
+	fn collapses_separators_and_empty_divs() {
+		let mut tree = DocumentNode::from_html(
+			"<p>Before</p>\n<hr>\n<hr/>\n<div> </div><!-- gap --><hr>\n<p>After</p><hr><div></div>",
+		);
+		tree.normalize();
+		let DocumentNode::Root(root) = &tree else {
+			panic!("Expected a root");
+		};
+		let kinds: Vec<&str> = root
+			.iter()
+			.map(|node| match node {
+				DocumentNode::Sep => "sep",
+				DocumentNode::Paragraph(_) => "p",
+				_ => "other",
+			})
+			.collect();
+		assert_eq!(kinds, ["p", "sep", "p", "sep"]);
+	}
+
+	#[test]
+	fn classifies_inline_nodes() {
+		let text = || DocumentNode::TextLeaf("text".to_string());
+		assert!(text().is_inline());
+		assert!(DocumentNode::Emph(vec![text(), DocumentNode::Strong(vec![text()])]).is_inline());
+		assert!(!DocumentNode::Emph(vec![text(), DocumentNode::Div(vec![text()])]).is_inline());
+		assert!(!DocumentNode::Sep.is_inline());
+
+		let DocumentNode::Root(root) =
+			DocumentNode::from_html("<p>Some <code>code</code> and <a href=\"#\">a link</a></p>")
+		else {
+			panic!("Expected a root");
+		};
+		assert!(root[0].is_block());
+		let DocumentNode::Paragraph(paragraph) = &root[0] else {
+			panic!("Expected a paragraph");
+		};
+		assert!(paragraph.iter().all(DocumentNode::is_inline));
+	}
+
+	#[test]
+	fn links_iframe_embeds() {
+		let link = |node| match node {
+			DocumentNode::Link { url, label, .. } => {
+				(url, label.iter().map(ToString::to_string).collect::<String>())
+			}
+			_ => panic!("Expected a link"),
+		};
+		let (url, label) =
+			link(iframe_to_node("https://www.youtube.com/embed/dQw4w9WgXcQ?start=42&rel=0"));
+		assert_eq!(url, "https://www.youtube.com/watch?v=dQw4w9WgXcQ&t=42");
+		assert_eq!(label, "YouTube video");
+		let (url, _) = link(iframe_to_node("//player.vimeo.com/video/76979871?h=8272103f6e"));
+		assert_eq!(url, "https://vimeo.com/76979871");
+
+		let (url, label) = link(iframe_to_node(" https://maps.example/embed?q=Bern "));
+		assert_eq!(url, "https://maps.example/embed?q=Bern");
+		assert_eq!(label, "Embedded content (open externally)");
+		// Not a player, just a YouTube page.
+		let (url, _) = link(iframe_to_node("https://www.youtube.com/about/"));
+		assert_eq!(url, "https://www.youtube.com/about/");
+
+		let tree = DocumentNode::from_html(concat!(
+			r#"<p><iframe src="https://www.youtube-nocookie.com/embed/abc"></iframe>"#,
+			"<iframe></iframe></p>",
+		));
+		let mut urls = vec![];
+		tree.visit(&mut |node| {
+			if let DocumentNode::Link { url, .. } = node {
+				urls.push(url.clone());
+			}
+		});
+		assert_eq!(urls, ["https://www.youtube.com/watch?v=abc"]);
+	}
+
+	#[test]
+	fn links_video_pages() {
+		use super::video_link;
+
+		for href in [
+			"https://www.youtube.com/watch?v=dQw4w9WgXcQ",
+			"https://youtu.be/dQw4w9WgXcQ",
+			"https://vimeo.com/76979871",
+		] {
+			let Some(DocumentNode::Link { url, label, .. }) = video_link(href) else {
+				panic!("Expected a link to {href}");
+			};
+			assert_eq!(url, href);
+			assert!(label[0].to_string().ends_with(" video"), "{href}");
 		}
+		assert!(video_link("https://www.youtube.com/watch").is_none());
+		assert!(video_link("https://vimeo.com/about").is_none());
+		assert!(video_link("https://example.com/watch?v=1").is_none());
+	}
+
+	#[test]
+	fn keeps_mathml_source() {
+		let math = concat!(
+			r#"<math display="block">"#,
+			"<msup><mi>e</mi><mrow><mi>i</mi><mi>&#960;</mi></mrow></msup>",
+			"<mo>+</mo><mn>1</mn><mo>=</mo><mn>0</mn></math>",
+		);
+		let tree = DocumentNode::from_html(&format!("<p>Euler's identity: {math}</p>"));
+		let mut found = vec![];
+		tree.visit(&mut |node| {
+			if let DocumentNode::Math { source, text } = node {
+				found.push((source.clone(), text.clone()));
+			}
+		});
+		assert_eq!(found, [(math.to_string(), "e^(iπ)+1=0".to_string())]);
+		let DocumentNode::Root(root) = &tree else {
+			panic!("Expected a root");
+		};
+		let DocumentNode::Paragraph(paragraph) = &root[0] else {
+			panic!("Expected a paragraph");
+		};
+		assert_eq!(paragraph[1].to_string(), format!("${math}$"));
+		assert!(paragraph[1].is_block());
+
+		// TeX is more readable than anything made up from the MathML.
+		let tree = DocumentNode::from_html(concat!(
+			"<math><semantics><mfrac><mn>1</mn><mn>2</mn></mfrac>",
+			r#"<annotation encoding="application/x-tex">\frac{1}{2}</annotation>"#,
+			"</semantics></math>",
+		));
+		let DocumentNode::Root(root) = &tree else {
+			panic!("Expected a root");
+		};
+		assert!(matches!(&root[0], DocumentNode::Math { text, .. } if text == "\\frac{1}{2}"));
+		assert!(root[0].is_inline());
+	}
+
+	#[test]
+	fn classifies_links() {
+		use super::{classify_link, LinkKind};
+		assert_eq!(classify_link("https://example.com/#top"), LinkKind::Web);
+		assert_eq!(classify_link("HTTP://example.com"), LinkKind::Web);
+		assert_eq!(classify_link("mailto:me@example.com"), LinkKind::Mail);
+		assert_eq!(classify_link("tel:+15555555555"), LinkKind::Phone);
+		assert_eq!(classify_link("#section-2"), LinkKind::Fragment);
+		assert_eq!(classify_link("about:blank"), LinkKind::Other);
+		assert_eq!(classify_link("javascript:alert(1)"), LinkKind::Other);
+	}
+
+	#[test]
+	fn parses_definition_lists() {
+		let dom = Dom::parse("<dl><dt>Rust</dt><dd>A language</dd>\n<dt>Winter</dt><dd>A reader</dd></dl>")
+			.unwrap();
+		let DocumentNode::Root(root) = DocumentNode::from(dom) else {
+			panic!("Expected a root");
+		};
+		let DocumentNode::DefList(pairs) = &root[0] else {
+			panic!("Expected a definition list");
+		};
+		assert_eq!(pairs.len(), 2);
+		assert_eq!(root[0].to_string(), "Rust: A language\nWinter: A reader");
+	}
+
+	#[test]
+	fn formats_list_markers() {
+		use super::{list_marker, ListStyle};
+
+		assert_eq!(list_marker(1, ListStyle::Decimal), "1");
+		assert_eq!(list_marker(0, ListStyle::Decimal), "0");
+		assert_eq!(list_marker(42, ListStyle::Decimal), "42");
+		assert_eq!(list_marker(1, ListStyle::LowerAlpha), "a");
+		assert_eq!(list_marker(26, ListStyle::LowerAlpha), "z");
+		assert_eq!(list_marker(27, ListStyle::LowerAlpha), "aa");
+		assert_eq!(list_marker(703, ListStyle::LowerAlpha), "aaa");
+		assert_eq!(list_marker(3, ListStyle::UpperAlpha), "C");
+		assert_eq!(list_marker(28, ListStyle::UpperAlpha), "AB");
+		assert_eq!(list_marker(4, ListStyle::LowerRoman), "iv");
+		assert_eq!(list_marker(9, ListStyle::UpperRoman), "IX");
+		assert_eq!(list_marker(1994, ListStyle::UpperRoman), "MCMXCIV");
+		assert_eq!(list_marker(0, ListStyle::LowerAlpha), "0");
+		assert_eq!(list_marker(4000, ListStyle::UpperRoman), "4000");
+	}
+
+	#[test]
+	fn reads_ordered_list_attributes() {
+		use super::ListStyle;
+
+		let tree = DocumentNode::from_html(r#"<ol start="3" type="i"><li>c</li></ol><ol><li>a</li></ol>"#);
+		let DocumentNode::Root(lists) = &tree else {
+			panic!("Expected a root");
+		};
+		assert!(matches!(
+			lists[0],
+			DocumentNode::OList { start: 3, style: ListStyle::LowerRoman, .. }
+		));
+		assert!(matches!(
+			lists[1],
+			DocumentNode::OList { start: 1, style: ListStyle::Decimal, .. }
+		));
+		assert_eq!(tree.to_html(), r#"<ol start="3" type="i"><li>c</li></ol><ol><li>a</li></ol>"#);
+	}
+
+	#[test]
+	fn parses_figures() {
+		let mut tree = DocumentNode::from_html(
+			"<figure>\n<img src=\"https://example.com/cat.png\" alt=\"A cat\">\n<figcaption>My <em>cat</em></figcaption>\n</figure>",
+		);
+		tree.normalize();
+		let DocumentNode::Root(root) = &tree else {
+			panic!("Expected a root");
+		};
+		let DocumentNode::Figure { image, caption } = &root[0] else {
+			panic!("Expected a figure");
+		};
+		assert!(matches!(&**image, DocumentNode::Image { url, .. } if url == "https://example.com/cat.png"));
+		assert_eq!(DocumentNode::many_to_string(caption.iter(), ""), "My cat");
+		// The caption comes after the image's label.
+		assert!(tree.to_string().starts_with("A cat\nMy"));
+
+		let gallery = DocumentNode::from_html(
+			"<figure><img src=\"a.png\" alt=\"A\"><img src=\"b.png\" alt=\"B\"></figure>",
+		);
+		let DocumentNode::Root(root) = &gallery else {
+			panic!("Expected a root");
+		};
+		let DocumentNode::Figure { image, caption } = &root[0] else {
+			panic!("Expected a figure");
+		};
+		assert!(matches!(&**image, DocumentNode::Div(images) if images.len() == 2));
+		assert!(caption.is_empty());
+		assert_eq!(gallery.to_string(), "A B");
+	}
+
+	#[test]
+	fn visits_every_node() {
+		let mut tree = DocumentNode::Root(vec![
+			DocumentNode::Div(vec![
+				DocumentNode::TextLeaf("Hello".to_string()),
+				DocumentNode::Link {
+					url: "https://example.com".to_string(),
+					mime: "text/html".to_string(),
+					label: vec![DocumentNode::TextLeaf("there".to_string())],
+					rel: vec![],
+					title: None,
+				},
+			]),
+			DocumentNode::DefList(vec![(
+				vec![DocumentNode::TextLeaf("Term".to_string())],
+				vec![DocumentNode::Strong(vec![DocumentNode::TextLeaf("Definition".to_string())])],
+			)]),
+			DocumentNode::Figure {
+				image: Box::new(DocumentNode::Image {
+					label: "Cat".to_string(),
+					url: "https://example.com/cat.png".to_string(),
+				}),
+				caption: vec![DocumentNode::TextLeaf("A cat".to_string())],
+			},
+		]);
+		let mut leaves = 0;
+		let mut nodes = 0;
+		tree.visit(&mut |node| {
+			nodes += 1;
+			if matches!(node, DocumentNode::TextLeaf(_)) {
+				leaves += 1;
+			}
+		});
+		assert_eq!(leaves, 5);
+		assert_eq!(nodes, 12);
+
+		tree.visit_mut(&mut |node| {
+			if let DocumentNode::TextLeaf(text) = node {
+				*text = text.to_uppercase();
+			}
+		});
+		let mut text = vec![];
+		tree.visit(&mut |node| {
+			if let DocumentNode::TextLeaf(leaf) = node {
+				text.push(leaf.clone());
+			}
+		});
+		assert_eq!(text, ["HELLO", "THERE", "TERM", "DEFINITION", "A CAT"]);
+	}
+
+	#[test]
+	fn keeps_link_rel_and_title() {
+		let tree = DocumentNode::from_html(
+			"<div><a href=\"https://example.com\" rel=\"Sponsored  nofollow\" title=\" Ad \">x</a></div>",
+		);
+		let mut found = None;
+		tree.visit(&mut |node| {
+			if let DocumentNode::Link { rel, title, .. } = node {
+				found = Some((rel.clone(), title.clone()));
+			}
+		});
+		assert_eq!(
+			found,
+			Some((
+				vec!["sponsored".to_string(), "nofollow".to_string()],
+				Some("Ad".to_string())
+			))
+		);
+	}
+
+	#[test]
+	fn parses_inline_formatting() {
+		let tree = DocumentNode::from_html(
+			"<div><del>a</del><s>b</s><u>c</u><ins>d</ins>x<sup>2</sup>H<sub>2</sub>O</div>",
+		);
+		let DocumentNode::Root(root) = &tree else {
+			panic!("Expected a root");
+		};
+		let DocumentNode::Div(div) = &root[0] else {
+			panic!("Expected a div");
+		};
+		assert!(matches!(
+			div.as_slice(),
+			[
+				DocumentNode::Strike(_),
+				DocumentNode::Strike(_),
+				DocumentNode::Underline(_),
+				DocumentNode::Underline(_),
+				DocumentNode::TextLeaf(_),
+				DocumentNode::Superscript(_),
+				DocumentNode::TextLeaf(_),
+				DocumentNode::Subscript(_),
+				DocumentNode::TextLeaf(_),
+			]
+		));
+		assert_eq!(tree.to_string(), "~~a~~ ~~b~~ c d x ^2 H _2 O");
+	}
+
+	#[test]
+	fn only_minimizes_for_web_links_when_asked() {
+		use super::{LinkClick, LinkKind};
+
+		assert!(!LinkClick::Open.minimizes(LinkKind::Web, false));
+		assert!(LinkClick::OpenAndMinimize.minimizes(LinkKind::Web, false));
+		assert!(!LinkClick::OpenAndMinimize.minimizes(LinkKind::Web, true));
+		assert!(!LinkClick::OpenAndMinimize.minimizes(LinkKind::Mail, false));
+		assert_eq!(LinkClick::default(), LinkClick::Open);
+	}
+
+	#[test]
+	fn decodes_entities_in_text() {
+		let tree = DocumentNode::from_html("<div>Tom &amp; Jerry&#8217;s &amp;lt;show&amp;gt;</div>");
+		assert_eq!(tree.to_string(), "Tom & Jerry’s &lt;show&gt;");
+	}
+
+	#[test]
+	fn media_handler_decides_presentation() {
+		use super::media::{MaybeLoaded, MediaHandler, MediaKind, MediaPresentation};
+		use std::cell::RefCell;
+
+		#[derive(Default)]
+		struct StreamEverything(RefCell<Vec<(MediaKind, String, String)>>);
+		impl MediaHandler for StreamEverything {
+			fn presentation(&self, kind: MediaKind, url: &str, mime: &str) -> MediaPresentation {
+				self.0
+					.borrow_mut()
+					.push((kind, url.to_string(), mime.to_string()));
+				MediaPresentation::Stream
+			}
+		}
+
+		let handler = StreamEverything::default();
+		let rt = tokio::runtime::Runtime::new().unwrap();
+		let mut tree = DocumentNode::from_html(
+			"<div><a type=\"video/mp4\" href=\"https://example.com/clip.mp4\">clip</a></div>",
+		);
+		tree.tick(&rt, &handler);
+		assert_eq!(
+			handler.0.take(),
+			[(
+				MediaKind::Video,
+				"https://example.com/clip.mp4".to_string(),
+				"video/mp4".to_string()
+			)]
+		);
+		// Streamed media is left for the external app to fetch.
+		let DocumentNode::Root(root) = &tree else {
+			panic!("Expected a root");
+		};
+		let DocumentNode::Div(div) = &root[0] else {
+			panic!("Expected a div");
+		};
+		assert!(matches!(
+			&div[0],
+			DocumentNode::Video {
+				fetched: MaybeLoaded::NotStarted(_),
+				..
+			}
+		));
+	}
+
+	#[test]
+	fn waits_to_load_media_when_not_autoloading() {
+		use super::media::{Autoload, DownloadAndOpen, MaybeLoaded};
+		use crate::fetch::mock::{MockResponse, MockServer};
+
+		let handler = Autoload {
+			handler: DownloadAndOpen,
+			media: false,
+			images: false,
+		};
+		let rt = tokio::runtime::Runtime::new().unwrap();
+		let server = rt.block_on(MockServer::start(|_| MockResponse::ok("episode")));
+		let mut node = DocumentNode::Audio {
+			label: "Episode".to_string(),
+			mime: "audio/mpeg".to_string(),
+			fetched: MaybeLoaded::NotStarted(server.url("/episode.mp3")),
+			requested: false,
+		};
+		node.tick(&rt, &handler);
+		assert!(matches!(
+			node,
+			DocumentNode::Audio {
+				fetched: MaybeLoaded::NotStarted(_),
+				..
+			}
+		));
+
+		// Clicking "Click to load" starts the download on the next tick.
+		if let DocumentNode::Audio { requested, .. } = &mut node {
+			*requested = true;
+		}
+		node.tick(&rt, &handler);
+		assert!(matches!(
+			node,
+			DocumentNode::Audio {
+				fetched: MaybeLoaded::Working(_),
+				..
+			}
+		));
+		node.cancel(&rt);
+	}
+
+	#[test]
+	fn round_trips_through_serde() {
+		use super::media::MaybeLoaded;
+
+		let tree = DocumentNode::from_html(concat!(
+			"<h1>Title</h1>",
+			"<ul><li>One</li><li><a href=\"https://example.com\">Two</a></li></ul>",
+			"<ol type=\"a\" start=\"3\"><li>Three</li></ol>",
+			"<img src=\"https://example.com/cat.png\" alt=\"Cat\">",
+		));
+		let json = serde_json::to_string(&tree).unwrap();
+		let back: DocumentNode = serde_json::from_str(&json).unwrap();
+		assert_eq!(serde_json::to_string(&back).unwrap(), json);
+		assert_eq!(back.to_markdown(), tree.to_markdown());
+
+		// Media only keeps its URL.
+		let node = DocumentNode::Video {
+			label: "Clip".to_string(),
+			mime: "video/mp4".to_string(),
+			fetched: MaybeLoaded::TooLarge("https://example.com/clip.mp4".to_string(), 5),
+			requested: true,
+		};
+		let json = serde_json::to_string(&node).unwrap();
+		let back: DocumentNode = serde_json::from_str(&json).unwrap();
+		let DocumentNode::Video {
+			fetched: MaybeLoaded::NotStarted(url),
+			requested: false,
+			..
+		} = back
+		else {
+			panic!("Expected a video which hasn't started loading");
+		};
+		assert_eq!(url, "https://example.com/clip.mp4");
 	}
 }
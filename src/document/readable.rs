@@ -0,0 +1,363 @@
+//! Pulling an article's text out of the web page it was published on, for feeds which only
+//! include a summary.
+
+use std::fmt::Display;
+
+use html_parser::{Dom, Element, Node};
+use thiserror::Error;
+
+use super::{limit_nesting, media::MaybeLoaded, truncate_html, DocumentNode};
+use crate::{
+	fetch::is_not_cached,
+	state::{max_body_len, read_response, RefreshError},
+	syndication::decode_feed,
+	FETCHER,
+};
+
+/// Elements which never hold the article itself.
+const BOILERPLATE_TAGS: &[&str] = &[
+	"nav", "header", "footer", "aside", "script", "style", "noscript", "form", "iframe", "button",
+	"svg", "template",
+];
+
+/// Words in a class or id which mark an element as something around the article, rather than
+/// the article.
+const BOILERPLATE_WORDS: &[&str] = &[
+	"nav",
+	"menu",
+	"footer",
+	"sidebar",
+	"comment",
+	"share",
+	"social",
+	"related",
+	"advert",
+	"promo",
+	"cookie",
+	"newsletter",
+	"breadcrumb",
+];
+
+/// Elements which can hold an article's paragraphs.
+const CONTAINERS: &[&str] = &["article", "main", "section", "div", "td", "body"];
+
+/// Elements whose text counts towards the container they're directly inside.
+const PARAGRAPHS: &[&str] = &["p", "pre", "blockquote", "h2", "h3", "ul", "ol", "figure"];
+
+/// An article's main content, as found by [`extract_readable`].
+pub struct ReadableArticle(pub DocumentNode);
+
+#[derive(Error, Debug)]
+pub enum ReadableError {
+	/// The page couldn't be parsed as HTML.
+	NotHtml,
+	/// Nothing on the page looked like an article.
+	NoContent,
+	/// The response was compressed, but couldn't be decompressed, for the given reason.
+	Undecodable(String),
+}
+
+impl Display for ReadableError {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			ReadableError::NotHtml => write!(f, "the page isn't HTML"),
+			ReadableError::NoContent => write!(f, "nothing on the page looks like an article"),
+			ReadableError::Undecodable(why) => write!(f, "couldn't decompress the page: {why}"),
+		}
+	}
+}
+
+impl TryFrom<Vec<u8>> for ReadableArticle {
+	type Error = ReadableError;
+
+	fn try_from(value: Vec<u8>) -> Result<Self, Self::Error> {
+		Self::from_bytes(&value, None)
+	}
+}
+
+impl ReadableArticle {
+	/// Finds the article in a page, in whatever encoding it's in. See [`decode_feed`].
+	///
+	/// # Errors
+	/// Fails if the page isn't HTML, or nothing in it looks like an article.
+	pub fn from_bytes(bytes: &[u8], charset: Option<&str>) -> Result<Self, ReadableError> {
+		let html = decode_feed(bytes, charset);
+		// Scripts and styles are dropped anyway, and are what trips the parser up most.
+		let html = strip_element(&strip_element(&html, "script"), "style");
+		let html = truncate_html(&html, max_body_len());
+		let dom = Dom::parse(&limit_nesting(&html)).map_err(|_| ReadableError::NotHtml)?;
+		match extract_readable(&dom) {
+			DocumentNode::Empty => Err(ReadableError::NoContent),
+			content => Ok(Self(content)),
+		}
+	}
+
+	/// Makes the article's links and images absolute, since pages usually link relative to
+	/// themselves.
+	pub fn resolve_urls(&mut self, page_url: &str) {
+		let Ok(base) = reqwest::Url::parse(page_url) else {
+			return;
+		};
+		self.0.visit_mut(&mut |node| {
+			if let DocumentNode::Link { url, .. } | DocumentNode::Image { url, .. } = node {
+				if let Ok(resolved) = base.join(url) {
+					*url = resolved.to_string();
+				}
+			}
+		});
+	}
+}
+
+/// Advances the download of a page, like [`MaybeLoaded::tick`], but decodes it in the charset
+/// its response gave, as when refreshing a feed.
+pub async fn tick_page(page: &mut MaybeLoaded<ReadableArticle>) {
+	match page {
+		MaybeLoaded::NotStarted(_) => page.tick().await,
+		MaybeLoaded::Working(url) => {
+			let url = url.clone();
+			if let Some(completion) = FETCHER.try_finish(&url).await {
+				*page = finish_page(url, completion).await;
+			}
+		}
+		_ => {}
+	}
+}
+
+/// Turns a finished download into the page's progress.
+async fn finish_page(
+	url: String,
+	completion: Result<reqwest::Response, reqwest_middleware::Error>,
+) -> MaybeLoaded<ReadableArticle> {
+	let response = match completion {
+		Ok(response) => response,
+		Err(e) if is_not_cached(&e) => return MaybeLoaded::NotCached(url),
+		Err(e) => return MaybeLoaded::Failed(url, e),
+	};
+	match read_response(response).await {
+		Ok((body, charset)) => {
+			let article = ReadableArticle::from_bytes(&body, charset.as_deref());
+			MaybeLoaded::Done(url, article)
+		}
+		Err(RefreshError::BadStatus(status)) => MaybeLoaded::BadStatus(status),
+		Err(RefreshError::RateLimited(_)) => MaybeLoaded::BadStatus(429),
+		Err(RefreshError::Feed(e)) => {
+			MaybeLoaded::Done(url, Err(ReadableError::Undecodable(e.to_string())))
+		}
+		Err(RefreshError::Body(e)) => MaybeLoaded::Failed(url, e.into()),
+		Err(RefreshError::Request(e)) => MaybeLoaded::Failed(url, e),
+		Err(RefreshError::Cancelled) => MaybeLoaded::NotStarted(url),
+	}
+}
+
+/// Finds the element holding the most paragraph text, going by a basic readability heuristic,
+/// and turns it into a document without the navigation, sidebars and such around it.
+/// Returns [`DocumentNode::Empty`] if there's no text at all.
+#[must_use]
+pub fn extract_readable(dom: &Dom) -> DocumentNode {
+	let mut best = None;
+	for node in &dom.children {
+		find_densest(node, &mut best);
+	}
+	let Some((_, element)) = best else {
+		return DocumentNode::Empty;
+	};
+	let mut element = element.clone();
+	strip_boilerplate(&mut element.children);
+	let mut content = DocumentNode::Root(vec![DocumentNode::from(element)]);
+	content.normalize();
+	content
+}
+
+fn is_boilerplate(element: &Element) -> bool {
+	let name = element.name.to_ascii_lowercase();
+	if BOILERPLATE_TAGS.contains(&name.as_str()) {
+		return true;
+	}
+	let role = element.attributes.get("role").cloned().flatten();
+	if matches!(role.as_deref(), Some("navigation" | "banner" | "contentinfo" | "complementary")) {
+		return true;
+	}
+	element
+		.classes
+		.iter()
+		.chain(element.id.as_ref())
+		.map(|name| name.to_ascii_lowercase())
+		.any(|name| BOILERPLATE_WORDS.iter().any(|word| name.contains(word)))
+}
+
+/// Keeps the highest-scoring container in `best`. Ties go to the one found first, which is
+/// the outermost.
+fn find_densest<'a>(node: &'a Node, best: &mut Option<(f32, &'a Element)>) {
+	let Node::Element(element) = node else {
+		return;
+	};
+	if is_boilerplate(element) {
+		return;
+	}
+	if CONTAINERS.contains(&element.name.to_ascii_lowercase().as_str()) {
+		let score = score(element);
+		if score > 0.0 && !best.is_some_and(|(best, _)| best >= score) {
+			*best = Some((score, element));
+		}
+	}
+	for child in &element.children {
+		find_densest(child, best);
+	}
+}
+
+/// How much paragraph text is directly inside `element`, discounted by how much of its text
+/// is links, which is what menus and link lists are made of.
+#[allow(clippy::cast_precision_loss)]
+fn score(element: &Element) -> f32 {
+	let paragraphs: usize = element
+		.children
+		.iter()
+		.map(|child| match child {
+			Node::Text(text) => text.trim().len(),
+			Node::Element(child)
+				if !is_boilerplate(child)
+					&& PARAGRAPHS.contains(&child.name.to_ascii_lowercase().as_str()) =>
+			{
+				text_len(&child.children, false)
+			}
+			_ => 0,
+		})
+		.sum();
+	let total = text_len(&element.children, false);
+	if total == 0 {
+		return 0.0;
+	}
+	let links = text_len(&element.children, true);
+	paragraphs as f32 * (1.0 - links as f32 / total as f32)
+}
+
+/// How much text is in `nodes`, leaving out boilerplate. If `in_links`, only text inside
+/// links is counted.
+fn text_len(nodes: &[Node], in_links: bool) -> usize {
+	nodes
+		.iter()
+		.map(|node| match node {
+			Node::Text(text) if !in_links => text.trim().len(),
+			Node::Element(element) if !is_boilerplate(element) => {
+				if in_links && element.name.eq_ignore_ascii_case("a") {
+					text_len(&element.children, false)
+				} else {
+					text_len(&element.children, in_links)
+				}
+			}
+			_ => 0,
+		})
+		.sum()
+}
+
+fn strip_boilerplate(nodes: &mut Vec<Node>) {
+	nodes.retain(|node| !matches!(node, Node::Element(element) if is_boilerplate(element)));
+	for node in nodes {
+		if let Node::Element(element) = node {
+			strip_boilerplate(&mut element.children);
+		}
+	}
+}
+
+/// Removes every `<tag>` element from `html`, along with everything inside it.
+fn strip_element(html: &str, tag: &str) -> String {
+	let lower = html.to_ascii_lowercase();
+	let open = format!("<{tag}");
+	let close = format!("</{tag}>");
+	let mut out = String::with_capacity(html.len());
+	let mut from = 0;
+	while let Some(start) = lower[from..].find(&open).map(|i| from + i) {
+		out.push_str(&html[from..start]);
+		from = lower[start..]
+			.find(&close)
+			.map_or(html.len(), |end| start + end + close.len());
+	}
+	out.push_str(&html[from..]);
+	out
+}
+
+#[cfg(test)]
+mod tests {
+	use html_parser::Dom;
+
+	use super::{extract_readable, ReadableArticle};
+	use crate::document::DocumentNode;
+
+	#[test]
+	fn extracts_article_text() {
+		let html = include_str!("../test-data/article.html");
+		let mut content = String::new();
+		extract_readable(&Dom::parse(html).unwrap()).visit(&mut |node| {
+			if let DocumentNode::TextLeaf(text) = node {
+				content.push_str(text);
+			}
+		});
+		assert!(content.contains("The first thing to know about sourdough"));
+		assert!(content.contains("patience is the main ingredient"));
+		let boilerplate = [
+			"Recipes",
+			"Trending",
+			"Subscribe to our newsletter",
+			"Share on Twitter",
+			"Great recipe",
+			"All rights reserved",
+		];
+		for boilerplate in boilerplate {
+			assert!(!content.contains(boilerplate), "{boilerplate} wasn't dropped");
+		}
+	}
+
+	#[test]
+	fn resolves_relative_urls() {
+		let html = r#"<html><body><script>if (a < b) {}</script><article>
+			<p>Plenty of text to make this the article, with a <a href="/more">link</a> in it.</p>
+			<p><img src="images/loaf.jpg" alt="A loaf"></p>
+		</article></body></html>"#;
+		let mut article = ReadableArticle::try_from(html.as_bytes().to_vec()).unwrap();
+		article.resolve_urls("https://example.com/posts/bread");
+		let mut urls = vec![];
+		article.0.visit(&mut |node| {
+			if let DocumentNode::Link { url, .. } | DocumentNode::Image { url, .. } = node {
+				urls.push(url.clone());
+			}
+		});
+		assert_eq!(urls, ["https://example.com/more", "https://example.com/posts/images/loaf.jpg"]);
+	}
+
+	#[tokio::test]
+	async fn decodes_pages_in_their_charset() {
+		use super::tick_page;
+		use crate::{
+			document::media::MaybeLoaded,
+			fetch::mock::{MockResponse, MockServer},
+		};
+
+		let (html, _, _) = encoding_rs::WINDOWS_1252.encode(
+			"<html><body><p>Crème brûlée, as made in every café.</p></body></html>",
+		);
+		let html = html.to_vec();
+		let server = MockServer::start(move |_| {
+			MockResponse::ok(html.clone())
+				.with_header("Content-Type", "text/html; charset=windows-1252")
+		})
+		.await;
+		let mut page = MaybeLoaded::NotStarted(server.url("/recipe"));
+		for _ in 0..500 {
+			tick_page(&mut page).await;
+			if !matches!(page, MaybeLoaded::NotStarted(_) | MaybeLoaded::Working(_)) {
+				break;
+			}
+			tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+		}
+		let MaybeLoaded::Done(_, Ok(article)) = page else {
+			panic!("Expected an article");
+		};
+		let mut content = String::new();
+		article.0.visit(&mut |node| {
+			if let DocumentNode::TextLeaf(text) = node {
+				content.push_str(text);
+			}
+		});
+		assert!(content.contains("Crème brûlée, as made in every café."), "{content}");
+	}
+}
@@ -0,0 +1,120 @@
+//! An error type which every error the library returns converts into, so callers like the
+//! CLI can gather them up with `?` and only handle one. Parsing a feed returns it directly;
+//! everything else returns its own part's error, which says more about what went wrong.
+
+use std::fmt::Display;
+
+use thiserror::Error;
+
+use crate::{
+	document::{
+		media::{AudioError, VideoError},
+		readable::ReadableError,
+	},
+	fetch::BodyError,
+	state::{ChannelFromBytesError, RefreshError},
+};
+
+/// What's shown when text isn't a feed, without the parsers' details.
+pub(crate) const NOT_A_FEED: &str = "Could not parse XML as Atom or RSS from input";
+
+#[derive(Error, Debug)]
+pub enum Error {
+	/// Text which should have been a feed is neither Atom nor RSS. Both parsers' complaints
+	/// are kept, but only RSS's is the source, since it's tried last.
+	Parse {
+		atom: atom_syndication::Error,
+		#[source]
+		rss: rss::Error,
+	},
+	/// A feed couldn't be downloaded, or what was downloaded couldn't be read.
+	Fetch(#[from] RefreshError),
+	/// A response's body couldn't be read.
+	Body(#[from] BodyError),
+	/// A document couldn't be decoded as a feed, or was a page linking to feeds instead.
+	Encoding(#[from] ChannelFromBytesError),
+	/// A web page had no article to show.
+	Readable(#[from] ReadableError),
+	Video(#[from] VideoError),
+	Audio(#[from] AudioError),
+	/// Read state being imported wasn't valid JSON.
+	ReadState(#[from] serde_json::Error),
+	Io(#[from] std::io::Error),
+}
+
+impl Display for Error {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			Error::Parse { rss, .. } => write!(f, "{NOT_A_FEED}: {rss}"),
+			Error::Fetch(e) => write!(f, "Couldn't fetch the feed: {e}"),
+			Error::Body(e) => write!(f, "Couldn't read the response: {e}"),
+			Error::Encoding(e) => write!(f, "Couldn't decode the feed: {e}"),
+			Error::Readable(e) => write!(f, "Couldn't find the article: {e}"),
+			Error::Video(e) => write!(f, "Couldn't load the video: {e}"),
+			Error::Audio(e) => write!(f, "Couldn't load the audio: {e}"),
+			Error::ReadState(e) => write!(f, "Couldn't read the read state: {e}"),
+			Error::Io(e) => write!(f, "{e}"),
+		}
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use std::{error::Error as _, io::ErrorKind, str::FromStr};
+
+	use super::Error;
+	use crate::{
+		document::{
+			media::{AudioError, VideoError},
+			readable::ReadableError,
+		},
+		fetch::BodyError,
+		state::{ChannelFromBytesError, RefreshError},
+		syndication::Feed,
+	};
+
+	#[test]
+	fn wraps_each_kind_of_error() {
+		let Err(parse) = Feed::from_str("<html></html>") else {
+			panic!("Expected a parse error");
+		};
+		assert!(matches!(parse, Error::Parse { .. }));
+		assert!(parse.to_string().starts_with("Could not parse XML as Atom or RSS"));
+		assert!(parse.source().is_some_and(|source| source.is::<rss::Error>()));
+
+		let wrapped = [
+			Error::from(RefreshError::BadStatus(404)),
+			Error::from(BodyError::TooLarge(10)),
+			Error::from(ChannelFromBytesError::Undecodable("truncated".to_string())),
+			Error::from(ReadableError::NoContent),
+			Error::from(VideoError::from(std::io::Error::from(ErrorKind::InvalidData))),
+			Error::from(AudioError::from(std::io::Error::from(ErrorKind::PermissionDenied))),
+			Error::from(serde_json::from_str::<()>("{").unwrap_err()),
+			Error::from(std::io::Error::from(ErrorKind::NotFound)),
+		];
+		let [fetch, body, encoding, readable, video, audio, read_state, io] = &wrapped;
+		assert!(matches!(fetch, Error::Fetch(RefreshError::BadStatus(404))));
+		assert!(matches!(body, Error::Body(BodyError::TooLarge(10))));
+		assert!(matches!(encoding, Error::Encoding(ChannelFromBytesError::Undecodable(_))));
+		assert!(matches!(readable, Error::Readable(ReadableError::NoContent)));
+		assert!(matches!(video, Error::Video(VideoError::IoError(_))));
+		assert!(matches!(audio, Error::Audio(AudioError::IoError(_))));
+		assert!(matches!(read_state, Error::ReadState(_)));
+		assert!(matches!(io, Error::Io(e) if e.kind() == ErrorKind::NotFound));
+
+		assert_eq!(fetch.to_string(), "Couldn't fetch the feed: the server answered 404");
+		assert_eq!(
+			readable.to_string(),
+			"Couldn't find the article: nothing on the page looks like an article"
+		);
+		assert_eq!(
+			body.to_string(),
+			"Couldn't read the response: it's over the limit of 10 bytes"
+		);
+		for error in &wrapped {
+			let source = error.source().expect("Wrapped errors are the source");
+			assert!(error.to_string().ends_with(&source.to_string()));
+		}
+		assert!(encoding.source().is_some_and(|source| source.is::<ChannelFromBytesError>()));
+	}
+}
@@ -1,37 +1,482 @@
-use serde::Deserialize;
+use std::collections::BTreeSet;
 
-#[derive(Deserialize, Debug)]
-#[serde(rename = "link")]
-pub struct Link {
-	pub(crate) r#type: String,
-	pub(crate) href: String,
+use html_parser::{Dom, Element, Node};
+
+use crate::{
+	document::{limit_nesting, media::MaybeLoaded},
+	fetch::is_not_cached,
+	state::{read_response, ChannelFromBytesError, RefreshError, WFeed},
+	FETCHER,
+};
+
+const FEED_TYPES: &[&str] = &["application/rss+xml", "application/atom+xml"];
+
+/// Words in a link's address or text which suggest it leads to a feed.
+const FEED_WORDS: &[&str] = &["rss", "atom", "feed", "feeds"];
+
+/// A feed advertised by an HTML page.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DiscoveredFeed {
+	pub href: String,
+	/// The feed's type, or empty if it's `guessed`.
+	pub mime: String,
+	pub title: Option<String>,
+	/// Whether this is only an ordinary link which looks like it leads to a feed, rather than
+	/// one the page says is a feed.
+	pub guessed: bool,
 }
 
-// Takes an HTML page, and returns all linked RSS feeds.
+// Takes an HTML page, and returns all linked RSS feeds. If it doesn't advertise any, links in
+// its body which look like feeds are guessed at instead.
 #[must_use]
-pub fn find_feed(from: &str) -> Vec<String> {
-	let link_ex = "<link[^>]*>";
-	let link_ex = regex::Regex::new(link_ex).expect("Bad link regex");
-	link_ex
-		.find_iter(from)
-		.map(|matched| format!("{}</link>", matched.as_str()))
-		.flat_map(|matched| serde_xml_rs::from_str::<Link>(&matched))
-		.filter(|link| link.r#type == "application/rss+xml")
-		.map(|link| link.href)
-		.collect()
+pub fn find_feed(from: &str) -> Vec<DiscoveredFeed> {
+	let Ok(dom) = Dom::parse(&limit_nesting(from)) else {
+		return vec![];
+	};
+	let mut found = vec![];
+	dom.children
+		.iter()
+		.for_each(|node| collect_feed_links(node, &mut found));
+	if found.is_empty() {
+		dom.children
+			.iter()
+			.for_each(|node| collect_feed_anchors(node, &mut found));
+	}
+	found
+}
+
+/// Collects `<link rel="alternate">` feed elements, skipping the page body.
+fn collect_feed_links(node: &Node, found: &mut Vec<DiscoveredFeed>) {
+	let Node::Element(element) = node else {
+		return;
+	};
+	match element.name.to_ascii_lowercase().as_str() {
+		"body" => {}
+		"link" => {
+			if let (Some(mime), Some(href)) = (feed_mime(element), attribute(element, "href")) {
+				found.push(DiscoveredFeed {
+					href: href.trim().to_string(),
+					mime,
+					title: attribute(element, "title")
+						.map(str::trim)
+						.filter(|title| !title.is_empty())
+						.map(ToString::to_string),
+					guessed: false,
+				});
+			}
+		}
+		_ => element
+			.children
+			.iter()
+			.for_each(|node| collect_feed_links(node, found)),
+	}
+}
+
+/// Collects `<a>` elements which look like they lead to a feed, going by their address or
+/// text.
+fn collect_feed_anchors(node: &Node, found: &mut Vec<DiscoveredFeed>) {
+	let Node::Element(element) = node else {
+		return;
+	};
+	if !element.name.eq_ignore_ascii_case("a") {
+		element
+			.children
+			.iter()
+			.for_each(|node| collect_feed_anchors(node, found));
+		return;
+	}
+	let Some(href) = attribute(element, "href").map(str::trim) else {
+		return;
+	};
+	let text = text_of(&element.children);
+	let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+	let looks_like_feed = !href.starts_with('#')
+		&& !href.to_ascii_lowercase().starts_with("mailto:")
+		&& (href_suggests_feed(href) || has_feed_word(&text));
+	if looks_like_feed && !found.iter().any(|feed| feed.href == href) {
+		found.push(DiscoveredFeed {
+			href: href.to_string(),
+			mime: String::new(),
+			title: (!text.is_empty()).then_some(text),
+			guessed: true,
+		});
+	}
+}
+
+/// Whether a link's path ends in `.xml` or the like, or has a part like `/rss/`.
+fn href_suggests_feed(href: &str) -> bool {
+	let path = href.split(['?', '#']).next().unwrap_or_default();
+	let path = path.to_ascii_lowercase();
+	[".xml", ".rss", ".atom"]
+		.iter()
+		.any(|extension| path.ends_with(extension))
+		|| path
+			.split('/')
+			.any(|part| FEED_WORDS.contains(&part))
+}
+
+fn has_feed_word(text: &str) -> bool {
+	text.split(|c: char| !c.is_alphanumeric())
+		.any(|word| FEED_WORDS.contains(&word.to_lowercase().as_str()))
+}
+
+fn text_of(nodes: &[Node]) -> String {
+	nodes
+		.iter()
+		.map(|node| match node {
+			Node::Text(text) => text.clone(),
+			Node::Element(element) => text_of(&element.children),
+			Node::Comment(_) => String::new(),
+		})
+		.collect::<Vec<_>>()
+		.join(" ")
+}
+
+/// The feed type of a `<link>` element, if it's an alternate feed link at all.
+fn feed_mime(element: &Element) -> Option<String> {
+	let is_alternate = attribute(element, "rel").is_some_and(|rel| {
+		rel.split_ascii_whitespace()
+			.any(|rel| rel.eq_ignore_ascii_case("alternate"))
+	});
+	let mime = attribute(element, "type")?.trim().to_ascii_lowercase();
+	(is_alternate && FEED_TYPES.contains(&mime.as_str())).then_some(mime)
+}
+
+/// Fetches whatever the user typed into the New Subscription box. If that's a web page which
+/// links to a single feed, that feed is fetched in its place.
+pub struct FeedLookup {
+	pub progress: MaybeLoaded<WFeed>,
+	/// Every URL fetched so far, so pages which link to themselves or each other can't send
+	/// the lookup around in circles.
+	visited: BTreeSet<String>,
+}
+
+impl FeedLookup {
+	/// Starts looking up `input`, assuming `https://` if it's just a host like `example.com`.
+	#[must_use]
+	pub fn new(input: &str) -> Self {
+		let input = input.trim();
+		let url = if input.contains("://") {
+			input.to_string()
+		} else {
+			format!("https://{input}")
+		};
+		Self {
+			visited: BTreeSet::from([url.clone()]),
+			progress: MaybeLoaded::NotStarted(url),
+		}
+	}
+
+	/// The URL being fetched, which is where the feed came from once it's done.
+	#[must_use]
+	pub fn url(&self) -> Option<&str> {
+		self.progress.url()
+	}
+
+	/// Advances the download. The body is decompressed and decoded in the charset its response
+	/// gave, as when refreshing, before it's parsed. A page linking to exactly one feed which
+	/// hasn't been tried yet is followed straight away. If it links to several, they're left in
+	/// [`ChannelFromBytesError::HTMLWithLink`] for [`FeedLookup::pick`], with their `href`s
+	/// made absolute.
+	pub async fn tick(&mut self) {
+		match &self.progress {
+			// Subscribing to a local file is up to the user, so it's allowed here.
+			MaybeLoaded::NotStarted(url) => {
+				let url = url.clone();
+				FETCHER.start_feed_download(&url, false).await;
+				self.progress = MaybeLoaded::Working(url);
+			}
+			MaybeLoaded::Working(url) => {
+				let url = url.clone();
+				if let Some(completion) = FETCHER.try_finish(&url).await {
+					self.progress = finish_lookup(url, completion).await;
+				}
+			}
+			_ => {}
+		}
+		let MaybeLoaded::Done(page, Err(ChannelFromBytesError::HTMLWithLink(found))) =
+			&mut self.progress
+		else {
+			return;
+		};
+		let base = reqwest::Url::parse(page).ok();
+		let mut fresh = vec![];
+		for mut feed in std::mem::take(found) {
+			// Feed links are often relative to the page.
+			if let Some(url) = base.as_ref().and_then(|base| base.join(&feed.href).ok()) {
+				feed.href = url.to_string();
+			}
+			if !self.visited.contains(&feed.href) && !fresh.contains(&feed) {
+				fresh.push(feed);
+			}
+		}
+		match fresh.as_slice() {
+			[] => {
+				let page = page.clone();
+				self.progress = MaybeLoaded::Done(
+					page,
+					Err(ChannelFromBytesError::BadFeed(
+						"This page only links to pages which were already tried",
+					)),
+				);
+			}
+			[only] => {
+				let href = only.href.clone();
+				self.pick(&href);
+			}
+			_ => *found = fresh,
+		}
+	}
+
+	/// Fetches one of the feeds a page linked to.
+	pub fn pick(&mut self, href: &str) {
+		self.visited.insert(href.to_string());
+		self.progress = MaybeLoaded::NotStarted(href.to_string());
+	}
+}
+
+/// Turns a finished download into the lookup's progress.
+async fn finish_lookup(
+	url: String,
+	completion: Result<reqwest::Response, reqwest_middleware::Error>,
+) -> MaybeLoaded<WFeed> {
+	let response = match completion {
+		Ok(response) => response,
+		Err(e) if is_not_cached(&e) => return MaybeLoaded::NotCached(url),
+		Err(e) => return MaybeLoaded::Failed(url, e),
+	};
+	match read_response(response).await {
+		Ok((body, charset)) => {
+			let feed = WFeed::from_bytes(&body, charset.as_deref());
+			MaybeLoaded::Done(url, feed)
+		}
+		Err(RefreshError::BadStatus(status)) => MaybeLoaded::BadStatus(status),
+		Err(RefreshError::RateLimited(_)) => MaybeLoaded::BadStatus(429),
+		Err(RefreshError::Feed(e)) => MaybeLoaded::Done(url, Err(e)),
+		Err(RefreshError::Body(e)) => MaybeLoaded::Failed(url, e.into()),
+		Err(RefreshError::Request(e)) => MaybeLoaded::Failed(url, e),
+		Err(RefreshError::Cancelled) => MaybeLoaded::NotStarted(url),
+	}
+}
+
+fn attribute<'a>(element: &'a Element, name: &str) -> Option<&'a str> {
+	element
+		.attributes
+		.iter()
+		.find(|(key, _)| key.eq_ignore_ascii_case(name))
+		.and_then(|(_, value)| value.as_deref())
 }
 
 #[cfg(test)]
 mod tests {
-	use super::find_feed;
+	use super::{find_feed, DiscoveredFeed, FeedLookup};
+	use crate::{document::media::MaybeLoaded, state::ChannelFromBytesError};
+
+	fn hrefs(found: &[DiscoveredFeed]) -> Vec<&str> {
+		found.iter().map(|feed| feed.href.as_str()).collect()
+	}
 
 	#[test]
 	fn finds_link_in_html() {
 		let yt_html = include_str!("./test-data/youtube.html");
 		let found = find_feed(yt_html);
 		assert_eq!(
-			found,
+			hrefs(&found),
 			["https://www.youtube.com/feeds/videos.xml?channel_id=UCBR8-60-B28hp2BmDPdntcQ"]
 		);
+		assert_eq!(found[0].title.as_deref(), Some("RSS"));
+	}
+
+	#[test]
+	fn finds_multi_line_link() {
+		let html = include_str!("./test-data/multi-line-link.html");
+		let found = find_feed(html);
+		assert_eq!(
+			hrefs(&found),
+			[
+				"https://example.com/feed.xml",
+				"https://example.com/atom.xml"
+			]
+		);
+	}
+
+	#[test]
+	fn finds_titled_feeds() {
+		let html = r#"<html><head>
+			<link rel="alternate" type="application/rss+xml" title="Main Feed" href="/feed/">
+			<link rel="alternate" type="application/atom+xml" title=" Comments " href="/comments/feed/">
+		</head></html>"#;
+		assert_eq!(
+			find_feed(html),
+			[
+				DiscoveredFeed {
+					href: "/feed/".to_string(),
+					mime: "application/rss+xml".to_string(),
+					title: Some("Main Feed".to_string()),
+					guessed: false,
+				},
+				DiscoveredFeed {
+					href: "/comments/feed/".to_string(),
+					mime: "application/atom+xml".to_string(),
+					title: Some("Comments".to_string()),
+					guessed: false,
+				},
+			]
+		);
+	}
+
+	#[test]
+	fn guesses_feeds_from_anchors() {
+		let html = r#"<html><head><title>Old site</title></head><body>
+			<a href="/about">About</a>
+			<a href="/feed.xml">Subscribe</a>
+			<a href="/news/">Latest <b>RSS</b></a>
+			<a href="mailto:rss@example.com">Mail the RSS team</a>
+		</body></html>"#;
+		let found = find_feed(html);
+		assert_eq!(hrefs(&found), ["/feed.xml", "/news/"]);
+		assert!(found.iter().all(|feed| feed.guessed));
+		assert_eq!(found[0].title.as_deref(), Some("Subscribe"));
+		assert_eq!(found[1].title.as_deref(), Some("Latest RSS"));
+
+		// Feeds the page advertises come first, without any guesses.
+		let html = r#"<html><head>
+			<link rel="alternate" type="application/rss+xml" href="/real.xml">
+		</head><body><a href="/feed.xml">Subscribe</a></body></html>"#;
+		let found = find_feed(html);
+		assert_eq!(hrefs(&found), ["/real.xml"]);
+		assert!(!found[0].guessed);
+	}
+
+	async fn finish(lookup: &mut FeedLookup) {
+		for _ in 0..500 {
+			lookup.tick().await;
+			if !matches!(
+				lookup.progress,
+				MaybeLoaded::NotStarted(_) | MaybeLoaded::Working(_)
+			) {
+				return;
+			}
+			tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+		}
+		panic!("Lookup never finished");
+	}
+
+	#[tokio::test]
+	async fn follows_a_page_to_its_feed() {
+		use crate::fetch::mock::{MockResponse, MockServer};
+
+		let server = MockServer::start(|request| match request.path.as_str() {
+			"/feed.xml" => MockResponse::ok(
+				r#"<rss version="2.0"><channel><title>Found</title><link>l</link><description>d</description></channel></rss>"#,
+			),
+			"/blog" => MockResponse::ok(
+				r#"<html><head><link rel="alternate" type="application/rss+xml" href="/feed.xml"></head></html>"#,
+			),
+			"/loop" => MockResponse::ok(
+				r#"<html><head><link rel="alternate" type="application/rss+xml" href="/loop"></head></html>"#,
+			),
+			_ => MockResponse::ok(
+				r#"<html><head>
+					<link rel="alternate" type="application/rss+xml" href="feed.xml">
+					<link rel="alternate" type="application/atom+xml" href="/loop">
+				</head></html>"#,
+			),
+		})
+		.await;
+
+		let mut lookup = FeedLookup::new(&server.url("/blog"));
+		finish(&mut lookup).await;
+		assert!(matches!(lookup.progress, MaybeLoaded::Done(_, Ok(_))));
+		assert_eq!(lookup.url(), Some(server.url("/feed.xml").as_str()));
+
+		// A page which links to itself stops rather than fetching itself forever.
+		let mut lookup = FeedLookup::new(&server.url("/loop"));
+		finish(&mut lookup).await;
+		assert!(matches!(
+			lookup.progress,
+			MaybeLoaded::Done(_, Err(ChannelFromBytesError::BadFeed(_)))
+		));
+
+		// Several feeds are left to be picked from, with absolute links.
+		let mut lookup = FeedLookup::new(&server.url("/"));
+		finish(&mut lookup).await;
+		let MaybeLoaded::Done(_, Err(ChannelFromBytesError::HTMLWithLink(found))) =
+			&lookup.progress
+		else {
+			panic!("Expected a choice of feeds");
+		};
+		assert_eq!(hrefs(found), [server.url("/feed.xml"), server.url("/loop")]);
+		lookup.pick(&server.url("/loop"));
+		finish(&mut lookup).await;
+		assert!(matches!(
+			lookup.progress,
+			MaybeLoaded::Done(_, Err(ChannelFromBytesError::BadFeed(_)))
+		));
+	}
+
+	#[tokio::test]
+	async fn subscribes_to_gzipped_windows_1252_feeds() {
+		use std::io::Write;
+
+		use crate::{
+			fetch::mock::{MockResponse, MockServer},
+			state::Database,
+			syndication::Feed,
+		};
+
+		let (feed, _, _) = encoding_rs::WINDOWS_1252.encode(
+			r#"<?xml version="1.0"?>
+			<rss version="2.0"><channel><title>Café</title><link>l</link><description>d</description>
+				<item><title>Crème brûlée</title><guid>one</guid></item>
+			</channel></rss>"#,
+		);
+		let mut gzip = flate2::write::GzEncoder::new(vec![], flate2::Compression::default());
+		gzip.write_all(&feed).unwrap();
+		let gzipped = gzip.finish().unwrap();
+		let server = MockServer::start(move |request| match request.path.as_str() {
+			"/feed.xml" => MockResponse::ok(gzipped.clone())
+				.with_header("Content-Type", "application/rss+xml; charset=windows-1252")
+				.with_header("Content-Encoding", "gzip"),
+			_ => MockResponse::ok(vec![0x1f, 0x8b, 0, 1, 2, 3])
+				.with_header("Content-Encoding", "gzip"),
+		})
+		.await;
+
+		let mut lookup = FeedLookup::new(&server.url("/feed.xml"));
+		finish(&mut lookup).await;
+		let MaybeLoaded::Done(url, Ok(feed)) = &lookup.progress else {
+			panic!("Expected a feed, got {:?}", lookup.progress.url());
+		};
+		let Feed::RSS(channel) = &feed.0 else {
+			panic!("Expected an RSS feed");
+		};
+		assert_eq!(channel.title(), "Café");
+		let tmp = tempdir::TempDir::new("winter_lookup_test").unwrap();
+		let db = Database::from_dir(tmp.path().to_path_buf());
+		db.subscribe(url, &feed.0).await;
+		let Feed::RSS(channel) = &*db.get_subscription(url).await.unwrap() else {
+			panic!("Expected an RSS feed");
+		};
+		assert_eq!(channel.items()[0].title(), Some("Crème brûlée"));
+
+		// A body which can't be decompressed isn't mistaken for one that isn't a feed.
+		let mut lookup = FeedLookup::new(&server.url("/broken.xml"));
+		finish(&mut lookup).await;
+		assert!(matches!(
+			lookup.progress,
+			MaybeLoaded::Done(_, Err(ChannelFromBytesError::Undecodable(_)))
+		));
+		std::mem::drop(db);
+		std::mem::drop(tmp);
+	}
+
+	#[test]
+	fn assumes_https_for_bare_hosts() {
+		assert_eq!(FeedLookup::new(" example.com ").url(), Some("https://example.com"));
+		assert_eq!(
+			FeedLookup::new("http://example.com/feed").url(),
+			Some("http://example.com/feed")
+		);
 	}
 }
@@ -0,0 +1,234 @@
+//! A tiny HTTP/1.1 server for exercising the fetcher in tests without touching the network,
+//! and a middleware which does the same without even a socket.
+
+use std::{
+	net::SocketAddr,
+	sync::{
+		atomic::{AtomicUsize, Ordering},
+		Arc,
+	},
+	time::Duration,
+};
+
+use reqwest::{Request, Response};
+use reqwest_middleware::{ClientBuilder, ClientWithMiddleware, Middleware, Next};
+use task_local_extensions::Extensions;
+use tokio::{
+	io::{AsyncReadExt, AsyncWriteExt},
+	net::{TcpListener, TcpStream},
+	task::JoinHandle,
+};
+
+pub(crate) struct MockRequest {
+	pub path: String,
+	pub headers: Vec<(String, String)>,
+}
+
+impl MockRequest {
+	pub fn header(&self, name: &str) -> Option<&str> {
+		self.headers
+			.iter()
+			.find(|(k, _)| k.eq_ignore_ascii_case(name))
+			.map(|(_, v)| v.as_str())
+	}
+}
+
+pub(crate) struct MockResponse {
+	pub status: u16,
+	pub headers: Vec<(String, String)>,
+	pub body: Vec<u8>,
+	pub delay: Duration,
+}
+
+impl MockResponse {
+	pub fn ok(body: impl Into<Vec<u8>>) -> Self {
+		Self {
+			status: 200,
+			headers: vec![],
+			body: body.into(),
+			delay: Duration::ZERO,
+		}
+	}
+
+	pub fn status(status: u16) -> Self {
+		Self {
+			status,
+			headers: vec![],
+			body: vec![],
+			delay: Duration::ZERO,
+		}
+	}
+
+	pub fn with_delay(mut self, delay: Duration) -> Self {
+		self.delay = delay;
+		self
+	}
+
+	pub fn with_header(mut self, name: &str, value: &str) -> Self {
+		self.headers.push((name.to_string(), value.to_string()));
+		self
+	}
+}
+
+pub(crate) struct MockServer {
+	addr: SocketAddr,
+	task: JoinHandle<()>,
+	max_in_flight: Arc<AtomicUsize>,
+}
+
+impl MockServer {
+	pub async fn start<F>(handler: F) -> Self
+	where
+		F: Fn(&MockRequest) -> MockResponse + Send + Sync + 'static,
+	{
+		let listener = TcpListener::bind("127.0.0.1:0")
+			.await
+			.expect("Couldn't bind mock server");
+		let addr = listener.local_addr().expect("Mock server has no address");
+		let handler = Arc::new(handler);
+		let in_flight = Arc::new(AtomicUsize::new(0));
+		let max_in_flight = Arc::new(AtomicUsize::new(0));
+		let task = tokio::spawn({
+			let max_in_flight = max_in_flight.clone();
+			async move {
+				while let Ok((stream, _)) = listener.accept().await {
+					tokio::spawn(serve(
+						stream,
+						handler.clone(),
+						in_flight.clone(),
+						max_in_flight.clone(),
+					));
+				}
+			}
+		});
+		Self {
+			addr,
+			task,
+			max_in_flight,
+		}
+	}
+
+	pub fn url(&self, path: &str) -> String {
+		format!("http://{}{path}", self.addr)
+	}
+
+	/// The most requests this server has been handling at the same time.
+	pub fn max_in_flight(&self) -> usize {
+		self.max_in_flight.load(Ordering::SeqCst)
+	}
+}
+
+async fn serve<F>(
+	mut stream: TcpStream,
+	handler: Arc<F>,
+	in_flight: Arc<AtomicUsize>,
+	max_in_flight: Arc<AtomicUsize>,
+) where
+	F: Fn(&MockRequest) -> MockResponse,
+{
+	let mut buf = Vec::new();
+	let mut chunk = [0u8; 1024];
+	while !buf.windows(4).any(|w| w == b"\r\n\r\n") {
+		match stream.read(&mut chunk).await {
+			Ok(0) | Err(_) => return,
+			Ok(n) => buf.extend_from_slice(&chunk[..n]),
+		}
+	}
+	let head = String::from_utf8_lossy(&buf).to_string();
+	let mut lines = head.lines();
+	let path = lines
+		.next()
+		.and_then(|line| line.split(' ').nth(1))
+		.unwrap_or("/")
+		.to_string();
+	let headers = lines
+		.take_while(|line| !line.is_empty())
+		.filter_map(|line| line.split_once(':'))
+		.map(|(k, v)| (k.trim().to_string(), v.trim().to_string()))
+		.collect();
+
+	let now_in_flight = in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+	max_in_flight.fetch_max(now_in_flight, Ordering::SeqCst);
+	let response = handler(&MockRequest { path, headers });
+	tokio::time::sleep(response.delay).await;
+	let mut out = format!("HTTP/1.1 {} Mock\r\nConnection: close\r\n", response.status);
+	// Responses may advertise a different length, to test clients that check it.
+	if !response
+		.headers
+		.iter()
+		.any(|(k, _)| k.eq_ignore_ascii_case("Content-Length"))
+	{
+		out.push_str(&format!("Content-Length: {}\r\n", response.body.len()));
+	}
+	for (k, v) in &response.headers {
+		out.push_str(&format!("{k}: {v}\r\n"));
+	}
+	out.push_str("\r\n");
+	let _ = stream.write_all(out.as_bytes()).await;
+	let _ = stream.write_all(&response.body).await;
+	let _ = stream.shutdown().await;
+	in_flight.fetch_sub(1, Ordering::SeqCst);
+}
+
+impl Drop for MockServer {
+	fn drop(&mut self) {
+		self.task.abort();
+	}
+}
+
+/// Answers requests itself rather than passing them on, so a [`ClientWithMiddleware`] built
+/// with it never sends anything.
+pub(crate) struct MockTransport<F> {
+	handler: F,
+}
+
+impl<F> MockTransport<F>
+where
+	F: Fn(&MockRequest) -> MockResponse + Send + Sync + 'static,
+{
+	/// A client which answers every request with `handler`.
+	pub fn client(handler: F) -> ClientWithMiddleware {
+		ClientBuilder::new(reqwest::Client::new())
+			.with(Self { handler })
+			.build()
+	}
+}
+
+#[async_trait::async_trait]
+impl<F> Middleware for MockTransport<F>
+where
+	F: Fn(&MockRequest) -> MockResponse + Send + Sync + 'static,
+{
+	async fn handle(
+		&self,
+		req: Request,
+		_extensions: &mut Extensions,
+		_next: Next<'_>,
+	) -> reqwest_middleware::Result<Response> {
+		let url = req.url();
+		let path = match url.query() {
+			Some(query) => format!("{}?{query}", url.path()),
+			None => url.path().to_string(),
+		};
+		let headers = req
+			.headers()
+			.iter()
+			.map(|(k, v)| {
+				(
+					k.to_string(),
+					String::from_utf8_lossy(v.as_bytes()).to_string(),
+				)
+			})
+			.collect();
+		let response = (self.handler)(&MockRequest { path, headers });
+		tokio::time::sleep(response.delay).await;
+		let mut builder = http::Response::builder().status(response.status);
+		for (k, v) in &response.headers {
+			builder = builder.header(k, v);
+		}
+		let response = builder
+			.body(response.body)
+			.map_err(|e| reqwest_middleware::Error::Middleware(e.into()))?;
+		Ok(Response::from(response))
+	}
+}
@@ -1,68 +1,754 @@
-use std::{collections::BTreeMap, sync::Arc, time::Duration};
+use std::{
+	collections::{BTreeMap, VecDeque},
+	fmt::Display,
+	io::Read,
+	path::{Path, PathBuf},
+	sync::{
+		atomic::{AtomicBool, Ordering},
+		Arc, Mutex,
+	},
+	time::{Duration, Instant},
+};
 
-use http_cache_reqwest::{CACacheManager, Cache, HttpCache, HttpCacheOptions};
-use reqwest::{Client, Response};
+use chrono::{DateTime, Utc};
+use flate2::read::{DeflateDecoder, MultiGzDecoder, ZlibDecoder};
+use http_cache_reqwest::{
+	CACacheManager, Cache, CacheManager, CacheMode, HttpCache, HttpCacheOptions,
+};
+use reqwest::{Client, NoProxy, Proxy, Response, StatusCode};
 use reqwest_middleware::{ClientBuilder, ClientWithMiddleware};
-use tokio::{sync::RwLock, task::JoinHandle};
+use thiserror::Error;
+use tokio::{
+	io::AsyncReadExt,
+	sync::{RwLock, Semaphore},
+	task::JoinHandle,
+};
+
+use crate::state::meta::Credentials;
+
+#[cfg(test)]
+pub(crate) mod mock;
 
 type RequestOutcome = Result<Response, reqwest_middleware::Error>;
 
+/// How many [`FetchStat`]s are kept. Older ones are forgotten.
+const MAX_STATS: usize = 1024;
+
+/// How a finished request went, for working out why a feed is slow.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FetchStat {
+	/// The URL requested, without any credentials it had in it.
+	pub url: String,
+	/// From just before the request was sent until the response headers arrived, including
+	/// any time spent waiting for a download slot.
+	pub duration: Duration,
+	/// The body's length, if the server said.
+	pub size: Option<u64>,
+	/// Whether the response came from the HTTP cache, if it was an HTTP response.
+	pub cache_hit: Option<bool>,
+	/// The response's status code, or `None` if the request failed outright.
+	pub status: Option<u16>,
+}
+
+impl FetchStat {
+	fn new(url: String, duration: Duration, outcome: &RequestOutcome) -> Self {
+		let response = outcome.as_ref().ok();
+		Self {
+			url,
+			duration,
+			size: response.and_then(Response::content_length),
+			cache_hit: response.and_then(|response| {
+				// Set by the cache middleware.
+				let header = response.headers().get("x-cache")?;
+				Some(header.as_bytes() == b"HIT")
+			}),
+			status: response.map(|response| response.status().as_u16()),
+		}
+	}
+}
+
+/// Tunables for a [`Fetcher`].
+#[derive(Clone, Debug)]
+pub struct FetchOptions {
+	/// How long to wait for a connection to be established.
+	pub connect_timeout: Duration,
+	/// How long a whole request for a feed or page may take.
+	pub timeout: Duration,
+	/// How long a whole request for audio or video may take.
+	pub media_timeout: Duration,
+	/// How many requests may be in flight at once. Any more will wait their turn.
+	pub max_concurrent_downloads: usize,
+	/// A proxy to send requests through, like `http://proxy:3128` or `socks5://proxy:1080`.
+	/// If this is unset, the `HTTP_PROXY`, `HTTPS_PROXY` and `NO_PROXY` environment variables
+	/// are honored instead. Local files are always read directly.
+	pub proxy: Option<String>,
+	/// Comma-separated hosts, domains and IP ranges which bypass `proxy`, in the same format
+	/// as `NO_PROXY`. If this is unset, `NO_PROXY` itself is used.
+	pub no_proxy: Option<String>,
+	/// Keep a [`FetchStat`] for each request, which [`Fetcher::stats`] returns.
+	pub record_stats: bool,
+	/// The most bytes an audio or video download may be, or `None` for no limit.
+	pub max_media_size: Option<u64>,
+	/// Start out only answering from the cache. See [`Fetcher::set_offline`].
+	pub offline: bool,
+	/// Where the HTTP cache is kept. It lasts between runs.
+	pub cache_dir: PathBuf,
+}
+
+impl Default for FetchOptions {
+	fn default() -> Self {
+		Self {
+			connect_timeout: Duration::from_secs(10),
+			timeout: Duration::from_secs(30),
+			media_timeout: Duration::from_secs(600),
+			max_concurrent_downloads: 8,
+			proxy: None,
+			no_proxy: None,
+			record_stats: false,
+			max_media_size: Some(1024 * 1024 * 1024),
+			offline: false,
+			cache_dir: PathBuf::from("./http-cacache"),
+		}
+	}
+}
+
+/// How much is in a [`Fetcher`]'s HTTP cache.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CacheStats {
+	pub entries: usize,
+	/// The total size of the cached responses, as stored.
+	pub bytes: u64,
+}
+
+/// A download in progress, with how it was started so it can be started again for the next
+/// caller waiting on it.
+struct Download {
+	handle: JoinHandle<RequestOutcome>,
+	timeout: Duration,
+	client: Arc<ClientWithMiddleware>,
+	local_files: bool,
+}
+
 pub struct Fetcher {
 	client: Arc<ClientWithMiddleware>,
-	in_progress: Arc<RwLock<BTreeMap<String, JoinHandle<RequestOutcome>>>>,
+	/// Like `client`, but always goes to the server instead of answering from the cache.
+	reload_client: Arc<ClientWithMiddleware>,
+	/// Skips the cache entirely, since it reads whole bodies into memory to store them, which
+	/// would defeat [`FetchOptions::max_media_size`].
+	media_client: Arc<ClientWithMiddleware>,
+	/// Only answers from the cache, and is used for everything while offline.
+	offline_client: Arc<ClientWithMiddleware>,
+	offline: AtomicBool,
+	in_progress: Arc<RwLock<BTreeMap<String, Download>>>,
+	/// How many callers are waiting on each in-progress download.
+	waiters: Arc<RwLock<BTreeMap<String, usize>>>,
+	timeout: Duration,
+	media_timeout: Duration,
+	permits: Arc<Semaphore>,
+	/// Stats for finished requests, if they're being recorded.
+	stats: Option<Arc<Mutex<VecDeque<FetchStat>>>>,
+	max_media_size: Option<u64>,
+	cache: CACacheManager,
 }
 
 impl Fetcher {
 	#[must_use]
 	pub fn new() -> Self {
-		let client = ClientBuilder::new(Client::new())
-			.with(Cache(HttpCache {
-				mode: http_cache_reqwest::CacheMode::Default,
-				manager: CACacheManager::default(),
-				options: HttpCacheOptions::default(),
-			}))
-			.build();
+		Self::from_options(FetchOptions::default())
+	}
+
+	#[must_use]
+	pub fn from_options(options: FetchOptions) -> Self {
+		let mut builder = Client::builder().connect_timeout(options.connect_timeout);
+		if let Some(proxy) = options.proxy.as_deref().filter(|proxy| !proxy.is_empty()) {
+			match Proxy::all(proxy) {
+				Ok(proxy) => {
+					let no_proxy = options
+						.no_proxy
+						.as_deref()
+						.map_or_else(NoProxy::from_env, NoProxy::from_string);
+					builder = builder.proxy(proxy.no_proxy(no_proxy));
+				}
+				Err(e) => log::warn!("Ignoring invalid proxy {proxy}: {e}"),
+			}
+		}
+		let client = builder.build().expect("Failed to build HTTP client");
+		let cache = CACacheManager { path: options.cache_dir.clone() };
+		let with_cache = |mode| {
+			Arc::new(
+				ClientBuilder::new(client.clone())
+					.with(Cache(HttpCache {
+						mode,
+						manager: cache.clone(),
+						options: HttpCacheOptions::default(),
+					}))
+					.build(),
+			)
+		};
+		let clients = [
+			with_cache(CacheMode::Default),
+			with_cache(CacheMode::Reload),
+			Arc::new(ClientBuilder::new(client.clone()).build()),
+			with_cache(CacheMode::OnlyIfCached),
+		];
+		Self::from_clients(options, cache, clients)
+	}
+
+	/// Builds a fetcher which sends every request through `client`, such as one answering them
+	/// without the network in tests. Nothing is cached unless `client` does so itself.
+	#[must_use]
+	pub fn with_client(client: ClientWithMiddleware, options: FetchOptions) -> Self {
+		let cache = CACacheManager { path: options.cache_dir.clone() };
 		let client = Arc::new(client);
-		let in_progress = Arc::new(RwLock::new(BTreeMap::default()));
+		Self::from_clients(options, cache, [(); 4].map(|()| client.clone()))
+	}
+
+	/// Takes the normal, reload, media and offline clients, in that order.
+	fn from_clients(
+		options: FetchOptions,
+		cache: CACacheManager,
+		[client, reload_client, media_client, offline_client]: [Arc<ClientWithMiddleware>; 4],
+	) -> Self {
 		Self {
 			client,
-			in_progress,
+			reload_client,
+			media_client,
+			offline_client,
+			offline: AtomicBool::new(options.offline),
+			in_progress: Arc::new(RwLock::new(BTreeMap::default())),
+			waiters: Arc::new(RwLock::new(BTreeMap::default())),
+			timeout: options.timeout,
+			media_timeout: options.media_timeout,
+			permits: Arc::new(Semaphore::new(options.max_concurrent_downloads.max(1))),
+			stats: options.record_stats.then(Arc::default),
+			max_media_size: options.max_media_size,
+			cache,
+		}
+	}
+
+	/// Sets the total timeout for feeds and pages.
+	#[must_use]
+	pub fn with_timeout(mut self, timeout: Duration) -> Self {
+		self.timeout = timeout;
+		self
+	}
+
+	/// Sets the total timeout for audio and video.
+	#[must_use]
+	pub fn with_media_timeout(mut self, timeout: Duration) -> Self {
+		self.media_timeout = timeout;
+		self
+	}
+
+	/// Sets how many requests may be in flight at once.
+	#[must_use]
+	pub fn with_max_concurrent_downloads(mut self, max: usize) -> Self {
+		self.permits = Arc::new(Semaphore::new(max.max(1)));
+		self
+	}
+
+	/// Sets whether a [`FetchStat`] is kept for each request.
+	#[must_use]
+	pub fn with_stats(mut self, record: bool) -> Self {
+		self.stats = record.then(Arc::default);
+		self
+	}
+
+	/// Sets the most bytes an audio or video download may be.
+	#[must_use]
+	pub fn with_max_media_size(mut self, max: Option<u64>) -> Self {
+		self.max_media_size = max;
+		self
+	}
+
+	/// The most bytes an audio or video download may be, if there's a limit.
+	#[must_use]
+	pub fn max_media_size(&self) -> Option<u64> {
+		self.max_media_size
+	}
+
+	/// Sets whether downloads only come from the cache, without touching the network. Anything
+	/// that isn't cached fails with an error that [`is_not_cached`] recognizes, which includes
+	/// every media download, since those skip the cache. Downloads which are already running
+	/// carry on as they were.
+	pub fn set_offline(&self, offline: bool) {
+		self.offline.store(offline, Ordering::Relaxed);
+	}
+
+	/// Whether downloads only come from the cache.
+	#[must_use]
+	pub fn is_offline(&self) -> bool {
+		self.offline.load(Ordering::Relaxed)
+	}
+
+	/// How many responses are cached, and how big they are.
+	#[must_use]
+	pub fn cache_stats(&self) -> CacheStats {
+		let mut stats = CacheStats::default();
+		for entry in cacache::list_sync(&self.cache.path) {
+			match entry {
+				Ok(entry) => {
+					stats.entries += 1;
+					stats.bytes += entry.size as u64;
+				}
+				Err(e) => log::warn!("Couldn't list a cache entry: {e}"),
+			}
+		}
+		stats
+	}
+
+	/// Reads every cached response, and removes any which can't be read back, like ones whose
+	/// content doesn't match its checksum or was written by an incompatible version. Returns
+	/// how many were removed.
+	pub async fn check_cache(&self) -> usize {
+		let path = self.cache.path.clone();
+		let entries = tokio::task::spawn_blocking(move || {
+			cacache::list_sync(path)
+				.filter_map(|entry| match entry {
+					Ok(entry) => Some((entry.key, entry.integrity)),
+					Err(e) => {
+						log::warn!("Couldn't list a cache entry: {e}");
+						None
+					}
+				})
+				.collect::<Vec<_>>()
+		})
+		.await
+		.unwrap_or_default();
+		let mut removed = 0;
+		for (key, integrity) in entries {
+			let problem = match self.cache.get(&key).await {
+				Ok(Some(_)) => continue,
+				Ok(None) => "its content is missing or corrupt".to_string(),
+				Err(e) => e.to_string(),
+			};
+			log::warn!("Removing cache entry {key}, since {problem}");
+			if let Err(e) = self.cache.delete(&key).await {
+				log::warn!("Couldn't remove cache entry {key}: {e}");
+				continue;
+			}
+			removed += 1;
+			// The content would otherwise stay on disk, since removing an entry only forgets it.
+			let path = self.cache.path.clone();
+			let content = tokio::task::spawn_blocking(move || {
+				cacache::remove_hash_sync(path, &integrity)
+			});
+			if let Ok(Err(e)) = content.await {
+				log::debug!("Couldn't remove the content of cache entry {key}: {e}");
+			}
+		}
+		removed
+	}
+
+	/// Removes everything from the HTTP cache.
+	///
+	/// # Errors
+	/// Fails if the cache's files can't be removed.
+	pub fn clear_cache(&self) -> std::io::Result<()> {
+		if !self.cache.path.exists() {
+			return Ok(());
 		}
+		cacache::clear_sync(&self.cache.path).map_err(std::io::Error::other)
+	}
+
+	/// How the most recent requests went, oldest first. Empty unless stats are being recorded.
+	#[must_use]
+	pub fn stats(&self) -> Vec<FetchStat> {
+		self.stats
+			.as_ref()
+			.map(|stats| stats.lock().expect("Stats lock poisoned").iter().cloned().collect())
+			.unwrap_or_default()
 	}
 
+	/// Starts downloading `url`, unless it's already being downloaded. `file:` URLs fail with
+	/// [`LocalFileRefused`], since they could have come from a feed's content.
 	pub async fn start_download<S: ToString>(&self, url: S) {
+		self.start_download_with(url, self.timeout, &self.client, false)
+			.await;
+	}
+
+	/// Like [`Fetcher::start_download`], but allows for the longer media timeout and doesn't
+	/// cache the response.
+	pub async fn start_media_download<S: ToString>(&self, url: S) {
+		self.start_download_with(url, self.media_timeout, &self.media_client, false)
+			.await;
+	}
+
+	/// Like [`Fetcher::start_download`], but ignores any cached response and asks the server
+	/// again. If the URL is already being downloaded, this waits on that download instead.
+	pub async fn start_download_bypass_cache<S: ToString>(&self, url: S) {
+		self.start_download_with(url, self.timeout, &self.reload_client, false)
+			.await;
+	}
+
+	/// Like [`Fetcher::start_download`], but reads `file:` URLs from disk too. This is only for
+	/// the feeds being subscribed to, whose URLs the user gave.
+	pub async fn start_feed_download<S: ToString>(&self, url: S, bypass_cache: bool) {
+		let client = if bypass_cache { &self.reload_client } else { &self.client };
+		self.start_download_with(url, self.timeout, client, true)
+			.await;
+	}
+
+	async fn start_download_with<S: ToString>(
+		&self,
+		url: S,
+		timeout: Duration,
+		client: &Arc<ClientWithMiddleware>,
+		local_files: bool,
+	) {
 		let url = url.to_string();
-		let client = self.client.clone();
+		*self.waiters.write().await.entry(url.clone()).or_default() += 1;
 		if self.in_progress.read().await.contains_key(&url) {
 			return;
 		}
-		self.in_progress.write().await.insert(
-			url.clone(),
-			tokio::task::spawn(async move {
-				client
-					.get(url)
-					.timeout(Duration::from_secs(30))
-					.send()
-					.await
+		let download = self.spawn_download(url.clone(), timeout, client, local_files);
+		self.in_progress.write().await.insert(url, download);
+	}
+
+	fn spawn_download(
+		&self,
+		url: String,
+		timeout: Duration,
+		client: &Arc<ClientWithMiddleware>,
+		local_files: bool,
+	) -> Download {
+		let offline = self.is_offline();
+		let requested_client = client.clone();
+		let client = if offline { &self.offline_client } else { client }.clone();
+		let permits = self.permits.clone();
+		let stats = self.stats.clone();
+		Download {
+			handle: tokio::task::spawn(async move {
+				let started = Instant::now();
+				let outcome = async {
+					let _permit = permits
+						.acquire_owned()
+						.await
+						.expect("Download semaphore closed");
+					// reqwest only speaks HTTP, so local files are read directly.
+					if let Some(path) = file_path(&url) {
+						if !local_files {
+							return Err(reqwest_middleware::Error::middleware(LocalFileRefused));
+						}
+						return read_file(&path).await;
+					}
+					// Credentials embedded in the URL are sent as a header instead, since some
+					// servers reject them in the URL itself.
+					let (url, credentials) = Credentials::split_from_url(&url);
+					log::debug!("Downloading {url}");
+					let mut request = client.get(url).timeout(timeout);
+					if let Some(Credentials { username, password }) = credentials {
+						request = request.basic_auth(username, password);
+					}
+					let response = request.send().await?;
+					if offline && was_not_cached(&response) {
+						return Err(reqwest_middleware::Error::middleware(NotCached));
+					}
+					Ok(response)
+				}
+				.await;
+				let shown_url = Credentials::split_from_url(&url).0;
+				match &outcome {
+					Ok(response) => log::debug!(
+						"Downloaded {shown_url} in {:?} with status {}",
+						started.elapsed(),
+						response.status()
+					),
+					Err(e) => log::debug!("Download of {shown_url} failed: {e}"),
+				}
+				if let Some(stats) = stats {
+					let stat = FetchStat::new(shown_url, started.elapsed(), &outcome);
+					let mut stats = stats.lock().expect("Stats lock poisoned");
+					if stats.len() >= MAX_STATS {
+						stats.pop_front();
+					}
+					stats.push_back(stat);
+				}
+				outcome
 			}),
-		);
+			timeout,
+			client: requested_client,
+			local_files,
+		}
 	}
 
+	/// Takes the outcome of the download of `url`, if it's finished. A response can only be
+	/// read once, so if anyone else is waiting on the same URL, it's downloaded again for them.
 	pub async fn try_finish(&self, url: &str) -> Option<RequestOutcome> {
+		let mut waiters = self.waiters.write().await;
 		let mut in_progress = self.in_progress.write().await;
-		let handle = in_progress.remove(url)?;
-		if !handle.is_finished() {
-			in_progress.insert(url.to_string(), handle);
+		let download = in_progress.remove(url)?;
+		if !download.handle.is_finished() {
+			in_progress.insert(url.to_string(), download);
 			return None;
 		}
-		match handle.await {
+		match waiters.get_mut(url) {
+			Some(count) if *count > 1 => {
+				*count -= 1;
+				let again = self.spawn_download(
+					url.to_string(),
+					download.timeout,
+					&download.client,
+					download.local_files,
+				);
+				in_progress.insert(url.to_string(), again);
+			}
+			_ => {
+				waiters.remove(url);
+			}
+		}
+		drop(in_progress);
+		drop(waiters);
+		match download.handle.await {
 			Ok(o) => Some(o),
 			Err(e) => {
-				eprintln!("{e}");
+				log::error!("Download of {url} failed to finish: {e}");
 				None
 			}
 		}
 	}
+
+	/// Downloads a URL and waits for the outcome. Returns `None` if the download was cancelled.
+	pub async fn fetch(&self, url: &str) -> Option<RequestOutcome> {
+		self.start_download(url).await;
+		self.wait_for(url).await
+	}
+
+	/// Like [`Fetcher::fetch`], but ignores any cached response.
+	pub async fn fetch_bypass_cache(&self, url: &str) -> Option<RequestOutcome> {
+		self.start_download_bypass_cache(url).await;
+		self.wait_for(url).await
+	}
+
+	/// Like [`Fetcher::fetch`], but downloads with [`Fetcher::start_feed_download`].
+	pub async fn fetch_feed(&self, url: &str, bypass_cache: bool) -> Option<RequestOutcome> {
+		self.start_feed_download(url, bypass_cache).await;
+		self.wait_for(url).await
+	}
+
+	async fn wait_for(&self, url: &str) -> Option<RequestOutcome> {
+		loop {
+			if let Some(outcome) = self.try_finish(url).await {
+				return Some(outcome);
+			}
+			if !self.is_downloading(url).await {
+				return None;
+			}
+			tokio::time::sleep(Duration::from_millis(20)).await;
+		}
+	}
+
+	/// Gives up on a download. If something else is also waiting on the same URL, the download
+	/// carries on for them.
+	pub async fn cancel(&self, url: &str) {
+		let mut waiters = self.waiters.write().await;
+		let Some(count) = waiters.get_mut(url) else {
+			return;
+		};
+		*count = count.saturating_sub(1);
+		if *count > 0 {
+			return;
+		}
+		waiters.remove(url);
+		if let Some(download) = self.in_progress.write().await.remove(url) {
+			log::debug!("Cancelling download of {}", Credentials::split_from_url(url).0);
+			download.handle.abort();
+		}
+	}
+
+	pub async fn is_downloading(&self, url: &str) -> bool {
+		self.in_progress.read().await.contains_key(url)
+	}
+}
+
+/// The local path a `file:` URL points to, if that's what `url` is.
+fn file_path(url: &str) -> Option<PathBuf> {
+	let url = reqwest::Url::parse(url).ok()?;
+	if url.scheme() != "file" {
+		return None;
+	}
+	url.to_file_path().ok()
+}
+
+/// Reads a local file as if it had been downloaded. A missing file is a 404, like it would be
+/// from a web server. Files are held to the same size as a decompressed body, so something
+/// like `/dev/zero` can't be read forever.
+async fn read_file(path: &Path) -> RequestOutcome {
+	let read = async {
+		let mut body = vec![];
+		tokio::fs::File::open(path)
+			.await?
+			.take(MAX_DECOMPRESSED_LEN + 1)
+			.read_to_end(&mut body)
+			.await?;
+		if body.len() as u64 > MAX_DECOMPRESSED_LEN {
+			return Err(std::io::Error::new(
+				std::io::ErrorKind::InvalidData,
+				"The file is too big",
+			));
+		}
+		Ok(body)
+	};
+	let (status, body) = match read.await {
+		Ok(body) => (StatusCode::OK, body),
+		Err(e) if e.kind() == std::io::ErrorKind::NotFound => (StatusCode::NOT_FOUND, vec![]),
+		Err(e) => return Err(reqwest_middleware::Error::middleware(e)),
+	};
+	let response = http::Response::builder()
+		.status(status)
+		.body(body)
+		.map_err(reqwest_middleware::Error::middleware)?;
+	Ok(Response::from(response))
+}
+
+/// Why a response's body couldn't be read.
+#[derive(Error, Debug)]
+pub enum BodyError {
+	/// The body is bigger than the limit, which is given.
+	TooLarge(u64),
+	Read(#[from] reqwest::Error),
+}
+
+impl Display for BodyError {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			BodyError::TooLarge(limit) => write!(f, "it's over the limit of {limit} bytes"),
+			BodyError::Read(e) => write!(f, "{e}"),
+		}
+	}
+}
+
+/// How big a compressed body is allowed to get once it's decompressed, so a small response
+/// can't expand to fill memory.
+const MAX_DECOMPRESSED_LEN: u64 = 64 * 1024 * 1024;
+
+/// Undoes a body's `Content-Encoding`. reqwest is only asked for identity encoding, but some
+/// servers compress anyway, and some serve `.xml.gz` files as they are, so anything which
+/// starts like gzip is treated as gzip, whatever the header says.
+///
+/// # Errors
+/// Fails if the body claims to be compressed but isn't valid, or decompresses to more than
+/// [`MAX_DECOMPRESSED_LEN`] bytes.
+pub fn decompress(body: Vec<u8>, content_encoding: Option<&str>) -> std::io::Result<Vec<u8>> {
+	let encoding = content_encoding.map(|encoding| encoding.trim().to_ascii_lowercase());
+	let mut out = vec![];
+	if body.starts_with(&[0x1f, 0x8b]) {
+		MultiGzDecoder::new(body.as_slice())
+			.take(MAX_DECOMPRESSED_LEN + 1)
+			.read_to_end(&mut out)?;
+	} else if encoding.as_deref() == Some("deflate") {
+		// Servers disagree on whether deflate means zlib-wrapped or raw.
+		let zlib = ZlibDecoder::new(body.as_slice())
+			.take(MAX_DECOMPRESSED_LEN + 1)
+			.read_to_end(&mut out);
+		if zlib.is_err() {
+			out.clear();
+			DeflateDecoder::new(body.as_slice())
+				.take(MAX_DECOMPRESSED_LEN + 1)
+				.read_to_end(&mut out)?;
+		}
+	} else if matches!(encoding.as_deref(), Some("gzip" | "x-gzip")) {
+		return Err(std::io::Error::new(
+			std::io::ErrorKind::InvalidData,
+			"The body is labelled as gzip, but isn't",
+		));
+	} else {
+		return Ok(body);
+	}
+	if out.len() as u64 > MAX_DECOMPRESSED_LEN {
+		return Err(std::io::Error::new(
+			std::io::ErrorKind::InvalidData,
+			"The body is too big once decompressed",
+		));
+	}
+	Ok(out)
+}
+
+/// How long a `Retry-After` header says to wait, given as either seconds or an HTTP date.
+/// Dates which have already passed mean there's no need to wait.
+#[must_use]
+pub fn parse_retry_after(value: &str, now: DateTime<Utc>) -> Option<Duration> {
+	let value = value.trim();
+	if let Ok(secs) = value.parse() {
+		return Some(Duration::from_secs(secs));
+	}
+	let date = DateTime::parse_from_rfc2822(value).ok()?;
+	Some((date.with_timezone(&Utc) - now).to_std().unwrap_or(Duration::ZERO))
+}
+
+/// Reads a response's body, giving up as soon as it's known to be more than `limit` bytes.
+/// The advertised length is checked first, but the body is counted as it arrives too, in
+/// case the server didn't say or lied.
+pub async fn read_body(mut response: Response, limit: Option<u64>) -> Result<Vec<u8>, BodyError> {
+	let Some(limit) = limit else {
+		return Ok(response.bytes().await?.to_vec());
+	};
+	if response.content_length().is_some_and(|len| len > limit) {
+		return Err(BodyError::TooLarge(limit));
+	}
+	let mut body = Vec::new();
+	while let Some(chunk) = response.chunk().await? {
+		body.extend_from_slice(&chunk);
+		if body.len() as u64 > limit {
+			return Err(BodyError::TooLarge(limit));
+		}
+	}
+	Ok(body)
+}
+
+/// The error for a request made while offline for something that isn't in the cache.
+#[derive(Error, Debug)]
+pub struct NotCached;
+
+impl Display for NotCached {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		write!(f, "Not available offline")
+	}
+}
+
+/// The error for a `file:` URL downloaded anywhere but [`Fetcher::start_feed_download`].
+#[derive(Error, Debug)]
+pub struct LocalFileRefused;
+
+impl Display for LocalFileRefused {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		write!(f, "Only feeds can be read from local files")
+	}
+}
+
+/// Whether a response is the cache's stand-in for something it doesn't have, rather than a
+/// real response.
+fn was_not_cached(response: &Response) -> bool {
+	// The cache middleware answers with a gateway timeout and marks the lookup as a miss.
+	response.status() == StatusCode::GATEWAY_TIMEOUT
+		&& response
+			.headers()
+			.get("x-cache-lookup")
+			.is_some_and(|header| header.as_bytes() == b"MISS")
+}
+
+/// Whether a failed request failed because it was made offline for something that isn't in
+/// the cache.
+#[must_use]
+pub fn is_not_cached(error: &reqwest_middleware::Error) -> bool {
+	match error {
+		reqwest_middleware::Error::Reqwest(_) => false,
+		reqwest_middleware::Error::Middleware(e) => e.chain().any(|cause| cause.is::<NotCached>()),
+	}
+}
+
+/// Whether a failed request failed because it timed out, even if the error was wrapped by
+/// middleware.
+#[must_use]
+pub fn is_timeout(error: &reqwest_middleware::Error) -> bool {
+	match error {
+		reqwest_middleware::Error::Reqwest(e) => e.is_timeout(),
+		reqwest_middleware::Error::Middleware(e) => e.chain().any(|cause| {
+			cause
+				.downcast_ref::<reqwest::Error>()
+				.is_some_and(reqwest::Error::is_timeout)
+		}),
+	}
 }
 
 impl Default for Fetcher {
@@ -73,11 +759,70 @@ impl Default for Fetcher {
 
 #[cfg(test)]
 mod test {
-	use super::Fetcher;
+	use super::{
+		is_not_cached, is_timeout,
+		mock::{MockResponse, MockServer, MockTransport},
+		parse_retry_after, read_body, BodyError, CacheStats, FetchOptions, FetchStat, Fetcher,
+		LocalFileRefused,
+	};
+	use chrono::{DateTime, Utc};
+	use reqwest_middleware::Error;
+	use std::{
+		sync::{
+			atomic::{AtomicUsize, Ordering},
+			Arc,
+		},
+		time::Duration,
+	};
+
+	#[tokio::test]
+	async fn downloads_through_an_injected_client() {
+		let requests = Arc::new(AtomicUsize::new(0));
+		let client = MockTransport::client({
+			let requests = requests.clone();
+			move |request| {
+				requests.fetch_add(1, Ordering::SeqCst);
+				match request.path.as_str() {
+					"/feed.xml" => {
+						MockResponse::ok("<rss/>").with_header("Content-Type", "text/xml")
+					}
+					_ => MockResponse::status(404),
+				}
+			}
+		});
+		let fetcher = Fetcher::with_client(client, FetchOptions::default());
+		let url = "https://feeds.invalid/feed.xml";
+		fetcher.start_download(url).await;
+		let outcome = finish(&fetcher, url).await.unwrap();
+		assert_eq!(outcome.status().as_u16(), 200);
+		assert_eq!(outcome.text().await.unwrap(), "<rss/>");
+
+		let missing = "https://feeds.invalid/missing.xml";
+		fetcher.start_download(missing).await;
+		let outcome = finish(&fetcher, missing).await.unwrap();
+		assert_eq!(outcome.status().as_u16(), 404);
+		assert_eq!(requests.load(Ordering::SeqCst), 2);
+	}
+
+	#[test]
+	fn parses_retry_after() {
+		let now: DateTime<Utc> = "2023-10-21T07:28:00Z".parse().unwrap();
+		assert_eq!(parse_retry_after(" 120 ", now), Some(Duration::from_secs(120)));
+		assert_eq!(
+			parse_retry_after("Sat, 21 Oct 2023 07:30:00 GMT", now),
+			Some(Duration::from_secs(120))
+		);
+		assert_eq!(
+			parse_retry_after("Sat, 21 Oct 2023 07:00:00 GMT", now),
+			Some(Duration::ZERO)
+		);
+		assert_eq!(parse_retry_after("soon", now), None);
+	}
 
 	#[tokio::test]
 	async fn try_download_example_dot_com() {
-		let fetcher = Fetcher::default();
+		let cache = tempdir::TempDir::new("winter_cache_test").unwrap();
+		let fetcher = fetcher_in(&cache);
 		let outcome;
 		fetcher.start_download("https://example.com").await;
 
@@ -90,4 +835,310 @@ mod test {
 		let text = outcome.unwrap().text().await.unwrap();
 		eprintln!("{text}");
 	}
+
+	/// A fetcher with its cache in `cache`, so tests neither share one nor leave it behind.
+	fn fetcher_in(cache: &tempdir::TempDir) -> Fetcher {
+		Fetcher::from_options(FetchOptions {
+			cache_dir: cache.path().to_path_buf(),
+			..FetchOptions::default()
+		})
+	}
+
+	async fn finish(fetcher: &Fetcher, url: &str) -> Result<reqwest::Response, Error> {
+		loop {
+			if let Some(outcome) = fetcher.try_finish(url).await {
+				return outcome;
+			}
+			tokio::task::yield_now().await;
+		}
+	}
+
+	#[tokio::test]
+	async fn basic_auth_from_url() {
+		let server = MockServer::start(|request| {
+			if request.path != "/feed" {
+				return MockResponse::status(404);
+			}
+			// "user:hunter2"
+			if request.header("authorization") == Some("Basic dXNlcjpodW50ZXIy") {
+				MockResponse::ok("secret feed")
+			} else {
+				MockResponse::status(401)
+			}
+		})
+		.await;
+		let cache = tempdir::TempDir::new("winter_cache_test").unwrap();
+		let fetcher = fetcher_in(&cache);
+
+		let anonymous = server.url("/feed");
+		fetcher.start_download(&anonymous).await;
+		let outcome = finish(&fetcher, &anonymous).await.unwrap();
+		assert_eq!(outcome.status().as_u16(), 401);
+
+		let credentialed = anonymous.replacen("http://", "http://user:hunter2@", 1);
+		fetcher.start_download(&credentialed).await;
+		let outcome = finish(&fetcher, &credentialed).await.unwrap();
+		assert_eq!(outcome.status().as_u16(), 200);
+		assert_eq!(outcome.text().await.unwrap(), "secret feed");
+	}
+
+	#[tokio::test]
+	async fn records_stats() {
+		let server =
+			MockServer::start(|_| MockResponse::ok("stats").with_delay(Duration::from_millis(20)))
+				.await;
+		let url = server.url("/stats");
+		let cache = tempdir::TempDir::new("winter_cache_test").unwrap();
+		let quiet = fetcher_in(&cache);
+		quiet.fetch(&url).await.unwrap().unwrap();
+		assert!(quiet.stats().is_empty());
+
+		let fetcher = fetcher_in(&cache).with_stats(true);
+		fetcher.fetch(&url).await.unwrap().unwrap();
+		let stats = fetcher.stats();
+		let [FetchStat { url: recorded, duration, size, status, .. }] = stats.as_slice() else {
+			panic!("Expected one stat, got {stats:?}");
+		};
+		assert_eq!(recorded, &url);
+		assert!(*duration >= Duration::from_millis(20));
+		assert_eq!(*size, Some(5));
+		assert_eq!(*status, Some(200));
+
+		let with_credentials = url.replace("http://", "http://user:secret@");
+		fetcher.fetch(&with_credentials).await.unwrap().unwrap();
+		assert_eq!(fetcher.stats()[1].url, url);
+	}
+
+	#[tokio::test]
+	async fn times_out() {
+		let server =
+			MockServer::start(|_| MockResponse::ok("slow").with_delay(Duration::from_secs(5))).await;
+		let cache = tempdir::TempDir::new("winter_cache_test").unwrap();
+		let fetcher = fetcher_in(&cache).with_timeout(Duration::from_millis(200));
+		let url = server.url("/slow");
+		fetcher.start_download(&url).await;
+		let outcome = finish(&fetcher, &url).await;
+		assert!(is_timeout(&outcome.unwrap_err()));
+	}
+
+	#[tokio::test]
+	async fn limits_concurrent_downloads() {
+		let server =
+			MockServer::start(|_| MockResponse::ok("slow").with_delay(Duration::from_millis(200)))
+				.await;
+		let cache = tempdir::TempDir::new("winter_cache_test").unwrap();
+		let fetcher = fetcher_in(&cache).with_max_concurrent_downloads(3);
+		let urls: Vec<_> = (0..12).map(|n| server.url(&format!("/{n}"))).collect();
+		for url in &urls {
+			fetcher.start_download(url).await;
+		}
+		for url in &urls {
+			assert!(finish(&fetcher, url).await.unwrap().status().is_success());
+		}
+		assert!(server.max_in_flight() <= 3);
+	}
+
+	#[tokio::test]
+	async fn bypasses_cache() {
+		let hits = Arc::new(AtomicUsize::new(0));
+		let server = MockServer::start({
+			let hits = hits.clone();
+			move |_| {
+				let n = hits.fetch_add(1, Ordering::SeqCst) + 1;
+				MockResponse::ok(n.to_string()).with_header("Cache-Control", "max-age=3600")
+			}
+		})
+		.await;
+		let cache = tempdir::TempDir::new("winter_cache_test").unwrap();
+		let fetcher = fetcher_in(&cache);
+		let url = server.url("/cached");
+		let text = |outcome: Option<Result<reqwest::Response, Error>>| async {
+			outcome.unwrap().unwrap().text().await.unwrap()
+		};
+		assert_eq!(text(fetcher.fetch(&url).await).await, "1");
+		assert_eq!(text(fetcher.fetch(&url).await).await, "1");
+		assert_eq!(text(fetcher.fetch_bypass_cache(&url).await).await, "2");
+		assert_eq!(hits.load(Ordering::SeqCst), 2);
+	}
+
+	#[tokio::test]
+	async fn routes_through_proxy() {
+		let proxied = Arc::new(std::sync::Mutex::new(vec![]));
+		let proxy = MockServer::start({
+			let proxied = proxied.clone();
+			move |request| {
+				proxied.lock().unwrap().push(request.path.clone());
+				MockResponse::ok("via proxy")
+			}
+		})
+		.await;
+		let direct = MockServer::start(|_| MockResponse::ok("direct")).await;
+		let cache = tempdir::TempDir::new("winter_cache_test").unwrap();
+		let fetcher = Fetcher::from_options(FetchOptions {
+			proxy: Some(proxy.url("")),
+			no_proxy: Some("127.0.0.1".to_string()),
+			cache_dir: cache.path().to_path_buf(),
+			..FetchOptions::default()
+		});
+		let text = |outcome: Option<Result<reqwest::Response, Error>>| async {
+			outcome.unwrap().unwrap().text().await.unwrap()
+		};
+		assert_eq!(
+			text(fetcher.fetch("http://feeds.invalid/proxied.xml").await).await,
+			"via proxy"
+		);
+		assert_eq!(text(fetcher.fetch(&direct.url("/direct.xml")).await).await, "direct");
+		// Proxies are sent the whole URL rather than just the path.
+		assert_eq!(*proxied.lock().unwrap(), ["http://feeds.invalid/proxied.xml"]);
+	}
+
+	#[tokio::test]
+	async fn refuses_oversized_bodies() {
+		let server = MockServer::start(|request| match request.path.as_str() {
+			"/huge" => MockResponse::ok("tiny").with_header("Content-Length", "10000000000"),
+			_ => MockResponse::ok(vec![b'a'; 2048]),
+		})
+		.await;
+		let cache = tempdir::TempDir::new("winter_cache_test").unwrap();
+		let fetcher = fetcher_in(&cache);
+		let download = |url: String| {
+			let fetcher = &fetcher;
+			async move {
+				fetcher.start_media_download(&url).await;
+				finish(fetcher, &url).await.unwrap()
+			}
+		};
+		let response = download(server.url("/huge")).await;
+		assert!(matches!(
+			read_body(response, Some(1024)).await,
+			Err(BodyError::TooLarge(1024))
+		));
+		let response = download(server.url("/big")).await;
+		assert!(matches!(
+			read_body(response, Some(1024)).await,
+			Err(BodyError::TooLarge(1024))
+		));
+		let response = download(server.url("/big")).await;
+		assert_eq!(read_body(response, None).await.unwrap().len(), 2048);
+	}
+
+	#[tokio::test]
+	async fn only_reads_the_cache_offline() {
+		let hits = Arc::new(AtomicUsize::new(0));
+		let server = MockServer::start({
+			let hits = hits.clone();
+			move |_| {
+				hits.fetch_add(1, Ordering::SeqCst);
+				MockResponse::ok("cached").with_header("Cache-Control", "max-age=3600")
+			}
+		})
+		.await;
+		let cache = tempdir::TempDir::new("winter_cache_test").unwrap();
+		let fetcher = fetcher_in(&cache);
+		let cached = server.url("/offline-cached");
+		let outcome = fetcher.fetch(&cached).await.unwrap().unwrap();
+		assert_eq!(outcome.text().await.unwrap(), "cached");
+
+		fetcher.set_offline(true);
+		let outcome = fetcher.fetch(&cached).await.unwrap().unwrap();
+		assert_eq!(outcome.text().await.unwrap(), "cached");
+		let outcome = fetcher.fetch(&server.url("/offline-uncached")).await.unwrap();
+		assert!(is_not_cached(&outcome.unwrap_err()));
+		assert_eq!(hits.load(Ordering::SeqCst), 1);
+	}
+
+	#[tokio::test]
+	async fn reads_local_files() {
+		let tmp = tempdir::TempDir::new("winter_fetch_test").unwrap();
+		let path = tmp.path().join("feed.xml");
+		std::fs::write(&path, "local feed").unwrap();
+		let url = reqwest::Url::from_file_path(&path).unwrap().to_string();
+		let cache = tempdir::TempDir::new("winter_cache_test").unwrap();
+		let fetcher = fetcher_in(&cache);
+		let outcome = fetcher.fetch_feed(&url, false).await.unwrap().unwrap();
+		assert!(outcome.status().is_success());
+		assert_eq!(outcome.text().await.unwrap(), "local feed");
+
+		let missing = reqwest::Url::from_file_path(tmp.path().join("missing.xml"))
+			.unwrap()
+			.to_string();
+		let outcome = fetcher.fetch_feed(&missing, true).await.unwrap().unwrap();
+		assert_eq!(outcome.status().as_u16(), 404);
+
+		// Anything else could have been given the URL by a feed.
+		let refused = |outcome: Result<reqwest::Response, Error>| match outcome {
+			Err(Error::Middleware(e)) => e.is::<LocalFileRefused>(),
+			_ => false,
+		};
+		assert!(refused(fetcher.fetch(&url).await.unwrap()));
+		assert!(refused(fetcher.fetch_bypass_cache(&url).await.unwrap()));
+		fetcher.start_media_download(&url).await;
+		assert!(refused(finish(&fetcher, &url).await));
+
+		// Files which never end are given up on.
+		if std::path::Path::new("/dev/zero").exists() {
+			assert!(fetcher.fetch_feed("file:///dev/zero", false).await.unwrap().is_err());
+		}
+	}
+
+	#[tokio::test]
+	async fn cancels_only_when_unwanted() {
+		let server =
+			MockServer::start(|_| MockResponse::ok("slow").with_delay(Duration::from_secs(5))).await;
+		let cache = tempdir::TempDir::new("winter_cache_test").unwrap();
+		let fetcher = fetcher_in(&cache);
+		let url = server.url("/slow");
+		fetcher.start_download(&url).await;
+		fetcher.start_download(&url).await;
+		fetcher.cancel(&url).await;
+		assert!(fetcher.is_downloading(&url).await);
+		fetcher.cancel(&url).await;
+		assert!(!fetcher.is_downloading(&url).await);
+		assert!(fetcher.try_finish(&url).await.is_none());
+	}
+
+	#[tokio::test]
+	async fn finishes_for_every_waiter() {
+		let server = MockServer::start(|_| {
+			MockResponse::ok("slow").with_delay(Duration::from_millis(200))
+		})
+		.await;
+		let cache = tempdir::TempDir::new("winter_cache_test").unwrap();
+		let fetcher = fetcher_in(&cache);
+		let url = server.url("/slow");
+		let (first, second) = tokio::join!(fetcher.fetch(&url), fetcher.fetch(&url));
+		for outcome in [first, second] {
+			let response = outcome.expect("Waiter was left without an outcome").unwrap();
+			assert_eq!(response.text().await.unwrap(), "slow");
+		}
+		assert!(!fetcher.is_downloading(&url).await);
+	}
+
+	#[tokio::test]
+	async fn removes_corrupt_cache_entries() {
+		let server = MockServer::start(|_| {
+			MockResponse::ok("cached").with_header("Cache-Control", "max-age=3600")
+		})
+		.await;
+		let tmp = tempdir::TempDir::new("winter_cache_test").unwrap();
+		let fetcher = Fetcher::from_options(FetchOptions {
+			cache_dir: tmp.path().to_path_buf(),
+			..FetchOptions::default()
+		});
+		let url = server.url("/cached");
+		fetcher.fetch(&url).await.unwrap().unwrap();
+		cacache::write_sync(tmp.path(), "GET:http://feeds.invalid/corrupt", b"not a response")
+			.unwrap();
+		assert_eq!(fetcher.cache_stats().entries, 2);
+
+		assert_eq!(fetcher.check_cache().await, 1);
+		assert_eq!(fetcher.cache_stats().entries, 1);
+		assert_eq!(fetcher.check_cache().await, 0);
+		fetcher.set_offline(true);
+		let outcome = fetcher.fetch(&url).await.unwrap().unwrap();
+		assert_eq!(outcome.text().await.unwrap(), "cached");
+
+		fetcher.clear_cache().unwrap();
+		assert_eq!(fetcher.cache_stats(), CacheStats::default());
+	}
 }
@@ -128,6 +128,41 @@ pub struct Theme {
     pub crust: Color32,
 }
 
+impl Theme {
+    /// The color with the given name, like `"surface0"`.
+    pub fn color_mut(&mut self, name: &str) -> Option<&mut Color32> {
+        Some(match name {
+            "rosewater" => &mut self.rosewater,
+            "flamingo" => &mut self.flamingo,
+            "pink" => &mut self.pink,
+            "mauve" => &mut self.mauve,
+            "red" => &mut self.red,
+            "maroon" => &mut self.maroon,
+            "peach" => &mut self.peach,
+            "yellow" => &mut self.yellow,
+            "green" => &mut self.green,
+            "teal" => &mut self.teal,
+            "sky" => &mut self.sky,
+            "sapphire" => &mut self.sapphire,
+            "blue" => &mut self.blue,
+            "lavender" => &mut self.lavender,
+            "text" => &mut self.text,
+            "subtext1" => &mut self.subtext1,
+            "subtext0" => &mut self.subtext0,
+            "overlay2" => &mut self.overlay2,
+            "overlay1" => &mut self.overlay1,
+            "overlay0" => &mut self.overlay0,
+            "surface2" => &mut self.surface2,
+            "surface1" => &mut self.surface1,
+            "surface0" => &mut self.surface0,
+            "base" => &mut self.base,
+            "mantle" => &mut self.mantle,
+            "crust" => &mut self.crust,
+            _ => return None,
+        })
+    }
+}
+
 pub const LATTE: Theme = Theme {
     rosewater: Color32::from_rgb(220, 138, 120),
     flamingo: Color32::from_rgb(221, 120, 120),
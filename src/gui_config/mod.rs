@@ -1,4 +1,14 @@
-use eframe::{egui::{Context, Visuals}, HardwareAcceleration, NativeOptions};
+use std::{collections::BTreeMap, path::PathBuf, time::Duration};
+
+use eframe::{
+	egui::{Color32, Context, FontData, FontDefinitions, FontFamily, TextStyle, Visuals},
+	HardwareAcceleration, NativeOptions,
+};
+use winter::{
+	document::LinkClick,
+	fetch::FetchOptions,
+	state::{FeedSort, ReadStorage, DEFAULT_MAX_BODY_LEN},
+};
 mod catppuccin;
 
 #[derive(serde::Deserialize, serde::Serialize)]
@@ -28,18 +38,193 @@ impl From<WindowOptions> for NativeOptions {
             transparent: val.transparent,
             vsync: val.vsync,
             hardware_acceleration: if val.hardware_acceleration {HardwareAcceleration::Preferred} else {HardwareAcceleration::Off},
+            follow_system_theme: true,
             ..Default::default()
         }
     }
 }
 
+#[derive(serde::Deserialize, serde::Serialize)]
+pub(crate) struct NetworkOptions {
+	/// Seconds to wait for a connection to be established.
+	pub(crate) connect_timeout: u64,
+	/// Seconds a feed or page download may take.
+	pub(crate) timeout: u64,
+	/// Seconds an audio or video download may take.
+	pub(crate) media_timeout: u64,
+	/// How many downloads may run at once.
+	pub(crate) max_concurrent_downloads: usize,
+	/// Seconds between automatic refreshes of each feed, or 0 to only refresh when asked.
+	/// Feeds can override this.
+	pub(crate) refresh_interval: u64,
+	/// A proxy URL, like `socks5://localhost:1080`. Otherwise `HTTP_PROXY` and friends are
+	/// used.
+	pub(crate) proxy: Option<String>,
+	/// Hosts which skip `proxy`, like `NO_PROXY`.
+	pub(crate) no_proxy: Option<String>,
+	/// Megabytes an audio or video download may be, or 0 for no limit.
+	pub(crate) max_media_size: u64,
+	/// Start in offline mode, only reading feeds and pages which are already cached. Audio and
+	/// video are never cached, so they aren't available offline.
+	pub(crate) offline: bool,
+	/// Where to keep the HTTP cache. Relative paths are from the working directory.
+	pub(crate) cache_dir: PathBuf,
+}
+
+impl NetworkOptions {
+	pub(crate) fn refresh_interval(&self) -> Option<Duration> {
+		(self.refresh_interval > 0).then(|| Duration::from_secs(self.refresh_interval))
+	}
+}
+
+impl Default for NetworkOptions {
+	fn default() -> Self {
+		let defaults = FetchOptions::default();
+		Self {
+			connect_timeout: defaults.connect_timeout.as_secs(),
+			timeout: defaults.timeout.as_secs(),
+			media_timeout: defaults.media_timeout.as_secs(),
+			max_concurrent_downloads: defaults.max_concurrent_downloads,
+			refresh_interval: 0,
+			proxy: defaults.proxy,
+			no_proxy: defaults.no_proxy,
+			max_media_size: defaults.max_media_size.map_or(0, |max| max / (1024 * 1024)),
+			offline: defaults.offline,
+			cache_dir: defaults.cache_dir,
+		}
+	}
+}
+
+impl From<NetworkOptions> for FetchOptions {
+	fn from(val: NetworkOptions) -> Self {
+		FetchOptions {
+			connect_timeout: Duration::from_secs(val.connect_timeout),
+			timeout: Duration::from_secs(val.timeout),
+			media_timeout: Duration::from_secs(val.media_timeout),
+			max_concurrent_downloads: val.max_concurrent_downloads,
+			proxy: val.proxy,
+			no_proxy: val.no_proxy,
+			record_stats: false,
+			max_media_size: (val.max_media_size > 0).then(|| val.max_media_size * 1024 * 1024),
+			offline: val.offline,
+			cache_dir: val.cache_dir,
+		}
+	}
+}
+
+/// Options for how feeds and articles are presented.
+#[derive(serde::Deserialize, serde::Serialize, Clone)]
+#[allow(clippy::struct_excessive_bools)]
+pub(crate) struct ReaderOptions {
+	/// Collapse copies of the same article from different feeds in the "All Articles" view.
+	pub(crate) dedup_articles: bool,
+	pub(crate) density: ListDensity,
+	/// Group the article list under headers like "Today" and "This Week".
+	pub(crate) group_by_date: bool,
+	/// How many characters of the body to preview in a comfortable article list.
+	pub(crate) snippet_length: usize,
+	pub(crate) timestamps: TimestampStyle,
+	/// Start downloading audio and video as soon as an article is opened. Otherwise they wait
+	/// to be clicked, which is kinder to metered connections.
+	pub(crate) autoload_media: bool,
+	pub(crate) autoload_images: bool,
+	/// Show authors' pictures from Gravatar, looked up by their email address. This lets
+	/// Gravatar see which articles are being read, so it's off unless asked for.
+	pub(crate) author_avatars: bool,
+	/// Start with the side panels hidden while reading, as if `f` had been pressed.
+	pub(crate) focus_reading: bool,
+	/// Put a "Sponsored" badge next to links the page marks `rel="sponsored"`.
+	pub(crate) flag_sponsored_links: bool,
+	/// Make URLs written out in articles' text clickable, as if they were links.
+	pub(crate) linkify_urls: bool,
+	/// How many bytes of an article's HTML to show. Longer articles are cut short, with a note
+	/// to read the rest in the browser, since laying out megabytes of markup stalls the reader.
+	pub(crate) max_article_bytes: usize,
+	/// Go back to the article list when an article is marked read.
+	pub(crate) close_on_read: bool,
+	/// `open_and_minimize` to get the window out of the way when a link is opened in the
+	/// browser. Feeds can override this.
+	pub(crate) link_click: LinkClick,
+	/// How the channel list is sorted at first. It can be changed above the list.
+	pub(crate) channel_sort: FeedSort,
+	/// Only list this many channels at first, with a button to show the rest. `0` lists them
+	/// all.
+	pub(crate) channel_limit: usize,
+}
+
+impl Default for ReaderOptions {
+	fn default() -> Self {
+		Self {
+			dedup_articles: false,
+			density: ListDensity::default(),
+			group_by_date: false,
+			snippet_length: 120,
+			timestamps: TimestampStyle::default(),
+			autoload_media: true,
+			autoload_images: true,
+			author_avatars: false,
+			focus_reading: false,
+			flag_sponsored_links: false,
+			linkify_urls: false,
+			max_article_bytes: DEFAULT_MAX_BODY_LEN,
+			close_on_read: false,
+			link_click: LinkClick::default(),
+			channel_sort: FeedSort::default(),
+			channel_limit: 0,
+		}
+	}
+}
+
+#[derive(serde::Deserialize, serde::Serialize, Default, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ListDensity {
+	/// Just the title and date.
+	#[default]
+	Compact,
+	/// The title and date, plus a snippet of the body.
+	Comfortable,
+}
+
+#[derive(serde::Deserialize, serde::Serialize, Default, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum TimestampStyle {
+	/// The date an article was published.
+	#[default]
+	Absolute,
+	/// How long ago an article was published, like "3 hours ago".
+	Relative,
+}
+
 #[derive(serde::Deserialize, serde::Serialize, PartialEq)]
 #[serde(untagged)]
+#[allow(clippy::enum_variant_names)]
 pub enum Theme {
     Template(ThemeTemplate),
+    /// Follow the OS preference, switching between two templates.
+    Auto {
+        light: ThemeTemplate,
+        dark: ThemeTemplate,
+    },
     ExplicitTheme(Box<Visuals>),
 }
 
+impl Theme {
+    /// The template to use when the OS prefers dark (or light) mode, if this theme is built from one.
+    pub fn resolve(&self, dark_mode: bool) -> Option<&ThemeTemplate> {
+        match self {
+            Theme::Template(template) => Some(template),
+            Theme::Auto { light, dark } => Some(if dark_mode { dark } else { light }),
+            Theme::ExplicitTheme(_) => None,
+        }
+    }
+
+    pub fn apply(&self, ctx: &Context, dark_mode: bool, palette: &PaletteOverride) {
+        if let Theme::ExplicitTheme(visuals) = self {
+            ctx.set_visuals(*visuals.clone());
+        } else if let Some(template) = self.resolve(dark_mode) {
+            template.apply(ctx, palette);
+        }
+    }
+}
+
 impl Default for Theme {
     fn default() -> Self {
         Self::Template(ThemeTemplate::Frappe)
@@ -57,7 +242,7 @@ pub enum ThemeTemplate {
 }
 
 impl ThemeTemplate {
-	pub fn apply(&self, ctx: &Context) {
+	pub fn apply(&self, ctx: &Context, palette: &PaletteOverride) {
 		if self == &ThemeTemplate::Egui {
 			return;
 		}
@@ -68,12 +253,322 @@ impl ThemeTemplate {
 			ThemeTemplate::Frappe => catppuccin::FRAPPE,
 			ThemeTemplate::Egui => unreachable!(),
 		};
-		catppuccin::set_theme(ctx, theme);
+		catppuccin::set_theme(ctx, palette.apply(theme));
+	}
+}
+
+/// Replacements for colors of the catppuccin flavors, by name, like `surface0 = "#303446"`.
+/// They don't affect the plain egui theme or explicit visuals.
+#[derive(serde::Deserialize, serde::Serialize, Default, Clone, PartialEq, Eq, Debug)]
+#[serde(transparent)]
+pub(crate) struct PaletteOverride(pub(crate) BTreeMap<String, String>);
+
+impl PaletteOverride {
+	/// Replaces the named colors in `theme`, skipping any with unknown names or invalid hex.
+	fn apply(&self, mut theme: catppuccin::Theme) -> catppuccin::Theme {
+		for (name, hex) in &self.0 {
+			let Some(color) = theme.color_mut(name) else {
+				log::warn!("Ignoring override of unknown palette color {name}");
+				continue;
+			};
+			let Some(parsed) = parse_hex(hex) else {
+				log::warn!("Ignoring override of {name}: {hex} isn't a color like #rrggbb");
+				continue;
+			};
+			*color = parsed;
+		}
+		theme
+	}
+}
+
+/// Parses `#rrggbb`, with or without the `#`.
+fn parse_hex(hex: &str) -> Option<Color32> {
+	let hex = hex.strip_prefix('#').unwrap_or(hex);
+	if hex.len() != 6 || !hex.is_ascii() {
+		return None;
+	}
+	let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok();
+	Some(Color32::from_rgb(channel(0)?, channel(2)?, channel(4)?))
+}
+
+/// Working directories the user has opened, most recent first.
+#[derive(serde::Deserialize, serde::Serialize, Default, Clone, PartialEq, Eq, Debug)]
+pub(crate) struct RecentDirectories {
+	pub(crate) directories: Vec<PathBuf>,
+}
+
+impl RecentDirectories {
+	const FILE: &'static str = "winter/recent.toml";
+	const LIMIT: usize = 16;
+
+	/// Reads the list from the XDG state directory, or starts a new one.
+	pub(crate) fn load() -> Self {
+		let Some(location) = xdg::BaseDirectories::new()
+			.ok()
+			.and_then(|xdg| xdg.find_state_file(Self::FILE))
+		else {
+			return Self::default();
+		};
+		match std::fs::read_to_string(&location).map(|text| toml::from_str(&text)) {
+			Ok(Ok(recent)) => recent,
+			Ok(Err(e)) => {
+				log::warn!("Ignoring invalid {}: {e}", location.display());
+				Self::default()
+			}
+			Err(e) => {
+				log::error!("Failed to read {}: {e}", location.display());
+				Self::default()
+			}
+		}
+	}
+
+	pub(crate) fn save(&self) {
+		let result = xdg::BaseDirectories::new()
+			.map_err(std::io::Error::other)
+			.and_then(|xdg| xdg.place_state_file(Self::FILE))
+			.and_then(|location| {
+				let text = toml::to_string(self).map_err(std::io::Error::other)?;
+				std::fs::write(location, text)
+			});
+		if let Err(e) = result {
+			log::error!("Failed to save recent directories: {e}");
+		}
+	}
+
+	/// Moves a directory to the front of the list, forgetting the oldest if it's full.
+	pub(crate) fn touch(&mut self, dir: PathBuf) {
+		self.directories.retain(|d| d != &dir);
+		self.directories.insert(0, dir);
+		self.directories.truncate(Self::LIMIT);
+	}
+}
+
+/// Options for how the working directory is laid out.
+#[derive(serde::Deserialize, serde::Serialize, Default)]
+pub(crate) struct StorageOptions {
+	/// `directory` to keep a marker file per read article, or `index` to keep them all in one
+	/// file, which is quicker once there are many thousands.
+	pub(crate) read_markers: ReadStorage,
+}
+
+/// Which family body text is set in.
+#[derive(serde::Deserialize, serde::Serialize, Default, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum BodyFont {
+	#[default]
+	Sans,
+	/// There's no built-in serif font, so this needs [`FontOptions::file`].
+	Serif,
+	Mono,
+}
+
+/// Options for the fonts text is shown in.
+#[derive(serde::Deserialize, serde::Serialize, Clone, PartialEq, Debug)]
+pub(crate) struct FontOptions {
+	/// Points body text is shown at. Headings, buttons and small text are scaled along with it.
+	pub(crate) size: f32,
+	/// A TrueType or OpenType font to show text in. With `family = "sans"` it's used for
+	/// everything that isn't monospace; otherwise it's only used for body text.
+	pub(crate) file: Option<PathBuf>,
+	/// `sans`, `serif` or `mono`.
+	pub(crate) family: BodyFont,
+}
+
+impl FontOptions {
+	/// egui's own size for body text.
+	const DEFAULT_SIZE: f32 = 12.5;
+	const SIZES: std::ops::RangeInclusive<f32> = 6.0..=72.0;
+	/// What egui calls the font loaded from [`FontOptions::file`].
+	const CUSTOM: &'static str = "custom";
+
+	/// The size body text is shown at, or the default if the configured one is unusable.
+	pub(crate) fn size(&self) -> f32 {
+		if Self::SIZES.contains(&self.size) {
+			self.size
+		} else {
+			log::warn!("Ignoring font size {}, which isn't between 6 and 72", self.size);
+			Self::DEFAULT_SIZE
+		}
+	}
+
+	/// Reads [`FontOptions::file`], if there is one and it's a font.
+	pub(crate) fn load_file(&self) -> Option<Vec<u8>> {
+		let path = self.file.as_ref()?;
+		let data = std::fs::read(path)
+			.map_err(|e| log::warn!("Couldn't read font {}: {e}", path.display()))
+			.ok()?;
+		ab_glyph::FontRef::try_from_slice(&data)
+			.map_err(|e| log::warn!("{} isn't a usable font: {e}", path.display()))
+			.ok()?;
+		Some(data)
+	}
+
+	/// Loads the custom font, if any, and sizes and sets text in `ctx` accordingly.
+	pub(crate) fn apply(&self, ctx: &Context) {
+		let mut fonts = FontDefinitions::default();
+		let custom = self.load_file();
+		let body_family = match (self.family, custom.is_some()) {
+			(BodyFont::Mono, _) => FontFamily::Monospace,
+			(BodyFont::Serif, true) => FontFamily::Name(Self::CUSTOM.into()),
+			(BodyFont::Serif, false) => {
+				log::warn!("Serif body text needs a font file, so it'll be sans-serif instead");
+				FontFamily::Proportional
+			}
+			(BodyFont::Sans, _) => FontFamily::Proportional,
+		};
+		if let Some(data) = custom {
+			fonts
+				.font_data
+				.insert(Self::CUSTOM.to_string(), FontData::from_owned(data));
+			// Falling back on the built-in fonts for any characters it lacks.
+			let fallback = match body_family {
+				FontFamily::Monospace => FontFamily::Monospace,
+				_ => FontFamily::Proportional,
+			};
+			let mut chain = vec![Self::CUSTOM.to_string()];
+			chain.extend(fonts.families.get(&fallback).cloned().unwrap_or_default());
+			fonts.families.insert(body_family.clone(), chain);
+		}
+		ctx.set_fonts(fonts);
+
+		let scale = self.size() / Self::DEFAULT_SIZE;
+		let mut style = (*ctx.style()).clone();
+		for (text_style, font) in &mut style.text_styles {
+			font.size *= scale;
+			if *text_style == TextStyle::Body {
+				font.family = body_family.clone();
+			}
+		}
+		ctx.set_style(style);
+	}
+}
+
+impl Default for FontOptions {
+	fn default() -> Self {
+		Self {
+			size: Self::DEFAULT_SIZE,
+			file: None,
+			family: BodyFont::default(),
+		}
 	}
 }
 
 #[derive(serde::Deserialize, serde::Serialize, Default)]
 pub(crate) struct Config {
 	pub theme: Theme,
+	pub palette: PaletteOverride,
 	pub window: WindowOptions,
+	pub network: NetworkOptions,
+	pub reader: ReaderOptions,
+	pub storage: StorageOptions,
+	pub font: FontOptions,
+}
+
+#[cfg(test)]
+mod test {
+	use std::path::PathBuf;
+
+	use super::{
+		catppuccin, BodyFont, FontOptions, PaletteOverride, RecentDirectories, Theme, ThemeTemplate,
+	};
+
+	#[test]
+	fn auto_theme_follows_os() {
+		let theme: Theme = toml::Value::from(toml::toml! { light = "Latte"
+		dark = "Mocha" })
+		.try_into()
+		.unwrap();
+		assert!(
+			theme
+				== Theme::Auto {
+					light: ThemeTemplate::Latte,
+					dark: ThemeTemplate::Mocha
+				}
+		);
+		assert!(theme.resolve(false) == Some(&ThemeTemplate::Latte));
+		assert!(theme.resolve(true) == Some(&ThemeTemplate::Mocha));
+
+		let fixed = Theme::default();
+		assert!(fixed.resolve(false) == Some(&ThemeTemplate::Frappe));
+		assert!(fixed.resolve(false) == fixed.resolve(true));
+	}
+
+	#[test]
+	fn overrides_palette_colors() {
+		let palette: PaletteOverride = toml::from_str(
+			r##"
+			blue = "#ff0000"
+			surface0 = "00FF00"
+			text = "not a color"
+			sparkle = "#0000ff"
+			"##,
+		)
+		.unwrap();
+		let theme = palette.apply(catppuccin::FRAPPE);
+		assert_eq!(theme.blue, eframe::egui::Color32::from_rgb(255, 0, 0));
+		assert_eq!(theme.surface0, eframe::egui::Color32::from_rgb(0, 255, 0));
+		assert_eq!(
+			theme,
+			catppuccin::Theme {
+				blue: theme.blue,
+				surface0: theme.surface0,
+				..catppuccin::FRAPPE
+			}
+		);
+	}
+
+	#[test]
+	fn recent_directories_round_trip() {
+		let mut recent = RecentDirectories::default();
+		recent.touch(PathBuf::from("/home/me/work"));
+		recent.touch(PathBuf::from("/home/me/personal"));
+		recent.touch(PathBuf::from("/home/me/work"));
+		assert_eq!(
+			recent.directories,
+			[PathBuf::from("/home/me/work"), PathBuf::from("/home/me/personal")]
+		);
+		let text = toml::to_string(&recent).unwrap();
+		assert_eq!(
+			text,
+			"directories = [\"/home/me/work\", \"/home/me/personal\"]\n"
+		);
+		assert_eq!(toml::from_str::<RecentDirectories>(&text).unwrap(), recent);
+	}
+
+	#[test]
+	fn validates_font_options() {
+		let font: FontOptions = toml::from_str(
+			r#"
+			size = 16.0
+			file = "/nowhere/Serif.ttf"
+			family = "serif"
+			"#,
+		)
+		.unwrap();
+		assert_eq!(font.family, BodyFont::Serif);
+		assert_eq!(font.size(), 16.0);
+		assert!(font.load_file().is_none());
+		assert!(toml::from_str::<FontOptions>("size = 12.0\nfamily = \"fancy\"").is_err());
+
+		let tiny = FontOptions {
+			size: 1.0,
+			..FontOptions::default()
+		};
+		assert_eq!(tiny.size(), FontOptions::default().size());
+		let nan = FontOptions {
+			size: f32::NAN,
+			..FontOptions::default()
+		};
+		assert_eq!(nan.size(), FontOptions::default().size());
+
+		let tmp = tempdir::TempDir::new("winter_font_test").unwrap();
+		let path = tmp.path().join("broken.ttf");
+		std::fs::write(&path, b"not a font").unwrap();
+		let broken = FontOptions {
+			file: Some(path),
+			..FontOptions::default()
+		};
+		assert!(broken.load_file().is_none());
+		assert!(FontOptions::default().load_file().is_none());
+	}
 }
@@ -3,8 +3,71 @@ pub mod feed;
 pub mod state;
 pub mod fetch;
 pub mod document;
+pub mod opml;
 pub mod syndication;
+mod error;
+
+pub use error::Error;
+
+#[cfg(test)]
+mod log_capture;
+
+static FETCH_OPTIONS: std::sync::OnceLock<fetch::FetchOptions> = std::sync::OnceLock::new();
 
 lazy_static::lazy_static! {
-	static ref FETCHER: fetch::Fetcher = fetch::Fetcher::new();
+	static ref FETCHER: fetch::Fetcher =
+		fetch::Fetcher::from_options(FETCH_OPTIONS.get().cloned().unwrap_or_else(default_options));
+}
+
+#[cfg(not(test))]
+fn default_options() -> fetch::FetchOptions {
+	fetch::FetchOptions::default()
+}
+
+/// Tests share the fetcher, so its cache goes somewhere temporary instead of the working
+/// directory.
+#[cfg(test)]
+fn default_options() -> fetch::FetchOptions {
+	let cache_dir = std::env::temp_dir().join(format!("winter-test-cache-{}", std::process::id()));
+	fetch::FetchOptions {
+		cache_dir,
+		..fetch::FetchOptions::default()
+	}
+}
+
+/// Configures the shared fetcher. This only has an effect before the first download starts,
+/// and returns whether it took effect.
+pub fn configure_fetcher(options: fetch::FetchOptions) -> bool {
+	FETCH_OPTIONS.set(options).is_ok()
+}
+
+/// Switches the shared fetcher in or out of offline mode. See [`fetch::Fetcher::set_offline`].
+pub fn set_offline(offline: bool) {
+	FETCHER.set_offline(offline);
+}
+
+/// Whether the shared fetcher is in offline mode.
+#[must_use]
+pub fn is_offline() -> bool {
+	FETCHER.is_offline()
+}
+
+/// How much is in the shared fetcher's HTTP cache.
+#[must_use]
+pub fn cache_stats() -> fetch::CacheStats {
+	FETCHER.cache_stats()
+}
+
+/// Removes unreadable entries from the shared fetcher's HTTP cache. See
+/// [`fetch::Fetcher::check_cache`].
+pub async fn check_cache() -> usize {
+	FETCHER.check_cache().await
+}
+
+/// Empties the shared fetcher's HTTP cache.
+///
+/// # Errors
+/// Fails if the cache's files can't be removed.
+pub fn clear_cache() -> std::io::Result<()> {
+	FETCHER.clear_cache()
 }
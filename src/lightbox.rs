@@ -0,0 +1,147 @@
+//! Shows an article's image enlarged, over everything else.
+
+use eframe::{
+	egui::{
+		self, load::TexturePoll, Align2, Color32, Context, Image, Key, Order, Pos2, Rect, Sense,
+		Vec2,
+	},
+	epaint::FontId,
+};
+
+/// How far the image can be zoomed out and in, relative to fitting the window.
+const MIN_ZOOM: f32 = 0.25;
+const MAX_ZOOM: f32 = 16.0;
+
+/// The image being shown enlarged, if any, and how it's zoomed and panned.
+pub(crate) struct Lightbox {
+	url: Option<String>,
+	zoom: f32,
+	pan: Vec2,
+}
+
+impl Default for Lightbox {
+	fn default() -> Self {
+		Self {
+			url: None,
+			zoom: 1.0,
+			pan: Vec2::ZERO,
+		}
+	}
+}
+
+impl Lightbox {
+	/// Shows the image at `url`, fitted to the window.
+	pub(crate) fn open(&mut self, url: String) {
+		*self = Self {
+			url: Some(url),
+			..Self::default()
+		};
+	}
+
+	pub(crate) fn close(&mut self) {
+		*self = Self::default();
+	}
+
+	pub(crate) fn url(&self) -> Option<&str> {
+		self.url.as_deref()
+	}
+
+	/// Zooms in by `factor`, or out if it's under 1.
+	pub(crate) fn zoom_by(&mut self, factor: f32) {
+		self.zoom = (self.zoom * factor).clamp(MIN_ZOOM, MAX_ZOOM);
+	}
+
+	pub(crate) fn pan_by(&mut self, delta: Vec2) {
+		self.pan += delta;
+	}
+
+	/// Draws the image over the rest of the app, if one is open. Scrolling or pinching zooms,
+	/// dragging pans, and Escape or a click outside the image closes it.
+	pub(crate) fn show(&mut self, ctx: &Context) {
+		let Some(url) = self.url.clone() else {
+			return;
+		};
+		let screen = ctx.screen_rect();
+		let mut close = ctx.input(|i| i.key_pressed(Key::Escape));
+		egui::Area::new("lightbox")
+			.order(Order::Foreground)
+			.fixed_pos(Pos2::ZERO)
+			.show(ctx, |ui| {
+				let background = ui.allocate_rect(screen, Sense::click());
+				ui.painter()
+					.rect_filled(screen, 0.0, Color32::from_black_alpha(220));
+				let image = Image::new(url.as_str());
+				let texture = match image.load_for_size(ctx, screen.size()) {
+					Ok(TexturePoll::Ready { texture }) => texture,
+					Ok(TexturePoll::Pending { .. }) => {
+						let center = Rect::from_center_size(screen.center(), Vec2::splat(32.0));
+						ui.put(center, egui::Spinner::new());
+						close |= background.clicked();
+						return;
+					}
+					Err(e) => {
+						ui.painter().text(
+							screen.center(),
+							Align2::CENTER_CENTER,
+							format!("Couldn't load the image: {e}"),
+							FontId::proportional(16.0),
+							Color32::WHITE,
+						);
+						close |= background.clicked();
+						return;
+					}
+				};
+				// Fit the image inside the window, without blowing small images up.
+				let fit = (screen.size() * 0.9 / texture.size).min_elem().min(1.0);
+				let rect =
+					Rect::from_center_size(screen.center() + self.pan, texture.size * fit * self.zoom);
+				let shown = ui.allocate_rect(rect, Sense::click_and_drag());
+				image.paint_at(ui, rect);
+				if shown.dragged() {
+					self.pan_by(shown.drag_delta());
+				}
+				let (zoom, scroll) = ui.input(|i| (i.zoom_delta(), i.scroll_delta.y));
+				if zoom != 1.0 {
+					self.zoom_by(zoom);
+				} else if scroll != 0.0 {
+					self.zoom_by((scroll / 200.0).exp());
+				}
+				// The background is under the image, so it sees clicks on the image too.
+				close |= background.clicked()
+					&& !background
+						.interact_pointer_pos()
+						.is_some_and(|pos| rect.contains(pos));
+			});
+		if close {
+			self.close();
+		}
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use eframe::epaint::Vec2;
+
+	use super::{Lightbox, MAX_ZOOM};
+
+	#[test]
+	fn opens_and_closes() {
+		let mut lightbox = Lightbox::default();
+		assert_eq!(lightbox.url(), None);
+
+		lightbox.open("https://example.com/a.png".to_string());
+		assert_eq!(lightbox.url(), Some("https://example.com/a.png"));
+		lightbox.zoom_by(100.0);
+		assert_eq!(lightbox.zoom, MAX_ZOOM);
+		lightbox.pan_by(Vec2::new(10.0, 5.0));
+
+		// Opening another image starts it fitted to the window again.
+		lightbox.open("https://example.com/b.png".to_string());
+		assert_eq!(lightbox.url(), Some("https://example.com/b.png"));
+		assert_eq!(lightbox.zoom, 1.0);
+		assert_eq!(lightbox.pan, Vec2::ZERO);
+
+		lightbox.close();
+		assert_eq!(lightbox.url(), None);
+	}
+}
@@ -0,0 +1,43 @@
+//! A logger for tests, which keeps what's logged on each thread so a test can check what it
+//! caused. `#[tokio::test]`s run on a single thread, so this sees what their tasks log too.
+
+use std::{cell::RefCell, sync::Once};
+
+use log::{Level, LevelFilter, Log, Metadata, Record};
+
+thread_local! {
+	static CAPTURED: RefCell<Vec<(Level, String)>> = const { RefCell::new(vec![]) };
+}
+
+struct Capture;
+
+impl Log for Capture {
+	fn enabled(&self, _: &Metadata) -> bool {
+		true
+	}
+
+	fn log(&self, record: &Record) {
+		CAPTURED.with(|captured| {
+			captured
+				.borrow_mut()
+				.push((record.level(), record.args().to_string()));
+		});
+	}
+
+	fn flush(&self) {}
+}
+
+/// Starts capturing what this thread logs, forgetting anything captured before.
+pub(crate) fn start() {
+	static INSTALL: Once = Once::new();
+	INSTALL.call_once(|| {
+		log::set_logger(&Capture).expect("Another logger was set in tests");
+		log::set_max_level(LevelFilter::Trace);
+	});
+	CAPTURED.with(|captured| captured.borrow_mut().clear());
+}
+
+/// Everything this thread has logged since [`start`].
+pub(crate) fn take() -> Vec<(Level, String)> {
+	CAPTURED.with(|captured| std::mem::take(&mut *captured.borrow_mut()))
+}
@@ -0,0 +1,102 @@
+//! Writes the library's and the app's log messages to stderr, filtered by `RUST_LOG`.
+
+use log::{LevelFilter, Log, Metadata, Record};
+
+/// The level logged at when `RUST_LOG` doesn't say otherwise.
+const DEFAULT_LEVEL: LevelFilter = LevelFilter::Warn;
+
+/// Filters like `RUST_LOG`'s usual syntax: comma-separated directives which are either a level,
+/// like `debug`, or a module path and a level, like `winter::fetch=debug`. The most specific
+/// directive matching a message's module decides whether it's shown.
+struct StderrLogger {
+	default: LevelFilter,
+	/// `(module path, level)`, longest path first.
+	modules: Vec<(String, LevelFilter)>,
+}
+
+impl StderrLogger {
+	fn parse(spec: &str) -> Self {
+		let mut logger = Self {
+			default: DEFAULT_LEVEL,
+			modules: vec![],
+		};
+		for directive in spec.split(',').map(str::trim).filter(|d| !d.is_empty()) {
+			match directive.split_once('=') {
+				Some((module, level)) => match level.trim().parse() {
+					Ok(level) => logger.modules.push((module.trim().to_string(), level)),
+					Err(_) => eprintln!("Ignoring invalid RUST_LOG directive {directive}"),
+				},
+				None => match directive.parse() {
+					Ok(level) => logger.default = level,
+					// A bare module path turns everything on for it, as with env_logger.
+					Err(_) => logger.modules.push((directive.to_string(), LevelFilter::Trace)),
+				},
+			}
+		}
+		logger.modules.sort_by_key(|(module, _)| std::cmp::Reverse(module.len()));
+		logger
+	}
+
+	fn level_for(&self, target: &str) -> LevelFilter {
+		self.modules
+			.iter()
+			.find(|(module, _)| {
+				target == module
+					|| target
+						.strip_prefix(module.as_str())
+						.is_some_and(|rest| rest.starts_with("::"))
+			})
+			.map_or(self.default, |(_, level)| *level)
+	}
+
+	fn max_level(&self) -> LevelFilter {
+		self.modules
+			.iter()
+			.map(|(_, level)| *level)
+			.fold(self.default, Ord::max)
+	}
+}
+
+impl Log for StderrLogger {
+	fn enabled(&self, metadata: &Metadata) -> bool {
+		metadata.level() <= self.level_for(metadata.target())
+	}
+
+	fn log(&self, record: &Record) {
+		if self.enabled(record.metadata()) {
+			eprintln!("[{} {}] {}", record.level(), record.target(), record.args());
+		}
+	}
+
+	fn flush(&self) {}
+}
+
+/// Sends log messages to stderr, going by `RUST_LOG`. Only warnings and errors are shown if
+/// it's unset.
+pub(crate) fn init() {
+	let logger = StderrLogger::parse(&std::env::var("RUST_LOG").unwrap_or_default());
+	log::set_max_level(logger.max_level());
+	if log::set_boxed_logger(Box::new(logger)).is_err() {
+		eprintln!("A logger was already set up");
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use log::LevelFilter;
+
+	use super::StderrLogger;
+
+	#[test]
+	fn parses_rust_log() {
+		let logger = StderrLogger::parse("info,winter::fetch=debug, winter::state=off");
+		assert_eq!(logger.level_for("winter"), LevelFilter::Info);
+		assert_eq!(logger.level_for("winter::fetch"), LevelFilter::Debug);
+		assert_eq!(logger.level_for("winter::fetch::mock"), LevelFilter::Debug);
+		assert_eq!(logger.level_for("winter::fetcher"), LevelFilter::Info);
+		assert_eq!(logger.level_for("winter::state::inotify"), LevelFilter::Off);
+		assert_eq!(logger.max_level(), LevelFilter::Debug);
+
+		assert_eq!(StderrLogger::parse("").level_for("winter"), LevelFilter::Warn);
+	}
+}
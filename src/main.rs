@@ -6,20 +6,28 @@ use figment::{
 	providers::{Format, Serialized, Toml},
 	Figment, Profile,
 };
-use gui_config::Theme;
+use gui_config::{PaletteOverride, ReaderOptions, RecentDirectories, Theme};
 
-use std::{path::PathBuf, sync::Arc};
+use std::{path::PathBuf, sync::Arc, time::Duration};
 use tokio::runtime::Runtime;
 use winter::{
-	state::Database, document::media::TMP,
+	state::{validate_feed, Database, FeedFormat, FeedValidation, ReadStorage},
+	document::media::TMP,
+	syndication::Feed,
 };
 
 struct App {
 	inner: InnerApp,
-	theme: Option<Theme>,
+	theme: Theme,
+	palette: PaletteOverride,
+	/// Whether the OS preferred dark mode when the theme was last applied.
+	applied_dark_mode: Option<bool>,
+	/// Used when the platform can't tell us its preference.
+	default_dark_mode: bool,
 	rt: Arc<Runtime>,
 }
 
+#[allow(clippy::large_enum_variant)]
 enum InnerApp {
 	PickDirectory(PickDirectoryApp),
 	Working(main_app::MainApp),
@@ -27,11 +35,13 @@ enum InnerApp {
 
 impl eframe::App for App {
 	fn update(&mut self, ctx: &eframe::egui::Context, frame: &mut eframe::Frame) {
-		if let Some(theme) = self.theme.take() {
-			match theme {
-				Theme::Template(template) => template.apply(ctx),
-				Theme::ExplicitTheme(visuals) => ctx.set_visuals(*visuals),
-			}
+		let dark_mode = frame
+			.info()
+			.system_theme
+			.map_or(self.default_dark_mode, |theme| theme == eframe::Theme::Dark);
+		if self.applied_dark_mode != Some(dark_mode) {
+			self.theme.apply(ctx, dark_mode, &self.palette);
+			self.applied_dark_mode = Some(dark_mode);
 		}
 		match &mut self.inner {
 			InnerApp::PickDirectory(p) => {
@@ -44,10 +54,14 @@ impl eframe::App for App {
 	}
 }
 
-struct PickDirectoryApp;
+struct PickDirectoryApp {
+	options: ReaderOptions,
+	refresh_interval: Option<Duration>,
+	recent: RecentDirectories,
+	read_storage: ReadStorage,
+}
 
 impl PickDirectoryApp {
-	#[allow(clippy::unused_self)]
 	fn update(
 		&mut self,
 		ctx: &eframe::egui::Context,
@@ -61,12 +75,24 @@ impl PickDirectoryApp {
 				ui.heading("Select a working directory");
 				ui.label("(To avoid this step in the future, modify your application menu entry to include the target as a command-line argument)");
 				ui.separator();
-				if ui.button("Pick a Directory").clicked() {
-					if let Some(chosen) = rfd::FileDialog::new().pick_folder() {
-						let database = Database::from_dir(chosen);
-						out = Some(main_app::MainApp::from_db(database));
+				let mut chosen = None;
+				for dir in &self.recent.directories {
+					if ui.button(dir.display().to_string()).clicked() {
+						chosen = Some(dir.clone());
 					}
 				}
+				if ui.button("Pick a Directory").clicked() {
+					chosen = rfd::FileDialog::new().pick_folder();
+				}
+				if let Some(chosen) = chosen {
+					let database = Database::with_read_storage(chosen, self.read_storage);
+					out = Some(main_app::MainApp::from_db(
+						database,
+						self.options.clone(),
+						self.refresh_interval,
+						std::mem::take(&mut self.recent),
+					));
+				}
 			});
 		});
 		out
@@ -82,11 +108,120 @@ struct Args {
 	config_path: Vec<PathBuf>,
 	/// The path to the target directory. If this is unset, you will need to choose a directory at runtime.
 	target_directory: Option<PathBuf>,
+	/// Do something to the target directory and exit, instead of opening the GUI.
+	#[command(subcommand)]
+	command: Option<Command>,
+}
+
+#[derive(clap::Subcommand)]
+enum Command {
+	/// Fetch a feed (or a page which links to one) and subscribe to it.
+	Add { url: String },
+	/// Print every subscription's URL and title.
+	List,
+	/// Print every subscription as OPML.
+	ExportOpml,
+	/// Check what's at a feed URL, without subscribing to it.
+	Validate { url: String },
+	/// Mark the articles another reader's JSON export says were read as read here too.
+	ImportRead { path: PathBuf },
+}
+
+/// Runs a headless command against the database, returning the process's exit code.
+fn run_command(
+	command: Command,
+	target_dir: PathBuf,
+	read_storage: ReadStorage,
+	rt: &Runtime,
+) -> i32 {
+	rt.block_on(async {
+		let database = Database::with_read_storage(target_dir, read_storage);
+		database.reload().await;
+		let code = match command {
+			Command::Add { url } => match database.subscribe_url(&url).await {
+				Ok(pub_url) => {
+					println!("Subscribed to {pub_url}");
+					0
+				}
+				Err(e) => {
+					eprintln!("Failed to subscribe to {url}: {e}");
+					1
+				}
+			},
+			Command::List => {
+				for (pub_url, feed) in database.get_subscriptions().await {
+					let title = match &*feed {
+						Feed::Atom(a) => a.title().to_string(),
+						Feed::RSS(r) => r.title().to_string(),
+					};
+					println!("{pub_url}\t{title}");
+				}
+				0
+			}
+			Command::ExportOpml => {
+				print!("{}", winter::opml::export(&database.get_subscriptions().await));
+				0
+			}
+			Command::Validate { url } => match validate_feed(&url).await {
+				Ok(report) => {
+					print_validation(&report);
+					i32::from(!report.is_feed())
+				}
+				Err(e) => {
+					eprintln!("Failed to fetch {url}: {e}");
+					1
+				}
+			},
+			Command::ImportRead { path } => {
+				let imported = match std::fs::read_to_string(&path) {
+					Ok(json) => database.import_read_state(&json).await.map_err(|e| e.to_string()),
+					Err(e) => Err(e.to_string()),
+				};
+				match imported {
+					Ok(count) => {
+						println!("Marked {count} articles read");
+						0
+					}
+					Err(e) => {
+						eprintln!("Failed to import {}: {e}", path.display());
+						1
+					}
+				}
+			}
+		};
+		database.shutdown().await;
+		code
+	})
+}
+
+fn print_validation(report: &FeedValidation) {
+	match &report.format {
+		FeedFormat::Rss | FeedFormat::Atom => {
+			let kind = if report.format == FeedFormat::Rss { "RSS" } else { "Atom" };
+			let title = report.title.as_deref().unwrap_or("(untitled)");
+			println!("{kind} feed \"{title}\" with {} items", report.items);
+		}
+		FeedFormat::JsonFeed => println!("JSON Feed"),
+		FeedFormat::Html(found) => {
+			println!("Web page linking to feeds:");
+			for feed in found {
+				let guess = if feed.guessed { " (guess)" } else { "" };
+				println!("\t{}{guess}", feed.href);
+			}
+		}
+		FeedFormat::Invalid(e) => println!("Not a feed: {e}"),
+	}
+	for warning in &report.warnings {
+		println!("Warning: {warning}");
+	}
 }
 
 mod gui_config;
+mod lightbox;
+mod logging;
 
 fn main() {
+	logging::init();
 	// Parse arguments
 	let args = Args::parse();
 	// Load config
@@ -101,23 +236,59 @@ fn main() {
 		config = config.merge(Toml::file(location));
 	}
 	let config: gui_config::Config = config.extract().expect("Invalid config");
+	let refresh_interval = config.network.refresh_interval();
+	winter::configure_fetcher(config.network.into());
+	winter::state::set_max_body_len(config.reader.max_article_bytes);
 	let rt = Arc::new(Runtime::new().expect("Init runtime"));
+	if let Some(command) = args.command {
+		let Some(target_dir) = args.target_directory else {
+			eprintln!("A target directory is needed to run commands.");
+			std::process::exit(2);
+		};
+		std::process::exit(run_command(command, target_dir, config.storage.read_markers, &rt));
+	}
+	rt.spawn(async {
+		let removed = winter::check_cache().await;
+		if removed > 0 {
+			log::warn!("Removed {removed} unreadable entries from the HTTP cache");
+		}
+	});
 	// Build app
+	let recent = RecentDirectories::load();
 	let app = if let Some(target_dir) = args.target_directory {
 		let _rt = rt.enter();
-		InnerApp::Working(main_app::MainApp::from_db(Database::from_dir(target_dir)))
+		InnerApp::Working(main_app::MainApp::from_db(
+			Database::with_read_storage(target_dir, config.storage.read_markers),
+			config.reader,
+			refresh_interval,
+			recent,
+		))
 	} else {
-		InnerApp::PickDirectory(PickDirectoryApp)
+		InnerApp::PickDirectory(PickDirectoryApp {
+			options: config.reader,
+			refresh_interval,
+			recent,
+			read_storage: config.storage.read_markers,
+		})
 	};
-	let app = App {
+	let font = config.font;
+	let mut app = App {
 		inner: app,
 		rt: rt.clone(),
-		theme: Some(config.theme),
+		theme: config.theme,
+		palette: config.palette,
+		applied_dark_mode: None,
+		default_dark_mode: true,
 	};
 	eframe::run_native(
 		"winter",
 		config.window.into(),
-		Box::new(move |_| Box::new(app)),
+		Box::new(move |cc| {
+			egui_extras::install_image_loaders(&cc.egui_ctx);
+			font.apply(&cc.egui_ctx);
+			app.default_dark_mode = cc.egui_ctx.style().visuals.dark_mode;
+			Box::new(app)
+		}),
 	)
 	.expect("App crashed");
 	if let Some(tmp) = TMP.write().unwrap().take() {
@@ -1,64 +1,346 @@
-use std::{collections::BTreeMap, string::ToString};
+use std::{
+	collections::{BTreeMap, BTreeSet},
+	path::PathBuf,
+	str::FromStr,
+	string::ToString,
+	sync::Arc,
+	time::{Duration, Instant},
+};
+
+use chrono::{DateTime, Datelike, Local, Utc};
 
 use eframe::{
-	egui::{self, CentralPanel, CollapsingHeader, ScrollArea, SidePanel, TopBottomPanel},
+	egui::{
+		self, load::TexturePoll, Align, Align2, Button, CentralPanel, CollapsingHeader, Layout,
+		RichText, ScrollArea, SidePanel, TextEdit, TopBottomPanel,
+	},
 	epaint::{Color32, Vec2},
 	Frame,
 };
 
-use tokio::runtime::Runtime;
+use tokio::{runtime::Runtime, task::JoinHandle};
+use crate::{
+	gui_config::{ListDensity, ReaderOptions, RecentDirectories, TimestampStyle},
+	lightbox::Lightbox,
+};
 use winter::{
-	document::{media::MaybeLoaded, DocumentNode},
-	state::{ChannelFromBytesError, CommonArticle, Database, WFeed},
-	syndication::Feed,
+	document::{
+		media::{Autoload, DownloadAndOpen, MaybeLoaded, MediaHandler},
+		readable::{tick_page, ReadableArticle},
+		find::{begin_find, end_find},
+		classify_link, flag_sponsored_links, image_loaders_installed, set_link_click,
+		take_enlarge_request, video_link, DocumentNode, LinkClick, LinkKind,
+	},
+	feed::FeedLookup,
+	fetch::CacheStats,
+	state::{
+		meta::{jitter_seed, refresh_due_jittered, Credentials},
+		gravatar_url, BulkWrite, ChannelFromBytesError, CommonArticle, Database, FeedSort,
+		FeedSortKey, FeedValidation, RefreshError, RefreshProgress, Unsubscribed, QUARANTINE_DIR,
+	},
+	syndication::{Feed, TextDirection},
 };
 
+/// The channel ID used to select every article from every feed at once.
+pub(crate) const ALL_ARTICLES: &str = "winter:all-articles";
+
+/// How often to check whether any subscription is due to be refreshed. Often enough that the
+/// refreshes spread across the [`STARTUP_JITTER`](winter::state::meta::STARTUP_JITTER) really
+/// are spread out.
+const SCHEDULE_CHECK: Duration = Duration::from_secs(5);
+
+/// How long the notice of what a refresh found stays up.
+const NOTICE_DURATION: Duration = Duration::from_secs(5);
+
+/// How long an unsubscribe can be undone for.
+const UNDO_DURATION: Duration = Duration::from_secs(10);
+
+/// The most bytes an icon may be. Anything bigger isn't worth holding to draw at line height.
+const MAX_ICON_SIZE: u64 = 256 * 1024;
+
+/// What clicking a feed's links can do, offered in its header.
+const LINK_CLICKS: [(Option<LinkClick>, &str); 3] = [
+	(None, "Default"),
+	(Some(LinkClick::Open), "Open in browser"),
+	(Some(LinkClick::OpenAndMinimize), "Open in browser and minimize"),
+];
+
+/// What the channel list can be sorted by, offered above it.
+const FEED_SORTS: [(FeedSort, &str); 4] = [
+	(FeedSort::Manual, "As arranged"),
+	(FeedSort::Title, "By title"),
+	(FeedSort::Unread, "By unread"),
+	(FeedSort::Updated, "By last update"),
+];
+
+/// The per-feed refresh intervals offered in a channel's header, in seconds.
+const REFRESH_INTERVALS: [(Option<u64>, &str); 7] = [
+	(None, "Default"),
+	(Some(15 * 60), "Every 15 minutes"),
+	(Some(60 * 60), "Hourly"),
+	(Some(6 * 60 * 60), "Every 6 hours"),
+	(Some(24 * 60 * 60), "Daily"),
+	(Some(7 * 24 * 60 * 60), "Weekly"),
+	(Some(0), "Never"),
+];
+
 pub(crate) struct MainApp {
-	pub(crate) database: Database,
+	pub(crate) database: Arc<Database>,
+	pub(crate) options: ReaderOptions,
 	pub(crate) selection: Option<Selection>,
 	pub(crate) add_channel_working: Option<AddChannel>,
-	pub sub_refresh_progress: BTreeMap<String, MaybeLoaded<Feed>>,
+	/// Refreshes which are in flight, by subscription URL.
+	pub(crate) refreshing: BTreeMap<String, JoinHandle<Result<usize, RefreshError>>>,
+	/// How many of the refreshes started since they last all finished are done.
+	pub(crate) refresh_progress: RefreshProgress,
+	/// How the last refresh of each subscription failed, if it did.
+	pub(crate) refresh_errors: BTreeMap<String, RefreshError>,
+	/// New articles found by refreshes since they last all finished.
+	pub(crate) new_articles: usize,
+	/// The status line, and the [`Database::generation`] it was worked out for.
+	pub(crate) status: Option<(u64, String)>,
+	/// What each subscription is sorted by, and the [`Database::generation`] it was worked
+	/// out for.
+	pub(crate) feed_sort_keys: Option<(u64, BTreeMap<String, FeedSortKey>)>,
+	/// List every channel, even past [`ReaderOptions::channel_limit`].
+	pub(crate) show_all_channels: bool,
+	/// A message for the user, like what the last batch of refreshes found, and until when to
+	/// show it.
+	pub(crate) notice: Option<(String, Instant)>,
+	/// Article previews by `(pub_url, id)`, since deriving them means parsing the body.
+	pub(crate) snippets: BTreeMap<(String, String), String>,
+	/// Decides whether audio and video are downloaded or streamed.
+	pub(crate) media_handler: Box<dyn MediaHandler>,
+	/// Working directories which can be switched to.
+	pub(crate) recent: RecentDirectories,
+	/// How often subscriptions are refreshed automatically, unless they say otherwise.
+	pub(crate) refresh_interval: Option<Duration>,
+	/// When each subscription last started refreshing, this session.
+	pub(crate) last_refreshed: BTreeMap<String, Instant>,
+	/// When the current database was opened, which the first refreshes are spread out from.
+	pub(crate) session_started: Instant,
+	pub(crate) next_schedule_check: Instant,
+	/// Held while refreshes are running, so the database doesn't rescan for each feed they
+	/// write.
+	pub(crate) bulk_write: Option<BulkWrite>,
+	/// The last subscription removed, until it can no longer be undone.
+	pub(crate) undo_unsubscribe: Option<(Unsubscribed, Instant)>,
+	/// The HTTP cache's size, counted when the cache menu opens.
+	pub(crate) cache_stats: Option<CacheStats>,
+	/// Hide the side panels while an article is open, so it gets the whole window.
+	pub(crate) reading_focus: bool,
+	/// Searching the open article's text, while the find bar is open.
+	pub(crate) find: Option<FindBar>,
+	/// An image from the open article, shown enlarged over everything else.
+	pub(crate) lightbox: Lightbox,
+	/// Feeds' icons and authors' avatars, by URL.
+	pub(crate) icons: Icons,
 }
 
+/// Small images shown beside a line of text, by URL. See [`line_icon`].
+pub(crate) type Icons = BTreeMap<String, MaybeLoaded<Arc<[u8]>>>;
+
 pub(crate) struct Selection {
 	pub(crate) channel_id: String,
 	pub(crate) article: Option<SelectedArticle>,
+	/// Only articles in at least one of these categories are listed, unless it's empty.
+	pub(crate) category_filter: BTreeSet<String>,
+	pub(crate) list_scroll: ListScroll,
+	pub(crate) picks: Picks,
+}
+
+/// An article in the list, as `(pub_url, id)`.
+type ArticleKey = (String, String);
+
+/// The articles ticked in the list, to be marked all at once.
+#[derive(Default, Debug, PartialEq)]
+pub(crate) struct Picks {
+	picked: BTreeSet<ArticleKey>,
+	/// The article last ticked or unticked on its own, which shift-clicking picks a range from.
+	anchor: Option<ArticleKey>,
+}
+
+impl Picks {
+	fn contains(&self, key: &ArticleKey) -> bool {
+		self.picked.contains(key)
+	}
+
+	fn len(&self) -> usize {
+		self.picked.len()
+	}
+
+	fn is_empty(&self) -> bool {
+		self.picked.is_empty()
+	}
+
+	fn toggle(&mut self, key: ArticleKey) {
+		if !self.picked.remove(&key) {
+			self.picked.insert(key.clone());
+		}
+		self.anchor = Some(key);
+	}
+
+	/// Picks every article from the anchor to `key`, both included, going by `order`, the
+	/// list as it's shown. Without an anchor in the list, this just toggles `key`.
+	fn pick_range(&mut self, key: ArticleKey, order: &[ArticleKey]) {
+		let anchor = self.anchor.as_ref().and_then(|anchor| order.iter().position(|k| k == anchor));
+		let (Some(anchor), Some(end)) = (anchor, order.iter().position(|k| *k == key)) else {
+			self.toggle(key);
+			return;
+		};
+		let range = anchor.min(end)..=anchor.max(end);
+		self.picked.extend(order[range].iter().cloned());
+	}
+
+	fn clear(&mut self) {
+		*self = Self::default();
+	}
+
+	/// Whether the bulk action marks the picked articles read. It only marks them unread if
+	/// they're all read already, so a mix of read and unread articles all end up read.
+	fn marks_read(&self, has_read: impl Fn(&ArticleKey) -> bool) -> bool {
+		!self.picked.iter().all(has_read)
+	}
+
+	fn keys(&self) -> Vec<ArticleKey> {
+		self.picked.iter().cloned().collect()
+	}
+}
+
+/// Where the article list was scrolled to, so it can be put back after an article has been
+/// read, in case the list was hidden meanwhile.
+#[derive(Default, Debug, PartialEq)]
+pub(crate) struct ListScroll {
+	offset: f32,
+	/// Whether the list should be scrolled back to `offset` the next time it's shown.
+	restore: bool,
+}
+
+impl ListScroll {
+	/// Records where the list is scrolled to, as it's shown.
+	fn remember(&mut self, offset: f32) {
+		self.offset = offset;
+	}
+
+	/// Asks for the list to be scrolled back to where it was, when it's next shown.
+	fn return_to(&mut self) {
+		self.restore = true;
+	}
+
+	/// The offset to scroll the list to, once after [`ListScroll::return_to`].
+	fn take_restore(&mut self) -> Option<f32> {
+		std::mem::take(&mut self.restore).then_some(self.offset)
+	}
+}
+
+/// The find bar over the open article.
+#[derive(Default)]
+pub(crate) struct FindBar {
+	query: String,
+	/// Which match is picked out, counting from 0 in the order they're shown.
+	current: usize,
+	/// How many matches there were when the article was last shown.
+	count: usize,
+	/// Whether the picked match should be scrolled to the next time it's shown.
+	scroll: bool,
+	/// Whether the query box should take the keyboard focus.
+	focus: bool,
+}
+
+impl FindBar {
+	/// Picks out the next or previous match, going round at either end.
+	fn step(&mut self, forward: bool) {
+		if self.count == 0 {
+			return;
+		}
+		self.current = if forward {
+			(self.current + 1) % self.count
+		} else {
+			(self.current + self.count - 1) % self.count
+		};
+		self.scroll = true;
+	}
+
+	/// Shows the bar, returning whether it should stay open.
+	fn show(&mut self, ui: &mut egui::Ui) -> bool {
+		let mut close = false;
+		ui.horizontal(|ui| {
+			let edit = ui.add(TextEdit::singleline(&mut self.query).hint_text("Find in article"));
+			if std::mem::take(&mut self.focus) {
+				edit.request_focus();
+			}
+			if edit.changed() {
+				self.current = 0;
+				self.scroll = true;
+			}
+			if edit.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter)) {
+				self.step(!ui.input(|i| i.modifiers.shift));
+				edit.request_focus();
+			}
+			if ui.button("<").on_hover_text("Previous match").clicked() {
+				self.step(false);
+			}
+			if ui.button(">").on_hover_text("Next match").clicked() {
+				self.step(true);
+			}
+			if self.count > 0 {
+				ui.label(format!("{} of {}", self.current + 1, self.count));
+			} else if !self.query.is_empty() {
+				ui.weak("No matches");
+			}
+			close = ui.button("Close").clicked() || ui.input(|i| i.key_pressed(egui::Key::Escape));
+		});
+		!close
+	}
 }
 
 pub(crate) struct SelectedArticle {
 	article: CommonArticle,
 	tree: DocumentNode,
 	links: Vec<DocumentNode>,
+	full: Option<FullArticle>,
+}
+
+/// The article as it is on its web page, for feeds which only include a summary.
+enum FullArticle {
+	Fetching(MaybeLoaded<ReadableArticle>),
+	/// Holds whichever of the feed's version and the full article isn't being shown.
+	Fetched {
+		other: DocumentNode,
+		showing_full: bool,
+	},
 }
 
 impl SelectedArticle {
 	pub fn populate_links(mut self, _rt: &Runtime) -> Self {
+		let mut enclosures = self.article.enclosure_nodes();
 		self.links = self
 			.article
 			.links
 			.iter()
-			.map(|(label, mime, href)| {
-				if href.starts_with("https://www.youtube.com/watch")
-					|| href.starts_with("https://youtu.be")
-				{
-					return DocumentNode::Link {
-						label: vec![DocumentNode::TextLeaf("YouTube Video".to_string())],
-						mime: "text/html".to_string(),
-						url: href.to_string(),
-					};
+			.filter(|(_, _, href, rel)| {
+				rel != "enclosure" && !self.article.enclosures.iter().any(|(url, _, _)| url == href)
+			})
+			.map(|(label, mime, href, _)| {
+				if let Some(link) = video_link(href) {
+					return link;
 				}
 
 				if mime.starts_with("audio/") {
 					return DocumentNode::Audio {
 						label: label.to_string(),
+						mime: mime.clone(),
 						fetched: MaybeLoaded::NotStarted(href.to_string()),
+						requested: false,
 					};
 				}
 
 				if mime.starts_with("video/") {
 					return DocumentNode::Video {
 						label: label.to_string(),
+						mime: mime.clone(),
 						fetched: MaybeLoaded::NotStarted(href.to_string()),
+						requested: false,
 					};
 				}
 
@@ -66,28 +348,454 @@ impl SelectedArticle {
 					url: href.clone(),
 					mime: mime.clone(),
 					label: vec![DocumentNode::TextLeaf(label.clone())],
+					rel: vec![],
+					title: None,
 				}
 			})
 			.collect();
+		self.links.append(&mut enclosures);
 		self
 	}
+
+	/// Stops any media downloads this article started, since nobody is going to look at them.
+	pub fn cancel_downloads(&mut self, rt: &Runtime) {
+		self.tree.cancel(rt);
+		for node in &mut self.links {
+			node.cancel(rt);
+		}
+	}
+}
+
+/// Formats a duration like a media player would, as `M:SS` or `H:MM:SS`.
+fn format_duration(duration: Duration) -> String {
+	let seconds = duration.as_secs();
+	let (hours, minutes, seconds) = (seconds / 3600, seconds / 60 % 60, seconds % 60);
+	if hours > 0 {
+		format!("{hours}:{minutes:02}:{seconds:02}")
+	} else {
+		format!("{minutes}:{seconds:02}")
+	}
+}
+
+/// Describes a feed's refresh interval override, including ones set by hand which aren't
+/// in [`REFRESH_INTERVALS`].
+fn refresh_interval_label(secs: Option<u64>) -> String {
+	match REFRESH_INTERVALS.iter().find(|(option, _)| *option == secs) {
+		Some((_, label)) => format!("Refresh: {label}"),
+		None => format!(
+			"Refresh: every {}",
+			format_duration(Duration::from_secs(secs.unwrap_or_default()))
+		),
+	}
+}
+
+/// What the button which flips an article between read and unread says.
+fn read_toggle_label(read: bool) -> &'static str {
+	if read {
+		"Mark as Unread"
+	} else {
+		"Mark as Read"
+	}
+}
+
+/// Marks an article unread if it's read, or read if it isn't, returning whether it's now read.
+async fn toggle_read(database: &Database, pub_url: &str, article_id: &str) -> bool {
+	if database.has_read(pub_url, article_id).await {
+		database.unread(pub_url, article_id).await;
+		false
+	} else {
+		database.read(pub_url, article_id).await;
+		true
+	}
+}
+
+/// Shows an icon at `url`, like a feed's or an author's avatar, the size of a line of text.
+/// Nothing is shown while it loads, or if it can't be or is over [`MAX_ICON_SIZE`], rather
+/// than a broken image. It's downloaded through [`FETCHER`](winter::FETCHER) into `icons`,
+/// so it goes through the proxy and isn't fetched at all while offline.
+fn line_icon(ui: &mut egui::Ui, rt: &Runtime, icons: &mut Icons, url: &str) {
+	if !image_loaders_installed(ui.ctx()) {
+		return;
+	}
+	let icon = icons
+		.entry(url.to_string())
+		.or_insert_with(|| MaybeLoaded::NotStarted(url.to_string()));
+	rt.block_on(icon.tick_limited(MAX_ICON_SIZE));
+	let bytes = match icon {
+		MaybeLoaded::Done(_, Ok(bytes)) => bytes.clone(),
+		MaybeLoaded::NotStarted(_) | MaybeLoaded::Working(_) => {
+			ui.ctx().request_repaint_after(Duration::from_millis(100));
+			return;
+		}
+		// Including `TooLarge`, which is as good as having no icon.
+		_ => return,
+	};
+	let size = Vec2::splat(ui.text_style_height(&egui::TextStyle::Button));
+	let image = egui::Image::from_bytes(format!("bytes://{url}"), bytes).fit_to_exact_size(size);
+	if let Ok(TexturePoll::Ready { .. }) = image.load_for_size(ui.ctx(), size) {
+		ui.add(image);
+	}
+}
+
+/// Whether the channels panel should explain how to get started instead of listing feeds. It
+/// gets out of the way once the New Subscription panel is open.
+fn show_onboarding(subscriptions: usize, adding_channel: bool) -> bool {
+	subscriptions == 0 && !adding_channel
+}
+
+/// Which of the channels and articles panels to show. The article list stays beside an open
+/// article, unless reading is focused.
+fn visible_panels(
+	channel_selected: bool,
+	article_open: bool,
+	reading_focus: bool,
+) -> (bool, bool) {
+	let show_channels = !channel_selected;
+	let show_articles = channel_selected && !(article_open && reading_focus);
+	(show_channels, show_articles)
+}
+
+/// Describes how long before `now` something happened, like "3 hours ago". Anything a year
+/// old or more just gets its date.
+fn humanize(ts: DateTime<Local>, now: DateTime<Local>) -> String {
+	let plural = |n: i64, unit: &str| {
+		if n == 1 {
+			format!("1 {unit} ago")
+		} else {
+			format!("{n} {unit}s ago")
+		}
+	};
+	let delta = now - ts;
+	match delta.num_days() {
+		_ if delta.num_minutes() < 1 => "just now".to_string(),
+		_ if delta.num_hours() < 1 => plural(delta.num_minutes(), "minute"),
+		0 => plural(delta.num_hours(), "hour"),
+		1 => "yesterday".to_string(),
+		days @ 2..=6 => plural(days, "day"),
+		days @ 7..=29 => plural(days / 7, "week"),
+		days @ 30..=364 => plural(days / 30, "month"),
+		_ => ts.date_naive().to_string(),
+	}
+}
+
+/// A header the article list can be grouped under, from newest to oldest.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum Bucket {
+	Today,
+	Yesterday,
+	ThisWeek,
+	ThisMonth,
+	Older,
+	/// Feeds which don't date their articles get the Unix epoch.
+	Undated,
+}
+
+impl Bucket {
+	fn label(self) -> &'static str {
+		match self {
+			Bucket::Today => "Today",
+			Bucket::Yesterday => "Yesterday",
+			Bucket::ThisWeek => "This Week",
+			Bucket::ThisMonth => "This Month",
+			Bucket::Older => "Older",
+			Bucket::Undated => "Undated",
+		}
+	}
+}
+
+/// Which calendar-based group `ts` falls into as of `now`. Weeks start on Monday, and anything
+/// from the future counts as today.
+fn date_bucket(ts: DateTime<Local>, now: DateTime<Local>) -> Bucket {
+	if ts.timestamp() == 0 {
+		return Bucket::Undated;
+	}
+	let (day, today) = (ts.date_naive(), now.date_naive());
+	if day >= today {
+		Bucket::Today
+	} else if today.pred_opt() == Some(day) {
+		Bucket::Yesterday
+	} else if day.iso_week() == today.iso_week() {
+		Bucket::ThisWeek
+	} else if (day.year(), day.month()) == (today.year(), today.month()) {
+		Bucket::ThisMonth
+	} else {
+		Bucket::Older
+	}
 }
 
 #[derive(Default)]
 pub(crate) struct AddChannel {
 	pub url: String,
-	pub fetch_progress: Option<MaybeLoaded<WFeed>>,
+	pub lookup: Option<FeedLookup>,
+	/// What's questionable about the feed `lookup` found, once it's found one.
+	pub report: Option<FeedValidation>,
+	/// Whether the user has already clicked "Commit" once for a feed they're subscribed to.
+	pub confirm_merge: bool,
+	/// Whether the feed is pasted in as XML rather than fetched from `url`, which is then only
+	/// the key it's stored under.
+	pub paste_xml: bool,
+	pub raw_xml: String,
+	/// `raw_xml` parsed as of its last edit.
+	pub parsed_xml: Option<Result<Feed, winter::Error>>,
+}
+
+/// Points out any files the database couldn't make sense of and moved out of the way.
+fn quarantine_notice(database: &Database) -> Option<(String, Instant)> {
+	let quarantined = database.quarantined();
+	(quarantined > 0).then(|| {
+		let message = format!(
+			"Moved {quarantined} unreadable file(s) into {}",
+			database.dir().join(QUARANTINE_DIR).display()
+		);
+		(message, Instant::now() + NOTICE_DURATION)
+	})
+}
+
+/// Parses feed XML pasted in by the user.
+fn parse_pasted_feed(xml: &str) -> Result<Feed, winter::Error> {
+	Feed::from_str(xml.trim())
+}
+
+impl Drop for MainApp {
+	/// Refreshes are spawned onto the runtime, which outlives the app, so they're stopped
+	/// rather than left to finish writing to a database nothing is showing.
+	fn drop(&mut self) {
+		self.cancel_refreshes();
+	}
 }
 
 impl MainApp {
-	pub(crate) fn from_db(database: Database) -> Self {
+	pub(crate) fn from_db(
+		database: Database,
+		options: ReaderOptions,
+		refresh_interval: Option<Duration>,
+		mut recent: RecentDirectories,
+	) -> Self {
+		recent.touch(database.dir().to_path_buf());
+		recent.save();
+		let notice = quarantine_notice(&database);
+		let media_handler = Box::new(Autoload {
+			handler: DownloadAndOpen,
+			media: options.autoload_media,
+			images: options.autoload_images,
+		});
 		Self {
-			database,
+			reading_focus: options.focus_reading,
+			find: None,
+			lightbox: Lightbox::default(),
+			icons: BTreeMap::new(),
+			database: Arc::new(database),
+			options,
 			selection: None,
 			add_channel_working: None,
-			sub_refresh_progress: BTreeMap::new(),
+			refreshing: BTreeMap::new(),
+			refresh_progress: RefreshProgress::default(),
+			refresh_errors: BTreeMap::new(),
+			new_articles: 0,
+			status: None,
+			feed_sort_keys: None,
+			show_all_channels: false,
+			notice,
+			snippets: BTreeMap::new(),
+			media_handler,
+			recent,
+			refresh_interval,
+			last_refreshed: BTreeMap::new(),
+			session_started: Instant::now(),
+			next_schedule_check: Instant::now(),
+			bulk_write: None,
+			undo_unsubscribe: None,
+			cache_stats: None,
+		}
+	}
+
+	/// Stops every refresh which is still running.
+	fn cancel_refreshes(&mut self) {
+		for task in std::mem::take(&mut self.refreshing).into_values() {
+			task.abort();
+		}
+		self.refresh_progress.reset();
+		self.bulk_write = None;
+	}
+
+	/// Closes the current working directory and opens another in its place.
+	fn switch_profile(&mut self, dir: PathBuf, rt: &Runtime) {
+		self.cancel_refreshes();
+		if let Some(Selection {
+			article: Some(article),
+			..
+		}) = &mut self.selection
+		{
+			article.cancel_downloads(rt);
+		}
+		self.selection = None;
+		self.lightbox.close();
+		self.add_channel_working = None;
+		self.refresh_errors.clear();
+		self.new_articles = 0;
+		self.status = None;
+		self.feed_sort_keys = None;
+		// It belongs to the old database.
+		self.undo_unsubscribe = None;
+		self.snippets.clear();
+		self.last_refreshed.clear();
+		self.session_started = Instant::now();
+		let database = Database::with_read_storage(dir.clone(), self.database.read_storage());
+		let old = std::mem::replace(&mut self.database, Arc::new(database));
+		// If an aborted refresh still holds the old database, it stops syncing once that's dropped.
+		if let Ok(old) = Arc::try_unwrap(old) {
+			rt.block_on(old.shutdown());
+		}
+		self.notice = quarantine_notice(&self.database);
+		self.recent.touch(dir);
+		self.recent.save();
+	}
+
+	/// Says how many feeds and unread articles there are. Counting the unread articles means
+	/// going through all of them, so it's only redone when the database changes.
+	fn status_line(&mut self, rt: &Runtime) -> String {
+		let generation = self.database.generation();
+		if let Some((cached, status)) = &self.status {
+			if *cached == generation {
+				return status.clone();
+			}
+		}
+		let feeds = rt.block_on(self.database.subscription_count());
+		let unread = rt.block_on(self.database.total_unread());
+		let status = format!(
+			"{feeds} {} · {unread} unread",
+			if feeds == 1 { "feed" } else { "feeds" }
+		);
+		self.status = Some((generation, status.clone()));
+		status
+	}
+
+	/// Works out what each subscription is sorted by again, if the database has changed.
+	fn update_feed_sort_keys(&mut self, rt: &Runtime) {
+		let generation = self.database.generation();
+		if self.feed_sort_keys.as_ref().map(|(cached, _)| *cached) != Some(generation) {
+			let keys = rt.block_on(self.database.feed_sort_keys());
+			self.feed_sort_keys = Some((generation, keys));
+		}
+	}
+
+	/// Starts refreshing a subscription in the background, unless it's already refreshing.
+	fn start_refresh(&mut self, pub_url: String, bypass_cache: bool) {
+		if self.refreshing.contains_key(&pub_url) {
+			return;
 		}
+		self.refresh_errors.remove(&pub_url);
+		self.last_refreshed.insert(pub_url.clone(), Instant::now());
+		if self.bulk_write.is_none() {
+			self.bulk_write = Some(self.database.bulk_write());
+		}
+		if self.refreshing.is_empty() {
+			self.refresh_progress.reset();
+		}
+		self.refresh_progress.begin(1);
+		let database = self.database.clone();
+		let task = tokio::spawn({
+			let pub_url = pub_url.clone();
+			async move {
+				if bypass_cache {
+					database.force_refresh_one(&pub_url).await
+				} else {
+					database.refresh_one(&pub_url).await
+				}
+			}
+		});
+		self.refreshing.insert(pub_url, task);
 	}
+
+	/// Starts refreshing every subscription whose interval has passed since it was last
+	/// refreshed. Only checks every [`SCHEDULE_CHECK`], since it reads every feed's metadata.
+	fn schedule_refreshes(&mut self, rt: &Runtime) {
+		let now = Instant::now();
+		if now < self.next_schedule_check {
+			return;
+		}
+		self.next_schedule_check = now + SCHEDULE_CHECK;
+		for pub_url in rt.block_on(self.database.get_subscriptions()).into_keys() {
+			let meta = rt.block_on(self.database.get_meta(&pub_url));
+			if meta.rate_limited_until(Utc::now()).is_some() {
+				continue;
+			}
+			let interval = meta.refresh_delay(self.refresh_interval);
+			let last = self.last_refreshed.get(&pub_url).copied();
+			let seed = jitter_seed(&pub_url);
+			if refresh_due_jittered(last, interval, self.session_started, now, seed) {
+				self.start_refresh(pub_url, false);
+			}
+		}
+	}
+
+	/// Collects the results of any refreshes which have finished.
+	fn poll_refreshes(&mut self, rt: &Runtime) {
+		let finished: Vec<String> = self
+			.refreshing
+			.iter()
+			.filter(|(_, task)| task.is_finished())
+			.map(|(pub_url, _)| pub_url.clone())
+			.collect();
+		for pub_url in finished {
+			let Some(task) = self.refreshing.remove(&pub_url) else {
+				continue;
+			};
+			self.refresh_progress.finish_one();
+			match rt.block_on(task) {
+				Ok(Ok(added)) => self.new_articles += added,
+				Ok(Err(e)) => {
+					self.refresh_errors.insert(pub_url, e);
+				}
+				Err(e) => log::error!("Refresh of {pub_url} panicked: {e}"),
+			}
+		}
+		if self.refreshing.is_empty() {
+			self.bulk_write = None;
+		}
+		if self.refreshing.is_empty() && self.new_articles > 0 {
+			let message = match std::mem::take(&mut self.new_articles) {
+				1 => "1 new article".to_string(),
+				n => format!("{n} new articles"),
+			};
+			self.notice = Some((message, Instant::now() + NOTICE_DURATION));
+		}
+	}
+
+	/// Removes a subscription, keeping it for a while in case it was a misclick.
+	fn unsubscribe(&mut self, rt: &Runtime, pub_url: &str) {
+		if let Some(removed) = rt.block_on(self.database.unsubscribe(pub_url)) {
+			self.undo_unsubscribe = Some((removed, Instant::now() + UNDO_DURATION));
+		}
+	}
+
+	fn show_undo_unsubscribe(&mut self, ctx: &eframe::egui::Context, rt: &Runtime) {
+		let Some((removed, until)) = &self.undo_unsubscribe else {
+			return;
+		};
+		if Instant::now() >= *until {
+			self.undo_unsubscribe = None;
+			return;
+		}
+		let mut undo = false;
+		egui::Area::new("undo unsubscribe")
+			.anchor(Align2::LEFT_BOTTOM, [8.0, -48.0])
+			.show(ctx, |ui| {
+				egui::Frame::popup(ui.style()).show(ui, |ui| {
+					ui.horizontal(|ui| {
+						ui.label(format!("Unsubscribed from {}", removed.feed.title()));
+						undo = ui.button("Undo").clicked();
+					});
+				});
+			});
+		ctx.request_repaint_after(*until - Instant::now());
+		if undo {
+			if let Some((removed, _)) = self.undo_unsubscribe.take() {
+				rt.block_on(self.database.restore(removed));
+			}
+		}
+	}
+
 	pub(crate) fn update(
 		&mut self,
 		ctx: &eframe::egui::Context,
@@ -96,17 +804,51 @@ impl MainApp {
 	) {
 		let _rt = rt.enter();
 
+		self.poll_refreshes(rt);
+		self.schedule_refreshes(rt);
+		if self.refreshing.is_empty() {
+			ctx.request_repaint_after(SCHEDULE_CHECK);
+		} else {
+			ctx.request_repaint_after(Duration::from_millis(100));
+		}
+
+		if let Some((message, until)) = &self.notice {
+			if Instant::now() < *until {
+				egui::Area::new("refresh notice")
+					.anchor(Align2::RIGHT_BOTTOM, [-8.0, -48.0])
+					.show(ctx, |ui| {
+						egui::Frame::popup(ui.style()).show(ui, |ui| ui.label(message.as_str()));
+					});
+				ctx.request_repaint_after(*until - Instant::now());
+			} else {
+				self.notice = None;
+			}
+		}
+
+		self.show_undo_unsubscribe(ctx, rt);
+
+		if ctx.input_mut(|i| i.consume_key(egui::Modifiers::COMMAND, egui::Key::F)) {
+			self.find.get_or_insert_with(FindBar::default).focus = true;
+		}
+		if !ctx.wants_keyboard_input()
+			&& self.lightbox.url().is_none()
+			&& ctx.input(|i| i.key_pressed(egui::Key::F))
+		{
+			self.reading_focus = !self.reading_focus;
+		}
+
 		TopBottomPanel::bottom("sidebars").show(ctx, |ui| {
-			self.bottom_panel(ui);
+			self.bottom_panel(ui, rt);
 		});
-		let show_channels = matches!(self.selection, None);
-		let show_articles = matches!(
+		let article_open = matches!(
 			self.selection,
 			Some(Selection {
-				channel_id: _,
-				article: None
+				article: Some(_),
+				..
 			})
 		);
+		let (show_channels, show_articles) =
+			visible_panels(self.selection.is_some(), article_open, self.reading_focus);
 
 		SidePanel::left("channels")
 			.show_animated(ctx, show_channels, |ui| self.channels_panel(ui, rt));
@@ -117,7 +859,7 @@ impl MainApp {
 
 		SidePanel::right("new_subscription").show_animated(
 			ctx,
-			self.add_channel_working.is_some(),
+			self.add_channel_working.is_some() && !(article_open && self.reading_focus),
 			|ui| {
 				self.add_new_channel_panel(ui, rt);
 			},
@@ -126,27 +868,120 @@ impl MainApp {
 		CentralPanel::default().show(ctx, |ui| {
 			self.central_panel(ui, rt, frame);
 		});
+
+		if let Some(url) = take_enlarge_request(ctx) {
+			self.lightbox.open(url);
+		}
+		self.lightbox.show(ctx);
 	}
 
 	fn add_new_channel_panel(&mut self, ui: &mut egui::Ui, rt: &Runtime) {
 		ui.set_min_size(Vec2::new(200.0, 0.0));
 		if let Some(add_channel) = &mut self.add_channel_working {
-			if let Some(fetch_progress) = &mut add_channel.fetch_progress {
-				rt.block_on(fetch_progress.tick());
+			if let Some(lookup) = &mut add_channel.lookup {
+				let before = lookup.url().map(ToString::to_string);
+				rt.block_on(lookup.tick());
+				// Show where a page's feed link led.
+				if lookup.url() != before.as_deref() {
+					add_channel.url = lookup.url().unwrap_or_default().to_string();
+				}
+				if let (MaybeLoaded::Done(_, Ok(feed)), None) = (&lookup.progress, &add_channel.report)
+				{
+					add_channel.report = Some(FeedValidation::of_feed(&feed.0));
+				}
+			}
+			ui.horizontal(|ui| {
+				ui.selectable_value(&mut add_channel.paste_xml, false, "From URL");
+				ui.selectable_value(&mut add_channel.paste_xml, true, "Paste XML");
+			});
+			if add_channel.paste_xml {
+				ui.label("Feed XML:");
+				let edited = ScrollArea::vertical()
+					.max_height(300.0)
+					.show(ui, |ui| {
+						ui.add(
+							TextEdit::multiline(&mut add_channel.raw_xml)
+								.code_editor()
+								.desired_rows(10),
+						)
+					})
+					.inner
+					.changed();
+				if edited {
+					add_channel.parsed_xml = (!add_channel.raw_xml.trim().is_empty())
+						.then(|| parse_pasted_feed(&add_channel.raw_xml));
+				}
+				ui.label("Store it under this URL:");
+				ui.text_edit_singleline(&mut add_channel.url);
+				let mut commit = false;
+				match &add_channel.parsed_xml {
+					None => {}
+					Some(Err(e)) => {
+						ui.colored_label(Color32::RED, e.to_string());
+					}
+					Some(Ok(feed)) => {
+						ui.colored_label(
+							Color32::GREEN,
+							format!(
+								"OK! Got feed \"{}\".",
+								match feed {
+									Feed::RSS(r) => r.title().to_string(),
+									Feed::Atom(a) => a.title().to_string(),
+								}
+							),
+						);
+						commit = ui
+							.add_enabled(
+								!add_channel.url.trim().is_empty(),
+								Button::new("Commit"),
+							)
+							.clicked();
+					}
+				}
+				if commit {
+					if let Some(Ok(feed)) = &add_channel.parsed_xml {
+						rt.block_on(self.database.subscribe(add_channel.url.trim(), feed));
+					}
+					self.add_channel_working = None;
+				} else if ui.button("Cancel").clicked() {
+					self.add_channel_working = None;
+				}
+				return;
 			}
 			ui.text_edit_singleline(&mut add_channel.url);
-			if ui.button("Try it").clicked() {
-				add_channel.fetch_progress =
-					Some(MaybeLoaded::NotStarted(add_channel.url.to_string()));
+			if ui
+				.button("Try it")
+				.on_hover_text("Web pages which link to their feed work too")
+				.clicked()
+			{
+				add_channel.lookup = Some(FeedLookup::new(&add_channel.url));
+				add_channel.report = None;
+				add_channel.confirm_merge = false;
 			}
-			match &add_channel.fetch_progress {
+			match add_channel.lookup.as_ref().map(|lookup| &lookup.progress) {
 				None => {}
-				Some(MaybeLoaded::Done(_, Err(ChannelFromBytesError::HTMLWithLink(link)))) => {
-					add_channel.url = link.clone();
-					add_channel.fetch_progress = Some(MaybeLoaded::NotStarted(link.to_string()));
+				Some(MaybeLoaded::Done(_, Err(ChannelFromBytesError::HTMLWithLink(found)))) => {
+					let mut picked = None;
+					ui.label("This page links to several feeds:");
+					for feed in found {
+						let mut label = feed.title.clone().unwrap_or_else(|| feed.href.clone());
+						if feed.guessed {
+							label.push_str(" (guess)");
+						}
+						if ui.button(label).on_hover_text(&feed.href).clicked()
+						{
+							picked = Some(feed.href.clone());
+						}
+					}
+					if let (Some(href), Some(lookup)) = (picked, &mut add_channel.lookup) {
+						lookup.pick(&href);
+					}
+				}
+				Some(MaybeLoaded::Done(_, Err(ChannelFromBytesError::BadFeed(why)))) => {
+					ui.colored_label(Color32::RED, format!("Not a feed: {why}"));
 				}
-				Some(MaybeLoaded::Done(_, Err(e))) => {
-					ui.colored_label(Color32::RED, format!("{e:#?}"));
+				Some(MaybeLoaded::Done(_, Err(ChannelFromBytesError::Undecodable(why)))) => {
+					ui.colored_label(Color32::RED, format!("Couldn't decode the response: {why}"));
 				}
 				Some(MaybeLoaded::Working(_) | MaybeLoaded::NotStarted(_)) => {
 					ui.label("...");
@@ -157,7 +992,13 @@ impl MainApp {
 				Some(MaybeLoaded::Failed(_, e)) => {
 					ui.colored_label(Color32::RED, format!("{e}"));
 				}
-				Some(MaybeLoaded::Done(_, Ok(channel))) => {
+				Some(MaybeLoaded::TooLarge(_, limit)) => {
+					ui.colored_label(Color32::RED, format!("Over the {limit} byte limit"));
+				}
+				Some(MaybeLoaded::NotCached(_)) => {
+					ui.colored_label(Color32::RED, "Not available offline");
+				}
+				Some(MaybeLoaded::Done(fetched_url, Ok(channel))) => {
 					ui.colored_label(
 						Color32::GREEN,
 						format!(
@@ -168,9 +1009,36 @@ impl MainApp {
 							}
 						),
 					);
-					if ui.button("Commit").clicked() {
-						rt.block_on(self.database.subscribe(&add_channel.url, &channel.0));
-						self.add_channel_working = None;
+					for warning in add_channel.report.iter().flat_map(|report| &report.warnings) {
+						ui.colored_label(Color32::YELLOW, warning);
+					}
+					let existing = rt.block_on(self.database.subscribed_as(fetched_url));
+					if let Some(existing) = &existing {
+						ui.colored_label(
+							Color32::YELLOW,
+							format!("Already subscribed as {existing} — commit will merge."),
+						);
+					}
+					let label = if add_channel.confirm_merge {
+						"Confirm Merge"
+					} else {
+						"Commit"
+					};
+					if ui.button(label).clicked() {
+						if existing.is_some() && !add_channel.confirm_merge {
+							add_channel.confirm_merge = true;
+						} else {
+							// Merge into the existing subscription rather than adding a near-duplicate.
+							let url = match (existing, Credentials::split_from_url(fetched_url)) {
+								(Some(existing), (_, Some(credentials))) => {
+									credentials.join_to_url(&existing)
+								}
+								(Some(existing), (_, None)) => existing,
+								(None, _) => fetched_url.clone(),
+							};
+							rt.block_on(self.database.subscribe(&url, &channel.0));
+							self.add_channel_working = None;
+						}
 					}
 				}
 			}
@@ -185,85 +1053,317 @@ impl MainApp {
 		ui: &mut egui::Ui,
 		rt: &Runtime,
 	) -> egui::scroll_area::ScrollAreaOutput<()> {
-		if ui.button("Refresh").clicked() {
-			for (key, _value) in rt.block_on(self.database.get_subscriptions()) {
-				self.sub_refresh_progress
-					.insert(key.clone(), MaybeLoaded::NotStarted(key));
-			}
-		}
-		for value in self.sub_refresh_progress.values_mut() {
-			rt.block_on(value.tick());
+		if ui.button("All Articles").clicked() {
+			self.selection = Some(Selection {
+				channel_id: ALL_ARTICLES.to_string(),
+				article: None,
+				category_filter: BTreeSet::new(),
+				list_scroll: ListScroll::default(),
+				picks: Picks::default(),
+			});
 		}
+		let subscriptions = rt.block_on(self.database.subscription_count());
 		ScrollArea::new([false, true]).show(ui, |ui| {
 			ui.set_min_size(Vec2::new(200.0, 0.0));
+			if show_onboarding(subscriptions, self.add_channel_working.is_some()) {
+				ui.vertical_centered(|ui| {
+					ui.add_space(16.0);
+					ui.heading("No subscriptions yet");
+					ui.label(
+						"Subscribe to a feed, or to a web page which links to one, and its articles will show up here.",
+					);
+					ui.add_space(8.0);
+					if ui
+						.add(Button::new(RichText::new("Add your first subscription").strong()))
+						.clicked()
+					{
+						self.add_channel_working = Some(AddChannel::default());
+					}
+				});
+				return;
+			}
+			let mut sort = self.options.channel_sort;
+			egui::ComboBox::from_id_source("channel_sort")
+				.selected_text(
+					FEED_SORTS
+						.iter()
+						.find(|(option, _)| *option == sort)
+						.map_or("", |(_, label)| label),
+				)
+				.show_ui(ui, |ui| {
+					for (option, label) in FEED_SORTS {
+						ui.selectable_value(&mut sort, option, label);
+					}
+				});
+			self.options.channel_sort = sort;
 			ScrollArea::new([false, true]).show(ui, |ui| {
-				for (key, value) in rt.block_on(self.database.get_subscriptions()) {
-					let title = match &*value {
-						Feed::Atom(a) => a.title().to_string(),
-						Feed::RSS(r) => r.title().to_string(),
-					};
-					let description = match &*value {
+				let mut feeds = rt.block_on(self.database.ordered_subscriptions());
+				if sort != FeedSort::Manual {
+					self.update_feed_sort_keys(rt);
+					if let Some((_, keys)) = &self.feed_sort_keys {
+						feeds.sort_by(|(a, _, _), (b, _, _)| match (keys.get(a), keys.get(b)) {
+							(Some(a), Some(b)) => a.compare(b, sort),
+							_ => std::cmp::Ordering::Equal,
+						});
+					}
+				}
+				let count = feeds.len();
+				let limit = self.options.channel_limit;
+				let hidden = if self.show_all_channels || limit == 0 {
+					0
+				} else {
+					count.saturating_sub(limit)
+				};
+				// Swaps the feeds at these positions once the list is drawn.
+				let mut swap = None;
+				for (position, (key, value, meta)) in feeds.iter().enumerate().take(count - hidden)
+				{
+					let key = key.clone();
+					let title = value.title().to_string();
+					let description = match &**value {
 						Feed::Atom(_) => "Atom feed, no description available",
 						Feed::RSS(r) => r.description(),
 					};
-					if ui.button(&title).clicked() {
+					let icon = value.icon_url().or_else(|| value.favicon_url(&key));
+					let clicked = ui
+						.horizontal(|ui| {
+							if let Some(icon) = &icon {
+								line_icon(ui, rt, &mut self.icons, icon);
+							}
+							ui.button(&title).clicked()
+						})
+						.inner;
+					if clicked {
 						self.selection = Some(Selection {
 							channel_id: key.clone(),
 							article: None,
+							category_filter: BTreeSet::new(),
+							list_scroll: ListScroll::default(),
+							picks: Picks::default(),
 						});
 					}
-					match self.sub_refresh_progress.get(&key) {
-						None => {}
-						Some(MaybeLoaded::NotStarted(_) | MaybeLoaded::Working(_)) => {
+					let is_refreshing = self.refreshing.contains_key(&key);
+					if is_refreshing {
+						ui.horizontal(|ui| {
+							ui.spinner();
 							ui.label("Reloading...");
-						}
-						Some(MaybeLoaded::Failed(_, e)) => {
-							ui.colored_label(Color32::RED, format!("failed: {e}"));
-						}
-						Some(MaybeLoaded::Done(_, Err(e))) => {
-							ui.colored_label(Color32::RED, format!("failed: {e}"));
-						}
-						Some(MaybeLoaded::BadStatus(status)) => {
-							ui.colored_label(Color32::RED, format!("failed: {status}"));
-						}
-						Some(MaybeLoaded::Done(_, Ok(channel))) => {
-							rt.block_on(self.database.subscribe(&key, channel));
-							self.sub_refresh_progress.remove(&key);
-						}
+						});
+					} else if meta.is_gone() {
+						ui.horizontal_wrapped(|ui| {
+							ui.colored_label(Color32::RED, "This feed seems to be gone.");
+							if ui.button("Unsubscribe").clicked() {
+								self.unsubscribe(rt, &key);
+							}
+						});
+					} else if let Some(until) = meta.rate_limited_until(Utc::now()) {
+						let until = until.with_timezone(&Local).format("%H:%M");
+						ui.label(
+							RichText::new(format!("Rate limited until {until}"))
+								.color(ui.visuals().warn_fg_color),
+						)
+						.on_hover_text("The server asked for this feed to be refreshed less often");
+					} else if let Some(e) = self.refresh_errors.get(&key) {
+						ui.colored_label(Color32::RED, format!("failed: {e}"));
 					}
 					CollapsingHeader::new("Description")
 						.id_source(&title)
 						.show(ui, |ui| {
 							ui.label(description);
+							ui.label(RichText::new(&key).monospace().weak());
+							ui.horizontal(|ui| {
+								if ui.button("Copy Feed URL").clicked() {
+									ui.output_mut(|o| o.copied_text = key.clone());
+								}
+								if ui.button("Open Feed in Browser").clicked() {
+									if let Err(e) = open::that(&key) {
+										log::error!("Failed to open {key}: {e}");
+									}
+								}
+							});
+							// Positions only mean anything in the arranged order.
+							let arranged = sort == FeedSort::Manual;
+							ui.horizontal(|ui| {
+								if ui
+									.add_enabled(arranged && position > 0, Button::new("Move Up"))
+									.clicked()
+								{
+									swap = Some((position - 1, position));
+								}
+								if ui
+									.add_enabled(
+										arranged && position + 1 < count,
+										Button::new("Move Down"),
+									)
+									.clicked()
+								{
+									swap = Some((position, position + 1));
+								}
+							});
+							if ui
+								.add_enabled(!is_refreshing, Button::new("Refresh"))
+								.clicked()
+							{
+								self.start_refresh(key.clone(), false);
+							}
+							if ui
+								.add_enabled(!is_refreshing, Button::new("Force Refresh"))
+								.on_hover_text("Refresh without using any cached copy of the feed")
+								.clicked()
+							{
+								self.start_refresh(key.clone(), true);
+							}
+							let current = meta.refresh_interval_secs;
+							let mut chosen = current;
+							egui::ComboBox::from_id_source(("refresh_interval", &key))
+								.selected_text(refresh_interval_label(current))
+								.show_ui(ui, |ui| {
+									for (secs, label) in REFRESH_INTERVALS {
+										ui.selectable_value(&mut chosen, secs, label);
+									}
+								});
+							if chosen != current {
+								rt.block_on(self.database.set_refresh_interval(&key, chosen));
+							}
+							let mut click = meta.link_click;
+							egui::ComboBox::from_id_source(("link_click", &key))
+								.selected_text(format!(
+									"Links: {}",
+									LINK_CLICKS
+										.iter()
+										.find(|(option, _)| *option == click)
+										.map_or("Default", |(_, label)| label)
+								))
+								.show_ui(ui, |ui| {
+									for (option, label) in LINK_CLICKS {
+										ui.selectable_value(&mut click, option, label);
+									}
+								});
+							if click != meta.link_click {
+								rt.block_on(self.database.set_link_click(&key, click));
+							}
 							if ui.button("Unsubscribe").clicked() {
-								rt.block_on(self.database.unsubscribe(&key));
+								self.unsubscribe(rt, &key);
 							}
 						});
 					ui.separator();
 				}
+				if hidden > 0 {
+					if ui.button(format!("Show all ({hidden} more)")).clicked() {
+						self.show_all_channels = true;
+					}
+				} else if self.show_all_channels
+					&& limit > 0
+					&& count > limit
+					&& ui.button("Show fewer").clicked()
+				{
+					self.show_all_channels = false;
+				}
+				if let Some((a, b)) = swap {
+					let mut order: Vec<String> = feeds.into_iter().map(|(key, _, _)| key).collect();
+					order.swap(a, b);
+					rt.block_on(self.database.reorder_feeds(&order));
+				}
 			});
 		})
 	}
 
-	fn bottom_panel(&mut self, ui: &mut egui::Ui) {
+	fn bottom_panel(&mut self, ui: &mut egui::Ui, rt: &Runtime) {
 		ui.horizontal(|ui| {
 			if ui.button("Back").clicked() {
 				match &mut self.selection {
 					None => {}
 					Some(Selection {
-						channel_id: _,
 						article,
+						list_scroll,
+						..
 					}) if article.is_some() => {
+						if let Some(article) = article {
+							article.cancel_downloads(rt);
+						}
 						*article = None;
+						list_scroll.return_to();
 					}
 					Some(_) => {
 						self.selection = None;
 					}
 				}
 			}
+			ui.toggle_value(&mut self.reading_focus, "Focus")
+				.on_hover_text("Hide the side panels while reading (F)");
+			if ui.button("Refresh All").clicked() {
+				for pub_url in rt.block_on(self.database.get_subscriptions()).into_keys() {
+					self.start_refresh(pub_url, false);
+				}
+			}
 			if ui.button("New Subscription").clicked() {
 				self.add_channel_working = Some(AddChannel::default());
 			}
+			let mut offline = winter::is_offline();
+			if ui
+				.checkbox(&mut offline, "Offline")
+				.on_hover_text(
+					"Only show feeds and pages which are already cached. Audio and video \
+					 aren't cached, so they can't be played",
+				)
+				.changed()
+			{
+				winter::set_offline(offline);
+			}
+			let cache_menu = ui.menu_button("Cache", |ui| {
+				let stats = *self.cache_stats.get_or_insert_with(winter::cache_stats);
+				#[allow(clippy::cast_precision_loss)]
+				let megabytes = stats.bytes as f64 / (1024.0 * 1024.0);
+				ui.label(format!("{} responses, {megabytes:.1} MB", stats.entries));
+				if ui.button("Clear cache").clicked() {
+					let message = match winter::clear_cache() {
+						Ok(()) => "Cleared the cache".to_string(),
+						Err(e) => format!("Couldn't clear the cache: {e}"),
+					};
+					self.notice = Some((message, Instant::now() + NOTICE_DURATION));
+					self.cache_stats = None;
+					ui.close_menu();
+				}
+			});
+			if cache_menu.inner.is_none() {
+				self.cache_stats = None;
+			}
+			ui.separator();
+			let current = self.database.dir().to_path_buf();
+			let mut chosen = None;
+			egui::ComboBox::from_label("Profile")
+				.selected_text(current.display().to_string())
+				.show_ui(ui, |ui| {
+					for dir in &self.recent.directories {
+						if ui
+							.selectable_label(dir == &current, dir.display().to_string())
+							.clicked()
+						{
+							chosen = Some(dir.clone());
+						}
+					}
+				});
+			if ui.button("Add Profile").clicked() {
+				chosen = rfd::FileDialog::new().pick_folder();
+			}
+			if let Some(dir) = chosen.filter(|dir| dir != &current) {
+				self.switch_profile(dir, rt);
+			}
+			ui.with_layout(Layout::right_to_left(Align::Center), |ui| {
+				ui.label(self.status_line(rt));
+				// A lone refresh has its own spinner in the feed list.
+				let progress = &self.refresh_progress;
+				if !self.refreshing.is_empty() && progress.total() > 1 {
+					ui.add(
+						egui::ProgressBar::new(progress.fraction())
+							.desired_width(160.0)
+							.text(format!(
+								"Refreshed {} of {}",
+								progress.completed(),
+								progress.total()
+							)),
+					);
+				}
+			});
 		});
 	}
 
@@ -272,82 +1372,557 @@ impl MainApp {
 		let Some(selection) = &mut self.selection else {
 			return;
 		};
-		let Some(channel) = rt.block_on(self.database.get_subscription(&selection.channel_id)) else {
-			self.selection = None;
-			return;
+		let articles: Vec<CommonArticle> = if selection.channel_id == ALL_ARTICLES {
+			let subscriptions = rt.block_on(self.database.get_subscriptions());
+			CommonArticle::all_articles(&subscriptions, self.options.dedup_articles)
+		} else {
+			let Some(channel) = rt.block_on(self.database.get_subscription(&selection.channel_id)) else {
+				self.selection = None;
+				return;
+			};
+			let mut articles = CommonArticle::from_feed(&channel, selection.channel_id.clone());
+			articles.sort_by_key(|article| article.timestamp);
+			articles.reverse();
+			articles
 		};
-		let mut articles: Vec<CommonArticle> =
-			CommonArticle::from_feed(&channel, selection.channel_id.clone());
-		articles.sort_by_key(|article| article.timestamp);
-		articles.reverse();
-		ScrollArea::new([false, true]).show(ui, |ui| {
-			for article in articles {
-				ui.horizontal(|ui| {
-					if rt.block_on(self.database.has_read(&selection.channel_id, &article.id)) {
-						if ui.button("R").clicked() {
-							rt.block_on(self.database.unread(&selection.channel_id, &article.id));
+		if articles.is_empty() {
+			// Nothing went wrong, there's just nothing to show, so this isn't an error.
+			ui.weak("No articles yet");
+			return;
+		}
+		if !selection.picks.is_empty() {
+			ui.horizontal(|ui| {
+				ui.label(format!("{} selected", selection.picks.len()));
+				let read = selection.picks.marks_read(|(pub_url, id)| {
+					rt.block_on(self.database.has_read(pub_url, id))
+				});
+				if ui.button(if read { "Mark Read" } else { "Mark Unread" }).clicked() {
+					rt.block_on(self.database.set_read_many(&selection.picks.keys(), read));
+					selection.picks.clear();
+				}
+				if ui.button("Clear").clicked() {
+					selection.picks.clear();
+				}
+			});
+			ui.separator();
+		}
+		let categories = CommonArticle::categories_of(&articles);
+		if !categories.is_empty() {
+			ui.horizontal_wrapped(|ui| {
+				for category in categories {
+					let mut selected = selection.category_filter.contains(&category);
+					if ui.toggle_value(&mut selected, &category).changed() {
+						// Picked articles could be filtered out of sight.
+						selection.picks.clear();
+						if selected {
+							selection.category_filter.insert(category);
+						} else {
+							selection.category_filter.remove(&category);
 						}
-					} else if ui.button("x").clicked() {
-						rt.block_on(self.database.read(&selection.channel_id, &article.id));
-					}
-					ui.horizontal_wrapped(|ui| {
-						if ui.button(&article.title).clicked() {
-							let body = (article.body)();
-							selection.article = Some(
-								SelectedArticle {
-									article,
-									tree: body,
-									links: vec![],
-								}
-								.populate_links(rt),
-							);
-							return;
+					}
+				}
+			});
+			ui.separator();
+		}
+		let now = Local::now();
+		let mut groups: Vec<(Option<Bucket>, Vec<CommonArticle>)> = vec![];
+		for article in articles
+			.into_iter()
+			.filter(|article| article.matches_categories(&selection.category_filter))
+		{
+			let bucket = self.options.group_by_date.then(|| date_bucket(article.timestamp, now));
+			match groups.iter_mut().find(|(b, _)| *b == bucket) {
+				Some((_, group)) => group.push(article),
+				None => groups.push((bucket, vec![article])),
+			}
+		}
+		groups.sort_by_key(|(bucket, _)| *bucket);
+		let order: Vec<ArticleKey> = groups
+			.iter()
+			.flat_map(|(_, group)| group.iter())
+			.map(|article| (article.pub_url.clone(), article.id.clone()))
+			.collect();
+		let mut scroll_area = ScrollArea::new([false, true]);
+		if let Some(offset) = selection.list_scroll.take_restore() {
+			scroll_area = scroll_area.vertical_scroll_offset(offset);
+		}
+		let mut show_article = |ui: &mut egui::Ui, article: CommonArticle| {
+			let snippet = (self.options.density == ListDensity::Comfortable).then(|| {
+				self.snippets
+					.entry((article.pub_url.clone(), article.id.clone()))
+					.or_insert_with(|| article.snippet(self.options.snippet_length))
+					.clone()
+			});
+			let key = (article.pub_url.clone(), article.id.clone());
+			ui.horizontal(|ui| {
+				let mut picked = selection.picks.contains(&key);
+				if ui.checkbox(&mut picked, "").changed() {
+					if ui.input(|i| i.modifiers.shift) {
+						selection.picks.pick_range(key.clone(), &order);
+					} else {
+						selection.picks.toggle(key.clone());
+					}
+				}
+				let read = rt.block_on(self.database.has_read(&article.pub_url, &article.id));
+				if ui
+					.button(if read { "R" } else { "x" })
+					.on_hover_text(read_toggle_label(read))
+					.clicked()
+				{
+					rt.block_on(toggle_read(&self.database, &article.pub_url, &article.id));
+				}
+				ui.horizontal_wrapped(|ui| {
+					let title = ui.button(&article.title).context_menu(|ui| {
+						if ui.button(read_toggle_label(read)).clicked() {
+							rt.block_on(toggle_read(&self.database, &article.pub_url, &article.id));
+							ui.close_menu();
 						}
-						ui.label(article.timestamp.date_naive().to_string());
 					});
+					if title.clicked() && ui.input(|i| i.modifiers.shift) {
+						selection.picks.pick_range(key, &order);
+					} else if title.clicked() {
+						let mut body = (article.body)();
+						if self.options.linkify_urls {
+							body.linkify_text();
+						}
+						selection.article = Some(
+							SelectedArticle {
+								article,
+								tree: body,
+								links: vec![],
+								full: None,
+							}
+							.populate_links(rt),
+						);
+						return;
+					}
+					let date = article.timestamp.date_naive().to_string();
+					match self.options.timestamps {
+						TimestampStyle::Absolute => {
+							ui.label(date);
+						}
+						TimestampStyle::Relative => {
+							ui.label(humanize(article.timestamp, now)).on_hover_text(date);
+						}
+					}
+					if let Some(episode) = article.episode {
+						ui.weak(format!("#{episode}"));
+					}
+					if let Some(duration) = article.duration {
+						ui.weak(format_duration(duration));
+					}
+					if !article.also_in.is_empty() {
+						ui.weak(format!("(+{} more)", article.also_in.len()))
+							.on_hover_text(article.also_in.join("\n"));
+					}
 				});
+			});
+			if let Some(snippet) = snippet {
+				ui.weak(snippet);
+			}
+		};
+		let output = scroll_area.show(ui, |ui| {
+			for (bucket, group) in groups {
+				match bucket {
+					Some(bucket) => {
+						CollapsingHeader::new(bucket.label())
+							.default_open(true)
+							.show(ui, |ui| {
+								for article in group {
+									show_article(ui, article);
+								}
+							});
+					}
+					None => {
+						for article in group {
+							show_article(ui, article);
+						}
+					}
+				}
 			}
 		});
+		selection.list_scroll.remember(output.state.offset.y);
 	}
 
 	fn central_panel(&mut self, ui: &mut egui::Ui, rt: &Runtime, frame: &mut Frame) {
-		let Some(Selection { channel_id, article: Some(SelectedArticle { article, tree, links }) }) = &mut self.selection else {
+		let Some(Selection { article: Some(SelectedArticle { article, tree, links, full }), .. }) = &mut self.selection else {
 			ui.label("Select an article.");
 			return;
 		};
-		tree.tick(rt);
+		let media = self.media_handler.as_ref();
+		tree.tick(rt, media);
+		flag_sponsored_links(ui.ctx(), self.options.flag_sponsored_links);
+		let feed_click = rt.block_on(self.database.get_meta(&article.pub_url)).link_click;
+		set_link_click(ui.ctx(), feed_click.unwrap_or(self.options.link_click));
 
 		ui.heading(&article.title);
 		ui.separator();
 		ui.horizontal(|ui| {
-			for (name, email) in &article.authors {
-				if let Some(email) = email {
-					if ui.button(name).clicked() {
-						open::that(format!("mailto:{email}")).unwrap();
+			for (name, email, uri) in &article.authors {
+				if let Some(email) = email.as_ref().filter(|_| self.options.author_avatars) {
+					line_icon(ui, rt, &mut self.icons, &gravatar_url(email));
+				}
+				let mailto = || email.as_ref().map(|email| format!("mailto:{email}"));
+				// The feed picks the author's URI, so it's only opened if it's somewhere a link
+				// in the article could go too.
+				let openable = |target: &String| {
+					matches!(classify_link(target), LinkKind::Web | LinkKind::Mail)
+				};
+				let target = uri.clone().or_else(mailto).filter(openable);
+				match target {
+					Some(target) => {
+						if ui.button(name).on_hover_text(&target).clicked() {
+							if let Err(e) = open::that(&target) {
+								log::warn!("Couldn't open {target}: {e}");
+							}
+						}
+					}
+					None => {
+						ui.label(name);
 					}
-				} else {
-					ui.label(name);
 				}
 			}
 		});
 		ui.horizontal(|ui| ui.label(article.categories.join(", ")));
+		ui.horizontal(|ui| {
+			let primary_link = article.primary_link();
+			if ui
+				.add_enabled(primary_link.is_some(), Button::new("Copy Link"))
+				.clicked()
+			{
+				if let Some(link) = primary_link {
+					ui.output_mut(|o| o.copied_text = link.to_string());
+				}
+			}
+			if ui.button("Copy Title").clicked() {
+				ui.output_mut(|o| o.copied_text = article.title.clone());
+			}
+			let canonical_url = article.canonical_url();
+			if ui
+				.add_enabled(canonical_url.is_some(), Button::new("Open Article"))
+				.clicked()
+			{
+				if let Some(url) = canonical_url {
+					if let Err(e) = open::that(url) {
+						log::error!("Couldn't open {url}: {e}");
+					}
+				}
+			}
+			let (label, fetching) = match full {
+				Some(FullArticle::Fetched { showing_full, .. }) if *showing_full => {
+					("Show Feed's Version", false)
+				}
+				Some(FullArticle::Fetched { .. }) => ("Show Full Article", false),
+				Some(FullArticle::Fetching(page)) => (
+					"Fetch Full Article",
+					matches!(page, MaybeLoaded::NotStarted(_) | MaybeLoaded::Working(_)),
+				),
+				None => ("Fetch Full Article", false),
+			};
+			if ui
+				.add_enabled(canonical_url.is_some() && !fetching, Button::new(label))
+				.on_hover_text("For feeds which only include a summary")
+				.clicked()
+			{
+				match full {
+					Some(FullArticle::Fetched { other, showing_full }) => {
+						std::mem::swap(tree, other);
+						*showing_full = !*showing_full;
+					}
+					_ => {
+						*full = canonical_url.map(|url| {
+							FullArticle::Fetching(MaybeLoaded::NotStarted(url.to_string()))
+						});
+					}
+				}
+			}
+		});
+		if let Some(find) = &mut self.find {
+			if !find.show(ui) {
+				self.find = None;
+			}
+		}
+		if let Some(FullArticle::Fetching(page)) = full {
+			rt.block_on(tick_page(page));
+			let fetched = match page {
+				MaybeLoaded::NotStarted(_) | MaybeLoaded::Working(_) => {
+					ui.label("Fetching the full article...");
+					None
+				}
+				MaybeLoaded::Done(url, Ok(readable)) => {
+					readable.resolve_urls(url);
+					let mut body = std::mem::replace(&mut readable.0, DocumentNode::Empty);
+					if self.options.linkify_urls {
+						body.linkify_text();
+					}
+					Some(body)
+				}
+				MaybeLoaded::Done(_, Err(e)) => {
+					let message = format!("Couldn't find the article's text: {e}");
+					ui.colored_label(Color32::RED, message);
+					None
+				}
+				MaybeLoaded::BadStatus(status) => {
+					ui.colored_label(Color32::RED, format!("Got bad status code {status}"));
+					None
+				}
+				MaybeLoaded::Failed(_, e) => {
+					ui.colored_label(Color32::RED, format!("{e}"));
+					None
+				}
+				MaybeLoaded::TooLarge(_, limit) => {
+					ui.colored_label(Color32::RED, format!("Over the {limit} byte limit"));
+					None
+				}
+				MaybeLoaded::NotCached(_) => {
+					ui.colored_label(Color32::RED, "Not available offline");
+					None
+				}
+			};
+			if let Some(mut other) = fetched {
+				std::mem::swap(tree, &mut other);
+				*full = Some(FullArticle::Fetched {
+					other,
+					showing_full: true,
+				});
+			}
+		}
 
-		for node in links {
-			node.tick(rt);
-			node.show(ui, frame);
+		for node in links.iter_mut() {
+			node.tick(rt, media);
+			node.show(ui, frame, media);
 		}
 
 		ui.separator();
 
-		tree.show(ui, frame);
+		let layout = match article.direction() {
+			TextDirection::LeftToRight => Layout::top_down(Align::Min),
+			// Wrapped rows inside a right-aligned column run right-to-left.
+			TextDirection::RightToLeft => Layout::top_down(Align::Max),
+		};
+		if let Some(find) = &mut self.find {
+			begin_find(ui.ctx(), &find.query, find.current, std::mem::take(&mut find.scroll));
+		}
+		ui.with_layout(layout, |ui| tree.show(ui, frame, media));
+		if let Some(find) = &mut self.find {
+			find.count = end_find(ui.ctx());
+			if find.current >= find.count {
+				find.current = 0;
+			}
+		}
 
 		ui.separator();
-		if ui.button("Mark as Read").clicked() {
-			rt.block_on(self.database.read(channel_id, &article.id));
-			self.selection = Some(Selection {
-				channel_id: (*channel_id).to_string(),
-				article: None,
-			});
+		if ui.button("Export").clicked() {
+			if let Some(path) = rfd::FileDialog::new()
+				.set_file_name(format!("{}.md", article.title))
+				.add_filter("Markdown", &["md"])
+				.add_filter("HTML", &["html", "htm"])
+				.save_file()
+			{
+				let is_html = path
+					.extension()
+					.and_then(|ext| ext.to_str())
+					.is_some_and(|ext| {
+						ext.eq_ignore_ascii_case("html") || ext.eq_ignore_ascii_case("htm")
+					});
+				let contents = if is_html {
+					article.to_html()
+				} else {
+					article.to_markdown()
+				};
+				if let Err(e) = std::fs::write(&path, contents) {
+					log::error!("Failed to export article to {}: {e}", path.display());
+				}
+			}
+		}
+		let read = rt.block_on(self.database.has_read(&article.pub_url, &article.id));
+		if ui.button(read_toggle_label(read)).clicked()
+			&& rt.block_on(toggle_read(&self.database, &article.pub_url, &article.id))
+			&& self.options.close_on_read
+		{
+			tree.cancel(rt);
+			if let Some(FullArticle::Fetching(page)) = full {
+				rt.block_on(page.cancel());
+			}
+			for node in links.iter_mut() {
+				node.cancel(rt);
+			}
+			if let Some(selection) = &mut self.selection {
+				selection.article = None;
+				selection.list_scroll.return_to();
+			}
 		}
 	}
 }
+
+#[cfg(test)]
+mod test {
+	use super::{
+		date_bucket, humanize, parse_pasted_feed, read_toggle_label, show_onboarding, toggle_read,
+		visible_panels, Bucket, ListScroll, Picks,
+	};
+	use chrono::{Duration, Local, TimeZone};
+	use winter::{state::Database, syndication::Feed};
+
+	#[test]
+	fn humanizes_timestamps() {
+		let now = Local.with_ymd_and_hms(2023, 10, 20, 12, 0, 0).unwrap();
+		let ago = |delta: Duration| humanize(now - delta, now);
+		assert_eq!(ago(Duration::seconds(59)), "just now");
+		assert_eq!(ago(Duration::seconds(60)), "1 minute ago");
+		assert_eq!(ago(Duration::minutes(59)), "59 minutes ago");
+		assert_eq!(ago(Duration::hours(1)), "1 hour ago");
+		assert_eq!(ago(Duration::hours(23)), "23 hours ago");
+		assert_eq!(ago(Duration::hours(24)), "yesterday");
+		assert_eq!(ago(Duration::hours(47)), "yesterday");
+		assert_eq!(ago(Duration::days(2)), "2 days ago");
+		assert_eq!(ago(Duration::days(7)), "1 week ago");
+		assert_eq!(ago(Duration::days(29)), "4 weeks ago");
+		assert_eq!(ago(Duration::days(30)), "1 month ago");
+		assert_eq!(ago(Duration::days(364)), "12 months ago");
+		assert_eq!(ago(Duration::days(365)), "2022-10-20");
+		assert_eq!(ago(Duration::minutes(-5)), "just now");
+	}
+
+	#[test]
+	fn buckets_dates() {
+		// A Wednesday.
+		let now = Local.with_ymd_and_hms(2023, 10, 18, 12, 0, 0).unwrap();
+		let on = |month, day, hour| {
+			date_bucket(Local.with_ymd_and_hms(2023, month, day, hour, 0, 0).unwrap(), now)
+		};
+		assert_eq!(on(10, 19, 9), Bucket::Today);
+		assert_eq!(on(10, 18, 0), Bucket::Today);
+		assert_eq!(on(10, 17, 23), Bucket::Yesterday);
+		assert_eq!(on(10, 17, 0), Bucket::Yesterday);
+		assert_eq!(on(10, 16, 23), Bucket::ThisWeek);
+		assert_eq!(on(10, 16, 0), Bucket::ThisWeek);
+		assert_eq!(on(10, 15, 23), Bucket::ThisMonth);
+		assert_eq!(on(10, 1, 0), Bucket::ThisMonth);
+		assert_eq!(on(9, 30, 23), Bucket::Older);
+		assert_eq!(date_bucket(Local.timestamp_opt(0, 0).unwrap(), now), Bucket::Undated);
+
+		// Early in the week, days from last month still belong to this week.
+		let now = Local.with_ymd_and_hms(2023, 11, 2, 12, 0, 0).unwrap();
+		let on = |month, day| {
+			date_bucket(Local.with_ymd_and_hms(2023, month, day, 12, 0, 0).unwrap(), now)
+		};
+		assert_eq!(on(11, 1), Bucket::Yesterday);
+		assert_eq!(on(10, 30), Bucket::ThisWeek);
+		assert_eq!(on(10, 29), Bucket::Older);
+	}
+
+	#[test]
+	fn onboards_empty_directories() {
+		assert!(show_onboarding(0, false));
+		assert!(!show_onboarding(0, true));
+		assert!(!show_onboarding(1, false));
+		assert!(!show_onboarding(3, true));
+	}
+
+	#[test]
+	fn focusing_hides_panels_while_reading() {
+		// Nothing selected: just the channels.
+		assert_eq!(visible_panels(false, false, false), (true, false));
+		assert_eq!(visible_panels(false, false, true), (true, false));
+		// A channel, but no article yet, so there's nothing to focus on.
+		assert_eq!(visible_panels(true, false, false), (false, true));
+		assert_eq!(visible_panels(true, false, true), (false, true));
+		// Reading an article.
+		assert_eq!(visible_panels(true, true, false), (false, true));
+		assert_eq!(visible_panels(true, true, true), (false, false));
+	}
+
+	#[test]
+	fn restores_list_scroll_once() {
+		let mut scroll = ListScroll::default();
+		scroll.remember(120.0);
+		assert_eq!(scroll.take_restore(), None);
+
+		// Opening an article and coming back.
+		scroll.return_to();
+		assert_eq!(scroll.take_restore(), Some(120.0));
+		// After that, the list scrolls freely again.
+		assert_eq!(scroll.take_restore(), None);
+		scroll.remember(80.0);
+		scroll.return_to();
+		assert_eq!(scroll.take_restore(), Some(80.0));
+	}
+
+	#[test]
+	fn picks_articles_and_ranges() {
+		let order: Vec<(String, String)> = ["a", "b", "c", "d", "e"]
+			.into_iter()
+			.map(|id| ("feed".to_string(), id.to_string()))
+			.collect();
+		let mut picks = Picks::default();
+		// Without an anchor, shift-clicking picks just the one article.
+		picks.pick_range(order[1].clone(), &order);
+		assert_eq!(picks.keys(), &order[1..2]);
+		// From the anchor, ranges go either way.
+		picks.pick_range(order[3].clone(), &order);
+		assert_eq!(picks.keys(), &order[1..4]);
+		picks.toggle(order[2].clone());
+		assert_eq!(picks.len(), 2);
+		picks.toggle(order[4].clone());
+		picks.pick_range(order[0].clone(), &order);
+		assert_eq!(picks.keys(), order);
+
+		// A mix of read and unread articles all get marked read.
+		let read_only =
+			|read: &'static [&str]| move |(_, id): &(String, String)| read.contains(&id.as_str());
+		assert!(picks.marks_read(read_only(&["a", "b"])));
+		assert!(!picks.marks_read(read_only(&["a", "b", "c", "d", "e"])));
+
+		picks.clear();
+		assert!(picks.is_empty());
+		picks.pick_range(order[2].clone(), &order);
+		assert_eq!(picks.keys(), &order[2..3]);
+	}
+
+	#[tokio::test]
+	async fn toggles_read_state() {
+		let tmp = tempdir::TempDir::new("winter_read_test").unwrap();
+		let db = Database::from_dir(tmp.path().to_path_buf());
+		let url = "https://example.com/feed.xml";
+		assert_eq!(read_toggle_label(db.has_read(url, "one").await), "Mark as Read");
+		assert!(toggle_read(&db, url, "one").await);
+		assert!(db.has_read(url, "one").await);
+		assert_eq!(read_toggle_label(db.has_read(url, "one").await), "Mark as Unread");
+		assert!(!db.has_read(url, "two").await);
+
+		assert!(!toggle_read(&db, url, "one").await);
+		assert!(!db.has_read(url, "one").await);
+		assert_eq!(read_toggle_label(db.has_read(url, "one").await), "Mark as Read");
+		std::mem::drop(db);
+		std::mem::drop(tmp);
+	}
+
+	#[tokio::test]
+	async fn subscribes_to_pasted_xml() {
+		assert!(parse_pasted_feed("<html><body>Not a feed</body></html>").is_err());
+		let feed = parse_pasted_feed(
+			r#"
+			<rss version="2.0"><channel><title>Pasted</title><link>https://example.com</link><description>.</description>
+				<item><title>One</title><guid>one</guid></item>
+			</channel></rss>
+			"#,
+		)
+		.unwrap();
+
+		let tmp = tempdir::TempDir::new("winter_paste_test").unwrap();
+		let db = Database::from_dir(tmp.path().to_path_buf());
+		db.subscribe("https://example.com/generated.xml", &feed).await;
+		let Feed::RSS(channel) = &*db
+			.get_subscription("https://example.com/generated.xml")
+			.await
+			.unwrap()
+		else {
+			panic!("Expected an RSS feed");
+		};
+		assert_eq!(channel.title(), "Pasted");
+		assert_eq!(channel.items().len(), 1);
+		std::mem::drop(db);
+		std::mem::drop(tmp);
+	}
+}
@@ -0,0 +1,53 @@
+//! OPML, the usual format for moving subscription lists between feed readers.
+
+use std::{collections::BTreeMap, sync::Arc};
+
+use crate::{document::export::escape_html, syndication::Feed};
+
+/// Writes an OPML document listing every subscription, keyed by feed URL.
+#[must_use]
+pub fn export(subscriptions: &BTreeMap<String, Arc<Feed>>) -> String {
+	let mut out = String::from(
+		"<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+		<opml version=\"2.0\">\n\
+		<head><title>winter subscriptions</title></head>\n\
+		<body>\n",
+	);
+	for (pub_url, feed) in subscriptions {
+		let (kind, title) = match &**feed {
+			Feed::Atom(a) => ("atom", a.title().to_string()),
+			Feed::RSS(r) => ("rss", r.title().to_string()),
+		};
+		let title = escape_html(&title);
+		out.push_str(&format!(
+			"<outline type=\"{kind}\" text=\"{title}\" title=\"{title}\" xmlUrl=\"{}\"/>\n",
+			escape_html(pub_url)
+		));
+	}
+	out.push_str("</body>\n</opml>\n");
+	out
+}
+
+#[cfg(test)]
+mod tests {
+	use std::{collections::BTreeMap, sync::Arc};
+
+	use crate::syndication::Feed;
+
+	#[test]
+	fn exports_subscriptions() {
+		let mut channel = rss::Channel::default();
+		channel.set_title("Tom & Jerry");
+		let subscriptions = BTreeMap::from([(
+			"https://example.com/feed?a=1&b=2".to_string(),
+			Arc::new(Feed::RSS(channel)),
+		)]);
+		let opml = super::export(&subscriptions);
+		assert!(opml.contains(
+			"<outline type=\"rss\" text=\"Tom &amp; Jerry\" title=\"Tom &amp; Jerry\" \
+			xmlUrl=\"https://example.com/feed?a=1&amp;b=2\"/>"
+		));
+		assert!(opml.starts_with("<?xml"));
+		assert!(opml.trim_end().ends_with("</opml>"));
+	}
+}
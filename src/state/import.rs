@@ -0,0 +1,181 @@
+//! Reading which articles have been read out of other readers' exports.
+//!
+//! Three JSON shapes are accepted:
+//!
+//! - A plain list of pairs, `[{"feed_url": "https://example.com/feed.xml", "guid": "..."}]`,
+//!   where `guid` is the item's `<guid>` or the entry's `<id>`.
+//! - A Fever API export, `{"feeds": [{"id": 1, "url": "..."}], "items": [{"feed_id": 1, "url":
+//!   "...", "is_read": 1}]}`. Fever doesn't keep guids, so each item's `url` stands in for
+//!   one, which works for the many feeds whose guids are their links. Items whose `is_read`
+//!   is 0 are skipped.
+//! - A Google Reader style stream, `{"items": [{"origin": {"streamId": "feed/<feed url>"},
+//!   "originId": "...", "alternate": [{"href": "..."}], "categories": [...]}]}`, as Google
+//!   Reader's takeout, Inoreader and FreshRSS write. `originId` is the guid, falling back to
+//!   the first `alternate` link. If an item has categories, it's only imported if one of them
+//!   is the `state/com.google/read` tag.
+
+use std::collections::BTreeMap;
+
+use serde::Deserialize;
+
+/// A read-state export, in any of the shapes described in the module docs.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ReadStateExport {
+	Pairs(Vec<ReadPair>),
+	Fever(FeverExport),
+	GoogleReader(GoogleReaderExport),
+}
+
+#[derive(Deserialize)]
+struct ReadPair {
+	feed_url: String,
+	guid: String,
+}
+
+#[derive(Deserialize)]
+struct FeverExport {
+	feeds: Vec<FeverFeed>,
+	items: Vec<FeverItem>,
+}
+
+#[derive(Deserialize)]
+struct FeverFeed {
+	id: u64,
+	url: String,
+}
+
+#[derive(Deserialize)]
+struct FeverItem {
+	feed_id: u64,
+	url: String,
+	#[serde(default = "read_by_default")]
+	is_read: u8,
+}
+
+fn read_by_default() -> u8 {
+	1
+}
+
+#[derive(Deserialize)]
+struct GoogleReaderExport {
+	items: Vec<GoogleReaderItem>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GoogleReaderItem {
+	origin: GoogleReaderOrigin,
+	origin_id: Option<String>,
+	#[serde(default)]
+	alternate: Vec<GoogleReaderLink>,
+	#[serde(default)]
+	categories: Vec<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GoogleReaderOrigin {
+	stream_id: String,
+}
+
+#[derive(Deserialize)]
+struct GoogleReaderLink {
+	href: String,
+}
+
+/// Parses an export into `(feed url, guid)` pairs for every read article in it.
+///
+/// # Errors
+/// Fails if `json` isn't any of the accepted shapes.
+pub(super) fn parse_read_state(json: &str) -> Result<Vec<(String, String)>, serde_json::Error> {
+	Ok(match serde_json::from_str(json)? {
+		ReadStateExport::Pairs(pairs) => pairs
+			.into_iter()
+			.map(|pair| (pair.feed_url, pair.guid))
+			.collect(),
+		ReadStateExport::Fever(export) => {
+			let feeds: BTreeMap<u64, String> =
+				export.feeds.into_iter().map(|feed| (feed.id, feed.url)).collect();
+			export
+				.items
+				.into_iter()
+				.filter(|item| item.is_read != 0)
+				.filter_map(|item| Some((feeds.get(&item.feed_id)?.clone(), item.url)))
+				.collect()
+		}
+		ReadStateExport::GoogleReader(export) => export
+			.items
+			.into_iter()
+			.filter(|item| {
+				item.categories.is_empty()
+					|| item
+						.categories
+						.iter()
+						.any(|category| category.ends_with("/state/com.google/read"))
+			})
+			.filter_map(|item| {
+				let feed_url = item.origin.stream_id.strip_prefix("feed/")?.to_string();
+				let guid = item
+					.origin_id
+					.or_else(|| item.alternate.into_iter().next().map(|link| link.href))?;
+				Some((feed_url, guid))
+			})
+			.collect(),
+	})
+}
+
+#[cfg(test)]
+mod test {
+	use super::parse_read_state;
+
+	#[test]
+	fn parses_each_export_shape() {
+		let fever = r#"{
+			"feeds": [{"id": 7, "url": "https://example.com/feed.xml"}],
+			"items": [
+				{"feed_id": 7, "url": "https://example.com/read", "is_read": 1},
+				{"feed_id": 7, "url": "https://example.com/unread", "is_read": 0},
+				{"feed_id": 8, "url": "https://example.com/orphan", "is_read": 1}
+			]
+		}"#;
+		assert_eq!(
+			parse_read_state(fever).unwrap(),
+			[(
+				"https://example.com/feed.xml".to_string(),
+				"https://example.com/read".to_string()
+			)]
+		);
+
+		let google_reader = r#"{
+			"items": [
+				{
+					"origin": {"streamId": "feed/https://example.com/atom.xml"},
+					"originId": "urn:one",
+					"categories": ["user/1/state/com.google/read"]
+				},
+				{
+					"origin": {"streamId": "feed/https://example.com/atom.xml"},
+					"alternate": [{"href": "https://example.com/two"}]
+				},
+				{
+					"origin": {"streamId": "feed/https://example.com/atom.xml"},
+					"originId": "urn:unread",
+					"categories": ["user/1/state/com.google/reading-list"]
+				}
+			]
+		}"#;
+		assert_eq!(
+			parse_read_state(google_reader).unwrap(),
+			[
+				("https://example.com/atom.xml".to_string(), "urn:one".to_string()),
+				(
+					"https://example.com/atom.xml".to_string(),
+					"https://example.com/two".to_string()
+				),
+			]
+		);
+
+		assert!(parse_read_state(r#"{"something": "else"}"#).is_err());
+	}
+}
@@ -1,148 +1,314 @@
 use std::{
 	collections::{BTreeMap, BTreeSet},
-	path::{Path, PathBuf},
+	io::ErrorKind,
+	path::PathBuf,
 	str::FromStr,
-	sync::Arc,
+	sync::{
+		atomic::{AtomicU64, AtomicUsize, Ordering},
+		Arc,
+	},
 	time::Duration,
 };
 
-use base64::{
-	engine::{GeneralPurpose, GeneralPurposeConfig},
-	Engine,
-};
-use inotify::{Inotify, WatchMask};
+use inotify::{EventMask, Inotify, WatchMask};
 use rss::Channel;
-use crate::syndication::Feed;
-use tokio::{sync::RwLock};
+use crate::syndication::{decode_feed, Feed};
+use tokio::sync::{Notify, RwLock};
+
+use super::{
+	read_store::ReadStore,
+	store::{Changes, Collection, Store, StoreWatcher},
+	Merge, SubscriptionMeta,
+};
+
+/// Batches of writes the database is making itself. Its caches already hold what it's
+/// writing, so the events those writes cause don't each need the directory rescanned.
+#[derive(Default)]
+pub(super) struct BulkWrites {
+	/// How many batches are underway.
+	active: AtomicUsize,
+	/// Bumped as each batch starts and ends.
+	epoch: AtomicU64,
+	/// How many times the loop has rescanned because of events, rather than its timer.
+	#[cfg(test)]
+	pub(super) rescans: AtomicUsize,
+}
+
+impl BulkWrites {
+	pub(super) fn start(self: &Arc<Self>) -> BulkWrite {
+		self.active.fetch_add(1, Ordering::SeqCst);
+		self.epoch.fetch_add(1, Ordering::SeqCst);
+		BulkWrite(self.clone())
+	}
+
+	/// Whether events read now may have come from a batch, because one is underway or one
+	/// started or ended since `seen_epoch`, which is brought up to date.
+	fn covers(&self, seen_epoch: &mut u64) -> bool {
+		let epoch = self.epoch.load(Ordering::SeqCst);
+		let covered = epoch != *seen_epoch || self.active.load(Ordering::SeqCst) > 0;
+		*seen_epoch = epoch;
+		covered
+	}
+}
+
+/// Holds off rescanning for the database's own writes until it's dropped. Changes made by
+/// anything else meanwhile are still picked up by the periodic rescan, just a little later.
+pub struct BulkWrite(Arc<BulkWrites>);
+
+impl Drop for BulkWrite {
+	fn drop(&mut self) {
+		self.0.active.fetch_sub(1, Ordering::SeqCst);
+		self.0.epoch.fetch_add(1, Ordering::SeqCst);
+	}
+}
+
+/// How often [`sync_loop`] checks for changes. Every fifth check rescans regardless.
+#[cfg(not(test))]
+pub(super) const POLL_INTERVAL: Duration = Duration::from_secs(1);
 
-use super::Merge;
+/// Tests check more often, so they don't spend seconds waiting for the loop to notice.
+#[cfg(test)]
+pub(super) const POLL_INTERVAL: Duration = Duration::from_millis(100);
 
-pub async fn inotify_loop(
-	src_dir: PathBuf,
+/// Keeps the caches in sync with the store until `shutdown` is notified. A refresh which is
+/// already underway is allowed to finish first.
+#[allow(clippy::too_many_arguments)]
+pub async fn sync_loop(
+	store: Arc<dyn Store>,
+	read_store: Arc<dyn ReadStore>,
 	read_articles: Arc<RwLock<BTreeSet<String>>>,
 	subscriptions: Arc<RwLock<BTreeMap<String, Arc<Feed>>>>,
+	meta: Arc<RwLock<BTreeMap<String, SubscriptionMeta>>>,
+	generation: Arc<AtomicU64>,
+	bulk_writes: Arc<BulkWrites>,
+	shutdown: Arc<Notify>,
 ) {
-	let base64 = base64::engine::general_purpose::GeneralPurpose::new(
-		&base64::alphabet::STANDARD,
-		GeneralPurposeConfig::default(),
-	);
-	let read_dir = src_dir.join("read");
-	let sub_dir = src_dir.join("subs");
-
-	let mut inotify = Inotify::init().expect("Couldn't start inotify");
-	inotify
-		.watches()
-		.add(&read_dir, WatchMask::CREATE | WatchMask::DELETE)
-		.expect("Failed to watch read dir");
-	inotify
-		.watches()
-		.add(
-			&sub_dir,
-			WatchMask::CREATE | WatchMask::DELETE | WatchMask::MODIFY,
-		)
-		.expect("Failed to watch subs dir");
-
-	refresh(&read_dir, &sub_dir, &read_articles, &subscriptions, &base64).await;
+	let mut watchers: Vec<_> = std::iter::once(store.watch())
+		.chain(read_store.watch())
+		.collect();
+
+	refresh(&store, &read_store, &read_articles, &subscriptions, &generation).await;
+	refresh_meta(&store, &meta).await;
 	let mut counter = 0u8;
+	let mut seen_epoch = bulk_writes.epoch.load(Ordering::SeqCst);
 	loop {
 		counter += 1;
-		if counter == 5
-			|| inotify
-				.read_events(&mut [0])
-				.ok()
-				.map(|mut i| i.next())
-				.is_some()
-		{
+		let changes = watchers
+			.iter_mut()
+			.map(|watcher| watcher.changes())
+			.max()
+			.unwrap_or_default();
+		let mut changed = changes != Changes::None;
+		if bulk_writes.covers(&mut seen_epoch) {
+			changed = changes == Changes::Replaced;
+		}
+		#[cfg(test)]
+		if changed {
+			bulk_writes.rescans.fetch_add(1, Ordering::Relaxed);
+		}
+		if counter == 5 || changed {
 			counter = 0;
-			refresh(&read_dir, &sub_dir, &read_articles, &subscriptions, &base64).await;
+			refresh(&store, &read_store, &read_articles, &subscriptions, &generation).await;
+			refresh_meta(&store, &meta).await;
+		}
+		tokio::select! {
+			() = shutdown.notified() => break,
+			() = tokio::time::sleep(POLL_INTERVAL) => {}
+		}
+	}
+}
+
+/// Watches directories with inotify, recreating and watching them again if they're deleted
+/// or moved.
+pub(super) struct FsWatcher {
+	inotify: Inotify,
+	watched: Vec<(PathBuf, WatchMask)>,
+	buffer: [u8; 4096],
+}
+
+impl FsWatcher {
+	pub(super) fn new(watched: Vec<(PathBuf, WatchMask)>) -> Self {
+		let mut watcher = Self {
+			inotify: Inotify::init().expect("Couldn't start inotify"),
+			watched,
+			buffer: [0; 4096],
+		};
+		watcher.watch_all();
+		watcher
+	}
+
+	/// Watches each directory, creating any which have gone missing.
+	fn watch_all(&mut self) {
+		for (dir, mask) in &self.watched {
+			if let Err(e) = std::fs::create_dir_all(dir) {
+				log::error!("Couldn't create {}: {e}", dir.display());
+				continue;
+			}
+			let mask = *mask | WatchMask::DELETE_SELF | WatchMask::MOVE_SELF;
+			if let Err(e) = self.inotify.watches().add(dir, mask) {
+				log::error!("Couldn't watch {}: {e}", dir.display());
+			}
 		}
-		tokio::time::sleep(Duration::from_secs(1)).await;
 	}
 }
 
-async fn refresh(
-	read_dir: &Path,
-	sub_dir: &Path,
+impl StoreWatcher for FsWatcher {
+	fn changes(&mut self) -> Changes {
+		let mut changed = false;
+		let mut lost_watch = false;
+		let mut moved = vec![];
+		// Drain everything that's queued, so a burst of events is dealt with all at once.
+		loop {
+			match self.inotify.read_events(&mut self.buffer) {
+				Ok(events) => {
+					for event in events {
+						changed = true;
+						if event.mask.contains(EventMask::MOVE_SELF) {
+							// The watch follows the directory to wherever it went, which is no use.
+							moved.push(event.wd.clone());
+						}
+						lost_watch |= event.mask.intersects(
+							EventMask::DELETE_SELF | EventMask::MOVE_SELF | EventMask::IGNORED,
+						);
+					}
+				}
+				Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+				Err(e) => {
+					log::error!("Couldn't read inotify events: {e}");
+					break;
+				}
+			}
+		}
+		for wd in moved {
+			let _ = self.inotify.watches().remove(wd);
+		}
+		if lost_watch {
+			// Something replaced the directory tree, so start watching the new one.
+			self.watch_all();
+			Changes::Replaced
+		} else if changed {
+			Changes::Entries
+		} else {
+			Changes::None
+		}
+	}
+}
+
+/// Reads every entry in a collection, or `None` if it couldn't be listed.
+async fn load_all(
+	store: &Arc<dyn Store>,
+	collection: Collection,
+) -> Option<Vec<(String, Vec<u8>)>> {
+	let store = store.clone();
+	let loaded = tokio::task::spawn_blocking(move || {
+		let keys = match store.list(collection) {
+			Ok(keys) => keys,
+			Err(e) => {
+				log::error!("Couldn't list {}: {e}", collection.dir_name());
+				return None;
+			}
+		};
+		let entries = keys
+			.into_iter()
+			.filter_map(|key| match store.read(collection, &key) {
+				Ok(data) => Some((key, data)),
+				// It was removed since it was listed.
+				Err(e) if e.kind() == ErrorKind::NotFound => None,
+				Err(e) => {
+					log::error!("Couldn't read {key}, {e}");
+					None
+				}
+			})
+			.collect();
+		Some(entries)
+	})
+	.await;
+	loaded.unwrap_or_else(|e| {
+		log::error!("Couldn't load {}: {e}", collection.dir_name());
+		None
+	})
+}
+
+/// Reloads the read markers and subscriptions, bumping `generation` if the set of articles or
+/// which of them are read changed. The caches stay locked until the generation is bumped, so
+/// anything that sees the change also sees the new generation.
+pub(super) async fn refresh(
+	store: &Arc<dyn Store>,
+	read_store: &Arc<dyn ReadStore>,
 	read_articles: &Arc<RwLock<BTreeSet<String>>>,
 	subscriptions: &Arc<RwLock<BTreeMap<String, Arc<Feed>>>>,
-	base64: &GeneralPurpose,
+	generation: &AtomicU64,
 ) {
 	{
-		let mut read_dir = tokio::fs::read_dir(read_dir)
-			.await
-			.expect("Couldn't read read_dir");
+		// Held while loading, so an article marked read meanwhile isn't forgotten.
 		let mut read_articles = read_articles.write().await;
-		read_articles.clear();
-		while let Ok(Some(entry)) = read_dir.next_entry().await {
-			let name = entry.file_name();
-			let Some(name) = name.to_str() else {
-                eprintln!("File's name is not utf8");
-                continue;
-            };
-			let Ok(data) = base64.decode(name) else {
-                eprintln!("File {name}'s name is not base64");
-                continue;
-            };
-			let Ok(id) = String::from_utf8(data) else {
-                eprintln!("File {name}'s name is not base64'd utf8");
-                continue;
-            };
-			read_articles.insert(id);
+		let store = read_store.clone();
+		match tokio::task::spawn_blocking(move || store.load()).await {
+			Ok(Some(found)) => {
+				if *read_articles != found {
+					*read_articles = found;
+					generation.fetch_add(1, Ordering::Relaxed);
+				}
+			}
+			Ok(None) => {}
+			Err(e) => log::error!("Couldn't load read articles: {e}"),
 		}
 	}
-	{
-		let mut sub_dir = tokio::fs::read_dir(sub_dir)
-			.await
-			.expect("Couldn't read sub_dir");
-		let mut subscriptions = subscriptions.write().await;
-		let mut still_in_subs = BTreeSet::default();
-		while let Ok(Some(entry)) = sub_dir.next_entry().await {
-			// Get the subscription's URL
-			let name = entry.file_name();
-			let Some(name) = name.to_str() else {
-                eprintln!("File's name is not utf8");
-                continue;
-            };
-			let Ok(data) = base64.decode(name) else {
-                eprintln!("File {name}'s name is not base64");
-                continue;
-            };
-			let Ok(pub_url) = String::from_utf8(data) else {
-                eprintln!("File {name}'s name is not base64'd utf8");
-                continue;
-            };
-			// Get the subscription's contents
-			// let file = match OpenOptions::new().read(true).open(entry.path()).await {
-			// 	Err(e) => {
-			// 		eprintln!("Couldn't read {name}, {e}");
-			// 		continue;
-			// 	}
-			// 	Ok(f) => f,
-			// };
-			let file = match tokio::fs::read_to_string(entry.path()).await {
-				Err(e) => {
-					eprintln!("Couldn't read {name}, {e}");
-					continue;
-				}
-				Ok(f) => f,
-			};
-			let channel = match Feed::from_str(&file) {
-				Ok(c) => c,
-				Err(e) => {
-					eprintln!("RSS in {name} is invalid: {e}");
-					continue;
-				}
-            };
-
-			still_in_subs.insert(pub_url.clone());
-			let sub = Arc::make_mut(subscriptions.entry(pub_url).or_insert_with(
-				|| match channel {
-					Feed::RSS(_) => Arc::new(Feed::RSS(Channel::default())),
-					Feed::Atom(_) => Arc::new(Feed::Atom(atom_syndication::Feed::default())),
-				},
-			));
-			sub.merge(&channel);
+	// Locked before listing the store, so a subscription written meanwhile can't be missed.
+	let mut subscriptions = subscriptions.write().await;
+	let Some(entries) = load_all(store, Collection::Subs).await else {
+		return;
+	};
+	let mut changed = false;
+	let mut still_in_subs = BTreeSet::default();
+	for (pub_url, data) in entries {
+		let channel = match Feed::from_str(&decode_feed(&data, None)) {
+			Ok(c) => c,
+			Err(e) => {
+				log::warn!("Feed {pub_url} is invalid: {e}");
+				continue;
+			}
+		};
+
+		still_in_subs.insert(pub_url.clone());
+		changed |= !subscriptions.contains_key(&pub_url);
+		let sub = Arc::make_mut(subscriptions.entry(pub_url).or_insert_with(
+			|| match channel {
+				Feed::RSS(_) => Arc::new(Feed::RSS(Channel::default())),
+				Feed::Atom(_) => Arc::new(Feed::Atom(atom_syndication::Feed::default())),
+			},
+		));
+		changed |= sub.merge(&channel) > 0;
+	}
+	let before = subscriptions.len();
+	subscriptions.retain(|k, _| still_in_subs.contains(k));
+	changed |= subscriptions.len() != before;
+	if changed {
+		generation.fetch_add(1, Ordering::Relaxed);
+	}
+}
+
+pub(super) async fn refresh_meta(
+	store: &Arc<dyn Store>,
+	meta: &Arc<RwLock<BTreeMap<String, SubscriptionMeta>>>,
+) {
+	// Locked before listing the store, so metadata written meanwhile isn't replaced by what
+	// was there before.
+	let mut meta = meta.write().await;
+	let Some(entries) = load_all(store, Collection::Meta).await else {
+		return;
+	};
+	let mut new_meta = BTreeMap::new();
+	for (pub_url, data) in entries {
+		let parsed = String::from_utf8(data)
+			.map_err(|e| e.to_string())
+			.and_then(|text| toml::from_str::<SubscriptionMeta>(&text).map_err(|e| e.to_string()));
+		match parsed {
+			Ok(m) => {
+				new_meta.insert(pub_url, m);
+			}
+			Err(e) => log::warn!("Metadata for {pub_url} is invalid: {e}"),
 		}
-		subscriptions.retain(|k, _| still_in_subs.contains(k));
 	}
+	*meta = new_meta;
 }
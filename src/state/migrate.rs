@@ -0,0 +1,114 @@
+//! Moves files written before subscriptions were keyed by [`feed_identity`] to their new
+//! names.
+
+use std::{
+	fs,
+	path::{Path, PathBuf},
+	str::FromStr,
+};
+
+use base64::{engine::GeneralPurpose, Engine};
+
+use super::{feed_identity, meta::SubscriptionMeta, Merge};
+use crate::syndication::{decode_feed, Feed};
+
+/// The decoded names of the files in `dir`, alongside their paths.
+fn decoded_entries(dir: &Path, base64: &GeneralPurpose) -> Vec<(String, PathBuf)> {
+	let Ok(entries) = fs::read_dir(dir) else {
+		return vec![];
+	};
+	entries
+		.filter_map(Result::ok)
+		.filter_map(|entry| {
+			let name = base64.decode(entry.file_name().to_str()?).ok()?;
+			Some((String::from_utf8(name).ok()?, entry.path()))
+		})
+		.collect()
+}
+
+fn encode(key: &str, base64: &GeneralPurpose) -> String {
+	let mut name = String::new();
+	base64.encode_string(key, &mut name);
+	name
+}
+
+/// Renames every subscription stored under a URL to its identity, moving its metadata and
+/// read markers along with it and remembering the URL. Subscriptions which turn out to be
+/// the same feed are merged.
+///
+/// Read markers for feeds which are no longer subscribed to are left behind, so articles
+/// start out unread if one is subscribed to again. A marker's name is the feed's URL and the
+/// article's ID joined by `%`, and either may contain `%` too, so without the subscription's
+/// URL to match against there's no telling where one ends.
+pub(super) fn migrate_to_identities(
+	read_dir: &Path,
+	subs_dir: &Path,
+	meta_dir: &Path,
+	base64: &GeneralPurpose,
+) {
+	let read_markers = decoded_entries(read_dir, base64);
+	for (pub_url, path) in decoded_entries(subs_dir, base64) {
+		let key = feed_identity(&pub_url);
+		if key == pub_url {
+			continue;
+		}
+		if let Err(e) = migrate_subscription(&pub_url, &key, &path, subs_dir, meta_dir, base64) {
+			log::error!("Couldn't migrate the subscription to {pub_url}: {e}");
+			continue;
+		}
+		let prefix = format!("{pub_url}%");
+		for (marker, marker_path) in &read_markers {
+			let Some(guid) = marker.strip_prefix(&prefix) else {
+				continue;
+			};
+			let renamed = read_dir.join(encode(&format!("{key}%{guid}"), base64));
+			if let Err(e) = fs::rename(marker_path, renamed) {
+				log::error!("Couldn't migrate the read marker for {marker}: {e}");
+			}
+		}
+	}
+}
+
+fn migrate_subscription(
+	pub_url: &str,
+	key: &str,
+	path: &Path,
+	subs_dir: &Path,
+	meta_dir: &Path,
+	base64: &GeneralPurpose,
+) -> std::io::Result<()> {
+	let renamed = subs_dir.join(encode(key, base64));
+	if renamed.exists() {
+		let read_feed = |path: &Path| -> std::io::Result<Feed> {
+			Feed::from_str(&decode_feed(&fs::read(path)?, None)).map_err(std::io::Error::other)
+		};
+		let mut existing = read_feed(&renamed)?;
+		existing.merge(&read_feed(path)?);
+		fs::write(&renamed, existing.to_string())?;
+		fs::remove_file(path)?;
+	} else {
+		fs::rename(path, &renamed)?;
+	}
+
+	let old_meta = meta_dir.join(encode(pub_url, base64));
+	let new_meta = meta_dir.join(encode(key, base64));
+	let read_meta = |path: &Path| -> std::io::Result<SubscriptionMeta> {
+		match fs::read_to_string(path) {
+			Ok(text) => toml::from_str(&text).map_err(std::io::Error::other),
+			Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(SubscriptionMeta::default()),
+			Err(e) => Err(e),
+		}
+	};
+	let mut meta = read_meta(&new_meta)?;
+	let legacy = read_meta(&old_meta)?;
+	meta.credentials = meta.credentials.or(legacy.credentials);
+	meta.url = meta.url.or_else(|| Some(pub_url.to_string()));
+	fs::write(
+		&new_meta,
+		toml::to_string(&meta).map_err(std::io::Error::other)?,
+	)?;
+	if old_meta.exists() {
+		fs::remove_file(&old_meta)?;
+	}
+	Ok(())
+}
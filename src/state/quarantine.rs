@@ -0,0 +1,67 @@
+//! Moves files whose names can't be decoded out of the way, so they don't break the
+//! subscription or read marker they were meant to be, or get complained about every refresh.
+
+use std::{fs, path::Path};
+
+use base64::{engine::GeneralPurpose, Engine};
+
+/// Where unreadable files are moved to, inside the database directory.
+pub const QUARANTINE_DIR: &str = ".winter-quarantine";
+
+/// Whether a file name is a base64-encoded UTF-8 key, like the database writes.
+fn is_valid_name(name: &str, base64: &GeneralPurpose) -> bool {
+	base64
+		.decode(name)
+		.is_ok_and(|data| String::from_utf8(data).is_ok())
+}
+
+/// Moves every file in `dirs` whose name isn't a valid key into a matching directory under
+/// [`QUARANTINE_DIR`], returning how many were moved.
+pub(super) fn quarantine_invalid(
+	src_dir: &Path,
+	dirs: &[&Path],
+	base64: &GeneralPurpose,
+) -> usize {
+	let mut moved = 0;
+	for dir in dirs {
+		let Ok(entries) = fs::read_dir(dir) else {
+			continue;
+		};
+		let invalid: Vec<_> = entries
+			.filter_map(Result::ok)
+			.filter(|entry| {
+				!entry
+					.file_name()
+					.to_str()
+					.is_some_and(|name| is_valid_name(name, base64))
+			})
+			.collect();
+		if invalid.is_empty() {
+			continue;
+		}
+		let quarantine = src_dir
+			.join(QUARANTINE_DIR)
+			.join(dir.file_name().unwrap_or_default());
+		if let Err(e) = fs::create_dir_all(&quarantine) {
+			log::error!("Couldn't create {}: {e}", quarantine.display());
+			continue;
+		}
+		for entry in invalid {
+			let name = entry.file_name();
+			let mut target = quarantine.join(&name);
+			// Don't clobber anything quarantined earlier under the same name.
+			let mut n = 1;
+			while target.exists() {
+				let mut numbered = name.clone();
+				numbered.push(format!(".{n}"));
+				target = quarantine.join(numbered);
+				n += 1;
+			}
+			match fs::rename(entry.path(), &target) {
+				Ok(()) => moved += 1,
+				Err(e) => log::error!("Couldn't quarantine {}: {e}", entry.path().display()),
+			}
+		}
+	}
+	moved
+}
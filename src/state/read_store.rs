@@ -0,0 +1,234 @@
+//! Where the database keeps track of which articles have been read.
+
+use std::{
+	collections::BTreeSet,
+	fs::{self, OpenOptions},
+	io::{ErrorKind, Write},
+	path::{Path, PathBuf},
+	sync::{Arc, Mutex},
+};
+
+use base64::{
+	engine::{GeneralPurpose, GeneralPurposeConfig},
+	Engine,
+};
+use inotify::WatchMask;
+
+use super::{
+	inotify::FsWatcher,
+	store::{Collection, Store, StoreWatcher},
+};
+
+/// The name of the [`ReadStorage::Index`] file, inside the database directory.
+pub const READ_INDEX: &str = "read.log";
+
+/// How the database records which articles have been read.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ReadStorage {
+	/// An empty marker file per read article in `read/`, which is easy for other tools to
+	/// work with, but slow to list once there are tens of thousands of them.
+	#[default]
+	Directory,
+	/// A single log in [`READ_INDEX`] of articles being marked read and unread. When it's
+	/// first used, it starts out with whatever `read/` already holds.
+	Index,
+}
+
+/// Persists the keys of read articles, as made by `Database::read_key`.
+pub(super) trait ReadStore: Send + Sync {
+	/// Every key which is marked read, or `None` if they couldn't be loaded.
+	fn load(&self) -> Option<BTreeSet<String>>;
+
+	fn mark_read(&self, key: &str) -> std::io::Result<()>;
+
+	/// Marks every one of `keys` read, which stores can do in one go.
+	fn mark_all_read(&self, keys: &[String]) -> std::io::Result<()> {
+		keys.iter().try_for_each(|key| self.mark_read(key))
+	}
+
+	fn mark_unread(&self, key: &str) -> std::io::Result<()>;
+
+	/// Marks every one of `keys` unread, which stores can do in one go.
+	fn mark_all_unread(&self, keys: &[String]) -> std::io::Result<()> {
+		keys.iter().try_for_each(|key| self.mark_unread(key))
+	}
+
+	/// Watches for changes made by something else, unless they're made to the [`Store`] the
+	/// database already watches.
+	fn watch(&self) -> Option<Box<dyn StoreWatcher>>;
+}
+
+/// What's written in [`Collection::Read`] for each read article.
+const MARKER: &[u8] = b"This article has been read";
+
+/// Keeps read articles in `store`'s [`Collection::Read`].
+pub(super) fn in_store(store: Arc<dyn Store>) -> Arc<dyn ReadStore> {
+	Arc::new(MarkerStore { store })
+}
+
+/// Opens the kind of store `storage` asks for in `src_dir`, where `store` keeps the rest of
+/// the database.
+pub(super) fn open(
+	src_dir: &Path,
+	storage: ReadStorage,
+	store: Arc<dyn Store>,
+) -> Arc<dyn ReadStore> {
+	let directory = MarkerStore { store };
+	match storage {
+		ReadStorage::Directory => Arc::new(directory),
+		ReadStorage::Index => {
+			let index = IndexStore {
+				path: src_dir.join(READ_INDEX),
+				base64: engine(),
+				append: Mutex::new(()),
+			};
+			let existing = if index.path.exists() {
+				index.load()
+			} else {
+				directory.load()
+			};
+			if let Err(e) = index.compact(&existing.unwrap_or_default()) {
+				log::error!("Couldn't write {}: {e}", index.path.display());
+			}
+			Arc::new(index)
+		}
+	}
+}
+
+fn engine() -> GeneralPurpose {
+	GeneralPurpose::new(&base64::alphabet::STANDARD, GeneralPurposeConfig::default())
+}
+
+fn encode(key: &str, base64: &GeneralPurpose) -> String {
+	let mut name = String::new();
+	base64.encode_string(key, &mut name);
+	name
+}
+
+fn decode(name: &str, base64: &GeneralPurpose) -> Option<String> {
+	String::from_utf8(base64.decode(name).ok()?).ok()
+}
+
+/// A marker per read article, in the store's [`Collection::Read`].
+struct MarkerStore {
+	store: Arc<dyn Store>,
+}
+
+impl ReadStore for MarkerStore {
+	fn load(&self) -> Option<BTreeSet<String>> {
+		match self.store.list(Collection::Read) {
+			Ok(keys) => Some(keys.into_iter().collect()),
+			Err(e) => {
+				log::error!("Couldn't list read articles: {e}");
+				None
+			}
+		}
+	}
+
+	fn mark_read(&self, key: &str) -> std::io::Result<()> {
+		self.store.write(Collection::Read, key, MARKER)
+	}
+
+	fn mark_all_read(&self, keys: &[String]) -> std::io::Result<()> {
+		let entries: Vec<_> = keys.iter().map(|key| (key.as_str(), MARKER)).collect();
+		self.store.write_all(Collection::Read, &entries)
+	}
+
+	fn mark_unread(&self, key: &str) -> std::io::Result<()> {
+		self.store.delete(Collection::Read, key)
+	}
+
+	fn watch(&self) -> Option<Box<dyn StoreWatcher>> {
+		// Watching the store covers this.
+		None
+	}
+}
+
+/// A log with a line per change: `+` and the base64-encoded key when an article is read, or
+/// `-` when it's unread. It's compacted down to just the read articles when it's opened.
+struct IndexStore {
+	path: PathBuf,
+	base64: GeneralPurpose,
+	/// Held while appending, so lines from different threads don't interleave.
+	append: Mutex<()>,
+}
+
+impl IndexStore {
+	fn append(&self, line: &str) -> std::io::Result<()> {
+		let _lock = self.append.lock().expect("Read index lock poisoned");
+		OpenOptions::new()
+			.create(true)
+			.append(true)
+			.open(&self.path)?
+			.write_all(line.as_bytes())
+	}
+
+	/// Replaces the log with one which only marks `keys` read.
+	fn compact(&self, keys: &BTreeSet<String>) -> std::io::Result<()> {
+		let text: String = keys
+			.iter()
+			.map(|key| format!("+{}\n", encode(key, &self.base64)))
+			.collect();
+		let tmp = self.path.with_extension("log.tmp");
+		fs::write(&tmp, text)?;
+		fs::rename(tmp, &self.path)
+	}
+}
+
+impl ReadStore for IndexStore {
+	fn load(&self) -> Option<BTreeSet<String>> {
+		let text = match fs::read_to_string(&self.path) {
+			Ok(text) => text,
+			Err(e) if e.kind() == ErrorKind::NotFound => return Some(BTreeSet::new()),
+			Err(e) => {
+				log::error!("Couldn't read {}: {e}", self.path.display());
+				return None;
+			}
+		};
+		let mut found = BTreeSet::new();
+		let decode = |name: &str| decode(name, &self.base64);
+		for line in text.lines().filter(|line| !line.is_empty()) {
+			if let Some(key) = line.strip_prefix('+').and_then(decode) {
+				found.insert(key);
+			} else if let Some(key) = line.strip_prefix('-').and_then(decode) {
+				found.remove(&key);
+			} else {
+				log::warn!("Skipping invalid line in {}: {line}", self.path.display());
+			}
+		}
+		Some(found)
+	}
+
+	fn mark_read(&self, key: &str) -> std::io::Result<()> {
+		self.append(&format!("+{}\n", encode(key, &self.base64)))
+	}
+
+	fn mark_all_read(&self, keys: &[String]) -> std::io::Result<()> {
+		let lines: String = keys
+			.iter()
+			.map(|key| format!("+{}\n", encode(key, &self.base64)))
+			.collect();
+		self.append(&lines)
+	}
+
+	fn mark_unread(&self, key: &str) -> std::io::Result<()> {
+		self.append(&format!("-{}\n", encode(key, &self.base64)))
+	}
+
+	fn mark_all_unread(&self, keys: &[String]) -> std::io::Result<()> {
+		let lines: String = keys
+			.iter()
+			.map(|key| format!("-{}\n", encode(key, &self.base64)))
+			.collect();
+		self.append(&lines)
+	}
+
+	fn watch(&self) -> Option<Box<dyn StoreWatcher>> {
+		// Compacting renames a new log into place, so the directory is watched rather than
+		// the file itself.
+		let dir = self.path.parent().map(Path::to_path_buf).unwrap_or_default();
+		let mask = WatchMask::CREATE | WatchMask::MODIFY | WatchMask::DELETE | WatchMask::MOVED_TO;
+		Some(Box::new(FsWatcher::new(vec![(dir, mask)])))
+	}
+}
@@ -0,0 +1,294 @@
+//! Where the database keeps its subscriptions, their metadata and its read markers.
+
+use std::{
+	collections::BTreeMap,
+	fs,
+	io::ErrorKind,
+	path::PathBuf,
+	sync::{
+		atomic::{AtomicU64, Ordering},
+		Arc, Mutex,
+	},
+};
+
+use base64::{
+	engine::{GeneralPurpose, GeneralPurposeConfig},
+	Engine,
+};
+use inotify::WatchMask;
+
+use super::inotify::FsWatcher;
+
+/// A group of entries in a [`Store`], each under its own key.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Collection {
+	/// An empty entry per read article.
+	Read,
+	/// A feed per subscription, keyed by its identity.
+	Subs,
+	/// A subscription's metadata, as TOML, under the same key as the subscription.
+	Meta,
+}
+
+impl Collection {
+	pub const ALL: [Collection; 3] = [Collection::Read, Collection::Subs, Collection::Meta];
+
+	/// The directory the collection is kept in, inside the database directory.
+	#[must_use]
+	pub fn dir_name(self) -> &'static str {
+		match self {
+			Collection::Read => "read",
+			Collection::Subs => "subs",
+			Collection::Meta => "meta",
+		}
+	}
+}
+
+/// What a [`StoreWatcher`] has seen since it was last asked, least drastic first.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Changes {
+	#[default]
+	None,
+	/// Entries were added, changed or removed.
+	Entries,
+	/// A whole collection was replaced, like by a directory being moved into place.
+	Replaced,
+}
+
+/// Notices changes made to a store by anything else, like another database or a sync tool.
+pub trait StoreWatcher: Send {
+	/// What's changed since the last call. Changes the database made itself are usually
+	/// included too.
+	fn changes(&mut self) -> Changes;
+}
+
+/// Keeps the database's entries. The database caches everything in memory, and rescans the
+/// store when its [`StoreWatcher`] says something changed, so implementations only need to
+/// be simple, not fast. Calls may block, and are made from blocking threads.
+pub trait Store: Send + Sync {
+	/// The keys of every entry in `collection`.
+	///
+	/// # Errors
+	/// Fails if the collection can't be listed.
+	fn list(&self, collection: Collection) -> std::io::Result<Vec<String>>;
+
+	/// # Errors
+	/// Fails with [`ErrorKind::NotFound`] if there's no such entry.
+	fn read(&self, collection: Collection, key: &str) -> std::io::Result<Vec<u8>>;
+
+	/// Creates or replaces an entry.
+	///
+	/// # Errors
+	/// Fails if the entry can't be written.
+	fn write(&self, collection: Collection, key: &str, data: &[u8]) -> std::io::Result<()>;
+
+	/// Writes every one of `entries`, which stores can do in one go.
+	///
+	/// # Errors
+	/// Fails as soon as any entry can't be written.
+	fn write_all(&self, collection: Collection, entries: &[(&str, &[u8])]) -> std::io::Result<()> {
+		entries
+			.iter()
+			.try_for_each(|(key, data)| self.write(collection, key, data))
+	}
+
+	/// Removes an entry. Removing one which doesn't exist isn't an error.
+	///
+	/// # Errors
+	/// Fails if the entry exists but can't be removed.
+	fn delete(&self, collection: Collection, key: &str) -> std::io::Result<()>;
+
+	/// Starts watching for changes.
+	fn watch(&self) -> Box<dyn StoreWatcher>;
+}
+
+/// The database directory, with a directory per [`Collection`], and a file per entry named
+/// after its base64-encoded key, which makes it easy to sync with naive file-based tools.
+pub(super) struct FsStore {
+	dir: PathBuf,
+	base64: GeneralPurpose,
+}
+
+impl FsStore {
+	pub(super) fn new(dir: PathBuf) -> Self {
+		Self {
+			dir,
+			base64: GeneralPurpose::new(
+				&base64::alphabet::STANDARD,
+				GeneralPurposeConfig::default(),
+			),
+		}
+	}
+
+	fn path(&self, collection: Collection, key: &str) -> PathBuf {
+		let mut name = String::new();
+		self.base64.encode_string(key, &mut name);
+		self.dir.join(collection.dir_name()).join(name)
+	}
+}
+
+impl Store for FsStore {
+	/// Lists a collection's directory, recreating it if it's gone missing. Files whose names
+	/// aren't keys are skipped.
+	fn list(&self, collection: Collection) -> std::io::Result<Vec<String>> {
+		let dir = self.dir.join(collection.dir_name());
+		let entries = match fs::read_dir(&dir) {
+			Ok(entries) => entries,
+			Err(e) if e.kind() == ErrorKind::NotFound => {
+				fs::create_dir_all(&dir)?;
+				return Ok(vec![]);
+			}
+			Err(e) => return Err(e),
+		};
+		let mut keys = vec![];
+		for entry in entries.filter_map(Result::ok) {
+			let name = entry.file_name();
+			let Some(name) = name.to_str() else {
+				log::warn!("File's name is not utf8");
+				continue;
+			};
+			let Ok(data) = self.base64.decode(name) else {
+				log::warn!("File {name}'s name is not base64");
+				continue;
+			};
+			let Ok(key) = String::from_utf8(data) else {
+				log::warn!("File {name}'s name is not base64'd utf8");
+				continue;
+			};
+			keys.push(key);
+		}
+		Ok(keys)
+	}
+
+	fn read(&self, collection: Collection, key: &str) -> std::io::Result<Vec<u8>> {
+		fs::read(self.path(collection, key))
+	}
+
+	fn write(&self, collection: Collection, key: &str, data: &[u8]) -> std::io::Result<()> {
+		fs::write(self.path(collection, key), data)
+	}
+
+	fn delete(&self, collection: Collection, key: &str) -> std::io::Result<()> {
+		match fs::remove_file(self.path(collection, key)) {
+			Err(e) if e.kind() == ErrorKind::NotFound => Ok(()),
+			result => result,
+		}
+	}
+
+	fn watch(&self) -> Box<dyn StoreWatcher> {
+		let watched = Collection::ALL
+			.into_iter()
+			.map(|collection| {
+				let mask = match collection {
+					Collection::Read => WatchMask::CREATE | WatchMask::DELETE,
+					Collection::Subs | Collection::Meta => {
+						WatchMask::CREATE | WatchMask::DELETE | WatchMask::MODIFY
+					}
+				};
+				(self.dir.join(collection.dir_name()), mask)
+			})
+			.collect();
+		Box::new(FsWatcher::new(watched))
+	}
+}
+
+/// Keeps everything in memory, for tests and anything else which doesn't need it to last.
+/// Databases opened on clones of the same `Arc` see each other's changes.
+#[derive(Default)]
+pub struct MemoryStore {
+	entries: Mutex<BTreeMap<(Collection, String), Vec<u8>>>,
+	/// Bumped on every change, so watchers can tell something happened.
+	version: Arc<AtomicU64>,
+}
+
+impl MemoryStore {
+	fn entries(&self) -> std::sync::MutexGuard<'_, BTreeMap<(Collection, String), Vec<u8>>> {
+		self.entries.lock().expect("Memory store lock poisoned")
+	}
+
+	fn changed(&self) {
+		self.version.fetch_add(1, Ordering::SeqCst);
+	}
+}
+
+impl Store for MemoryStore {
+	fn list(&self, collection: Collection) -> std::io::Result<Vec<String>> {
+		Ok(self
+			.entries()
+			.keys()
+			.filter(|(c, _)| *c == collection)
+			.map(|(_, key)| key.clone())
+			.collect())
+	}
+
+	fn read(&self, collection: Collection, key: &str) -> std::io::Result<Vec<u8>> {
+		self.entries()
+			.get(&(collection, key.to_string()))
+			.cloned()
+			.ok_or_else(|| ErrorKind::NotFound.into())
+	}
+
+	fn write(&self, collection: Collection, key: &str, data: &[u8]) -> std::io::Result<()> {
+		self.entries()
+			.insert((collection, key.to_string()), data.to_vec());
+		self.changed();
+		Ok(())
+	}
+
+	fn delete(&self, collection: Collection, key: &str) -> std::io::Result<()> {
+		if self
+			.entries()
+			.remove(&(collection, key.to_string()))
+			.is_some()
+		{
+			self.changed();
+		}
+		Ok(())
+	}
+
+	fn watch(&self) -> Box<dyn StoreWatcher> {
+		Box::new(MemoryWatcher {
+			seen: self.version.load(Ordering::SeqCst),
+			version: self.version.clone(),
+		})
+	}
+}
+
+struct MemoryWatcher {
+	version: Arc<AtomicU64>,
+	seen: u64,
+}
+
+impl StoreWatcher for MemoryWatcher {
+	fn changes(&mut self) -> Changes {
+		let version = self.version.load(Ordering::SeqCst);
+		if std::mem::replace(&mut self.seen, version) == version {
+			Changes::None
+		} else {
+			Changes::Entries
+		}
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::{Changes, Collection, MemoryStore, Store};
+
+	#[test]
+	fn memory_store_keeps_entries_apart() {
+		let store = MemoryStore::default();
+		let mut watcher = store.watch();
+		store.write(Collection::Subs, "feed", b"<rss/>").unwrap();
+		store.write(Collection::Meta, "feed", b"").unwrap();
+		assert_eq!(watcher.changes(), Changes::Entries);
+		assert_eq!(watcher.changes(), Changes::None);
+
+		assert_eq!(store.list(Collection::Subs).unwrap(), ["feed"]);
+		assert_eq!(store.read(Collection::Subs, "feed").unwrap(), b"<rss/>");
+		assert!(store.list(Collection::Read).unwrap().is_empty());
+		store.delete(Collection::Subs, "feed").unwrap();
+		store.delete(Collection::Subs, "feed").unwrap();
+		assert!(store.read(Collection::Subs, "feed").is_err());
+		assert_eq!(store.list(Collection::Meta).unwrap(), ["feed"]);
+	}
+}
@@ -0,0 +1,196 @@
+//! Checking what's at a feed URL, without subscribing to it.
+
+use crate::{feed::DiscoveredFeed, syndication::Feed};
+
+use super::{download_body, ChannelFromBytesError, RefreshError, WFeed};
+
+/// What kind of document a URL turned out to hold.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum FeedFormat {
+	Rss,
+	Atom,
+	/// A [JSON Feed](https://jsonfeed.org), which can't be subscribed to yet.
+	JsonFeed,
+	/// A web page, with any feeds it links to.
+	Html(Vec<DiscoveredFeed>),
+	/// Nothing which could be made sense of, and why.
+	Invalid(&'static str),
+}
+
+/// A report on a feed, from [`validate_feed`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FeedValidation {
+	pub format: FeedFormat,
+	pub title: Option<String>,
+	pub items: usize,
+	/// Things which don't stop the feed being read, but may make it less useful, like items
+	/// without dates.
+	pub warnings: Vec<String>,
+}
+
+impl FeedValidation {
+	/// Checks a downloaded document, which is in the `charset` its response gave, if any.
+	#[must_use]
+	pub fn from_bytes(bytes: &[u8], charset: Option<&str>) -> Self {
+		match WFeed::from_bytes(bytes, charset) {
+			Ok(feed) => Self::of_feed(&feed.0),
+			Err(ChannelFromBytesError::HTMLWithLink(found)) => Self::other(FeedFormat::Html(found)),
+			Err(ChannelFromBytesError::BadFeed(_)) if is_json_feed(bytes) => {
+				let mut report = Self::other(FeedFormat::JsonFeed);
+				report
+					.warnings
+					.push("JSON Feeds aren't supported, so this can't be subscribed to".to_string());
+				report
+			}
+			Err(ChannelFromBytesError::BadFeed(e)) => Self::other(FeedFormat::Invalid(e)),
+			Err(ChannelFromBytesError::Undecodable(_)) => {
+				Self::other(FeedFormat::Invalid("The response couldn't be decompressed"))
+			}
+		}
+	}
+
+	/// Checks a feed which has already been parsed.
+	#[must_use]
+	pub fn of_feed(feed: &Feed) -> Self {
+		let (format, title, items, empty) = match feed {
+			Feed::RSS(channel) => (
+				FeedFormat::Rss,
+				channel.title(),
+				channel.items().len(),
+				channel
+					.items()
+					.iter()
+					.filter(|item| {
+						item.title().is_none()
+							&& item.description().is_none()
+							&& item.content().is_none()
+					})
+					.count(),
+			),
+			Feed::Atom(atom) => (
+				FeedFormat::Atom,
+				atom.title().as_str(),
+				atom.entries().len(),
+				atom.entries()
+					.iter()
+					.filter(|entry| {
+						entry.title().trim().is_empty()
+							&& entry.content().is_none()
+							&& entry.summary().is_none()
+					})
+					.count(),
+			),
+		};
+		let undated = feed
+			.articles_iter("")
+			.filter(|article| article.timestamp.timestamp() == 0)
+			.count();
+		let title = Some(title.trim().to_string()).filter(|title| !title.is_empty());
+
+		let mut warnings = vec![];
+		if title.is_none() {
+			warnings.push("The feed has no title".to_string());
+		}
+		if items == 0 {
+			warnings.push("The feed has no items".to_string());
+		}
+		if undated > 0 {
+			warnings.push(format!("{undated} of {items} items have no date"));
+		}
+		if empty > 0 {
+			warnings.push(format!("{empty} of {items} items have no title or content"));
+		}
+		Self {
+			format,
+			title,
+			items,
+			warnings,
+		}
+	}
+
+	fn other(format: FeedFormat) -> Self {
+		Self {
+			format,
+			title: None,
+			items: 0,
+			warnings: vec![],
+		}
+	}
+
+	/// Whether this is a feed which can be subscribed to.
+	#[must_use]
+	pub fn is_feed(&self) -> bool {
+		matches!(self.format, FeedFormat::Rss | FeedFormat::Atom)
+	}
+}
+
+fn is_json_feed(bytes: &[u8]) -> bool {
+	let text = String::from_utf8_lossy(bytes);
+	text.trim_start().starts_with('{') && text.contains("jsonfeed.org/version")
+}
+
+/// Downloads `url` and reports on what's there, without subscribing to it.
+///
+/// # Errors
+/// Fails if the download does, including with a bad status.
+pub async fn validate_feed(url: &str) -> Result<FeedValidation, RefreshError> {
+	let (body, charset) = download_body(url, false).await?;
+	Ok(FeedValidation::from_bytes(&body, charset.as_deref()))
+}
+
+#[cfg(test)]
+mod test {
+	use super::{FeedFormat, FeedValidation};
+
+	#[test]
+	fn reports_on_rss() {
+		let report = FeedValidation::from_bytes(
+			br#"<?xml version="1.0"?>
+			<rss version="2.0"><channel><title>Valid</title><link>l</link><description>d</description>
+				<item><title>Dated</title><pubDate>Mon, 02 Oct 2023 10:00:00 +0000</pubDate></item>
+				<item><title>Undated</title></item>
+				<item><guid>nothing</guid></item>
+			</channel></rss>"#,
+			None,
+		);
+		assert_eq!(report.format, FeedFormat::Rss);
+		assert!(report.is_feed());
+		assert_eq!(report.title.as_deref(), Some("Valid"));
+		assert_eq!(report.items, 3);
+		assert_eq!(
+			report.warnings,
+			[
+				"2 of 3 items have no date",
+				"1 of 3 items have no title or content"
+			]
+		);
+	}
+
+	#[test]
+	fn reports_on_pages() {
+		let report = FeedValidation::from_bytes(
+			br#"<html><head><link rel="alternate" type="application/atom+xml" href="/atom.xml"></head></html>"#,
+			None,
+		);
+		let FeedFormat::Html(found) = &report.format else {
+			panic!("Expected a page");
+		};
+		assert_eq!(found[0].href, "/atom.xml");
+		assert!(!report.is_feed());
+	}
+
+	#[test]
+	fn reports_on_malformed_xml() {
+		let report =
+			FeedValidation::from_bytes(b"<rss version=\"2.0\"><channel><title>Cut", None);
+		assert!(matches!(report.format, FeedFormat::Invalid(_)));
+		assert!(!report.is_feed());
+
+		let report = FeedValidation::from_bytes(
+			br#"{"version": "https://jsonfeed.org/version/1.1", "title": "JSON", "items": []}"#,
+			None,
+		);
+		assert_eq!(report.format, FeedFormat::JsonFeed);
+		assert_eq!(report.warnings.len(), 1);
+	}
+}
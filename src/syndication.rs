@@ -1,48 +1,361 @@
-/// Copied from the `syndication` crate
-/// I don't want to figure out cargo vendoring rn so I'm doing this instead
+//! Copied from the `syndication` crate
+//! I don't want to figure out cargo vendoring rn so I'm doing this instead
 
-use std::{str::FromStr, string::FromUtf8Error};
+use std::{fmt::Display, str::FromStr, time::Duration};
+
+use encoding_rs::{Encoding, UTF_8};
+
+use crate::Error;
 
 #[derive(Clone)]
+#[allow(clippy::large_enum_variant)]
 pub enum Feed {
     Atom(atom_syndication::Feed),
     RSS(rss::Channel),
 }
 
-impl TryFrom<Vec<u8>> for Feed {
-    type Error = String;
+impl Feed {
+    /// The language the feed declares, from RSS `<language>` or Atom `xml:lang`.
+    pub fn language(&self) -> Option<String> {
+        let language = match self {
+            Feed::Atom(atom_feed) => atom_feed.lang(),
+            Feed::RSS(rss_channel) => rss_channel.language(),
+        };
+        language.map(str::trim).filter(|l| !l.is_empty()).map(ToString::to_string)
+    }
 
-    fn try_from(value: Vec<u8>) -> Result<Self, Self::Error> {
-        let txt = match String::from_utf8(value) {
-            Ok(t) => t,
-            Err(e) => {return Err(e.to_string())}
+    /// The image the feed declares for itself, from RSS `<image>` or Atom `<icon>`, falling
+    /// back to Atom's `<logo>`. Relative URLs are resolved against the feed's site.
+    #[must_use]
+    pub fn icon_url(&self) -> Option<String> {
+        let icon = match self {
+            Feed::Atom(atom_feed) => atom_feed.icon().or(atom_feed.logo()),
+            Feed::RSS(rss_channel) => rss_channel.image().map(rss::Image::url),
+        };
+        let icon = icon.map(str::trim).filter(|icon| !icon.is_empty())?;
+        match self.site_url().and_then(|site| reqwest::Url::parse(&site).ok()) {
+            Some(site) => site.join(icon).ok().map(String::from),
+            None => Some(icon.to_string()),
+        }
+    }
+
+    /// Where the feed's site would keep its `/favicon.ico`, for feeds which don't declare an
+    /// [icon](Feed::icon_url). That's on the feed's site, or failing that on the host the feed
+    /// itself is at, `pub_url`.
+    #[must_use]
+    pub fn favicon_url(&self, pub_url: &str) -> Option<String> {
+        let site = self
+            .site_url()
+            .and_then(|site| reqwest::Url::parse(&site).ok())
+            .or_else(|| reqwest::Url::parse(pub_url).ok())
+            .filter(|site| matches!(site.scheme(), "http" | "https"))?;
+        site.join("/favicon.ico").ok().map(String::from)
+    }
+
+    /// The website the feed belongs to, from RSS `<link>` or Atom's `alternate` link.
+    #[must_use]
+    pub fn site_url(&self) -> Option<String> {
+        let site = match self {
+            Feed::Atom(atom_feed) => atom_feed
+                .links()
+                .iter()
+                .find(|link| link.rel() == "alternate")
+                .map(atom_syndication::Link::href),
+            Feed::RSS(rss_channel) => Some(rss_channel.link()),
+        };
+        site.map(str::trim).filter(|site| !site.is_empty()).map(ToString::to_string)
+    }
+
+    /// How often the feed asks to be polled, from RSS `<ttl>` (in minutes), or failing that
+    /// the Syndication module's `sy:updatePeriod` and `sy:updateFrequency`, which Atom feeds
+    /// can use too.
+    #[must_use]
+    pub fn suggested_ttl(&self) -> Option<Duration> {
+        let (ttl, period, frequency) = match self {
+            Feed::RSS(rss_channel) => {
+                let syndication = rss_channel.syndication_ext();
+                (
+                    rss_channel.ttl(),
+                    syndication.map(|sy| sy.period().to_string()),
+                    syndication.map(|sy| sy.frequency().to_string()),
+                )
+            }
+            Feed::Atom(atom_feed) => {
+                let sy = atom_feed.extensions().get("sy");
+                let value = |name: &str| {
+                    sy.and_then(|sy| sy.get(name))
+                        .and_then(|values| values.first())
+                        .and_then(|value| value.value())
+                        .map(ToString::to_string)
+                };
+                (None, value("updatePeriod"), value("updateFrequency"))
+            }
+        };
+        if let Some(minutes) = ttl.and_then(|ttl| ttl.trim().parse::<u64>().ok()) {
+            return (minutes > 0).then(|| Duration::from_secs(minutes * 60));
+        }
+        let period: u64 = match period?.trim() {
+            "hourly" => 60 * 60,
+            "daily" => 24 * 60 * 60,
+            "weekly" => 7 * 24 * 60 * 60,
+            "monthly" => 30 * 24 * 60 * 60,
+            "yearly" => 365 * 24 * 60 * 60,
+            _ => return None,
         };
-        match Feed::from_str(&txt) {
-            Ok(f) => Ok(f),
-            Err(e) => Err(e.to_string())
+        // The frequency is how many updates there are per period.
+        let frequency = frequency
+            .and_then(|frequency| frequency.trim().parse::<u64>().ok())
+            .filter(|frequency| *frequency > 0)
+            .unwrap_or(1);
+        Some(Duration::from_secs(period / frequency))
+    }
+
+    /// The feed's title, as it gives it.
+    #[must_use]
+    pub fn title(&self) -> &str {
+        match self {
+            Feed::Atom(atom_feed) => atom_feed.title().as_str(),
+            Feed::RSS(rss_channel) => rss_channel.title(),
+        }
+    }
+}
+
+/// Which way a feed's text runs.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TextDirection {
+    #[default]
+    LeftToRight,
+    RightToLeft,
+}
+
+impl TextDirection {
+    /// The direction of a language tag like `ar`, `he-IL` or `az-Arab`. Unknown languages are
+    /// left-to-right.
+    pub fn of_language(tag: &str) -> Self {
+        let mut subtags = tag.split(['-', '_']).map(str::to_ascii_lowercase);
+        let language = subtags.next().unwrap_or_default();
+        // An explicit script wins over the language's usual one.
+        if let Some(script) = subtags.find(|s| s.len() == 4 && s.chars().all(|c| c.is_ascii_alphabetic())) {
+            return match script.as_str() {
+                "adlm" | "arab" | "hebr" | "nkoo" | "rohg" | "syrc" | "thaa" => Self::RightToLeft,
+                _ => Self::LeftToRight,
+            };
         }
+        match language.as_str() {
+            "ar" | "arc" | "ckb" | "dv" | "fa" | "he" | "iw" | "ji" | "ks" | "ku" | "ps" | "sd"
+            | "syr" | "ug" | "ur" | "yi" => Self::RightToLeft,
+            _ => Self::LeftToRight,
+        }
+    }
+}
+
+/// Decodes a feed's bytes as text. The encoding comes from a byte-order mark, then the
+/// `charset` of the HTTP `Content-Type`, then the XML declaration, and is otherwise assumed to
+/// be UTF-8. Anything invalid in that encoding is replaced rather than failing outright.
+///
+/// The XML declaration is dropped, since the parsers would otherwise believe its encoding over
+/// the UTF-8 the text is now in.
+pub fn decode_feed(bytes: &[u8], charset: Option<&str>) -> String {
+    let (encoding, bom_len) = Encoding::for_bom(bytes)
+        .or_else(|| {
+            let encoding = charset
+                .and_then(|label| Encoding::for_label(label.trim().as_bytes()))
+                // A declaration which could be read as ASCII can't really be in UTF-16.
+                .or_else(|| declared_encoding(bytes).map(Encoding::output_encoding))?;
+            Some((encoding, 0))
+        })
+        .unwrap_or((UTF_8, 0));
+    let (text, _) = encoding.decode_without_bom_handling(&bytes[bom_len..]);
+    without_declaration(&text).to_string()
+}
+
+/// The XML declaration at the start of `text`, without its `<?xml` and `?>`.
+fn declaration(text: &[u8]) -> Option<(&[u8], usize)> {
+    let start = text.iter().position(|b| !b.is_ascii_whitespace())?;
+    let rest = text[start..].strip_prefix(b"<?xml")?;
+    // Not a processing instruction like `<?xml-stylesheet`.
+    if !rest.first()?.is_ascii_whitespace() {
+        return None;
+    }
+    let len = rest.windows(2).position(|w| w == b"?>")?;
+    Some((&rest[..len], start + "<?xml".len() + len + "?>".len()))
+}
+
+/// The encoding named by the XML declaration, if there is one.
+fn declared_encoding(bytes: &[u8]) -> Option<&'static Encoding> {
+    let (declaration, _) = declaration(bytes)?;
+    let declaration = std::str::from_utf8(declaration).ok()?;
+    let (_, rest) = declaration.split_once("encoding")?;
+    let rest = rest.trim_start().strip_prefix('=')?.trim_start();
+    let quote = rest.chars().next().filter(|c| *c == '"' || *c == '\'')?;
+    let label = rest[1..].split(quote).next()?;
+    Encoding::for_label(label.as_bytes())
+}
+
+fn without_declaration(text: &str) -> &str {
+    match declaration(text.as_bytes()) {
+        Some((_, end)) => &text[end..],
+        None => text,
+    }
+}
+
+impl TryFrom<Vec<u8>> for Feed {
+    type Error = Error;
+
+    fn try_from(value: Vec<u8>) -> Result<Self, Self::Error> {
+        Feed::from_str(&decode_feed(&value, None))
     }
 }
 
 impl FromStr for Feed {
-    type Err = &'static str;
+    type Err = Error;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // A byte-order mark or blank lines before the XML declaration make it invalid, but
+        // plenty of feeds have them anyway.
+        let s = s.trim_start_matches('\u{feff}').trim_start();
         match atom_syndication::Feed::from_str(s) {
             Ok(feed) => Ok(Feed::Atom(feed)),
-            _ => match rss::Channel::from_str(s) {
+            Err(atom) => match rss::Channel::from_str(s) {
                 Ok(feed) => Ok(Feed::RSS(feed)),
-                _ => Err("Could not parse XML as Atom or RSS from input"),
+                Err(rss) => Err(Error::Parse { atom, rss }),
             },
         }
     }
 }
 
-impl ToString for Feed {
-    fn to_string(&self) -> String {
+impl Display for Feed {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            Feed::Atom(atom_feed) => atom_feed.to_string(),
-            Feed::RSS(rss_channel) => rss_channel.to_string(),
+            Feed::Atom(atom_feed) => f.write_str(&atom_feed.to_string()),
+            Feed::RSS(rss_channel) => f.write_str(&rss_channel.to_string()),
         }
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod test {
+    use std::{str::FromStr, time::Duration};
+
+    use super::{decode_feed, Feed, TextDirection};
+
+    #[test]
+    fn reads_icon_url() {
+        let rss = Feed::from_str(
+            r#"<rss version="2.0"><channel><title>t</title><link>https://example.com/blog/</link><description>d</description>
+                <image><url>/icon.png</url><title>t</title><link>https://example.com/</link></image>
+            </channel></rss>"#,
+        )
+        .unwrap();
+        assert_eq!(rss.icon_url().as_deref(), Some("https://example.com/icon.png"));
+
+        let atom = |inner: &str| {
+            Feed::from_str(&format!(
+                r#"<feed xmlns="http://www.w3.org/2005/Atom"><title>t</title><id>i</id><updated>2023-10-02T10:00:00Z</updated>{inner}</feed>"#
+            ))
+            .unwrap()
+        };
+        assert_eq!(
+            atom(r#"<icon>https://example.com/favicon.png</icon><logo>https://example.com/logo.png</logo>"#)
+                .icon_url()
+                .as_deref(),
+            Some("https://example.com/favicon.png")
+        );
+        assert_eq!(
+            atom(r#"<link rel="alternate" href="https://example.org/"/><logo>logo.png</logo>"#)
+                .icon_url()
+                .as_deref(),
+            Some("https://example.org/logo.png")
+        );
+        assert_eq!(atom("").icon_url(), None);
+        assert_eq!(
+            atom("").favicon_url("https://feeds.example.net/atom.xml?x=1").as_deref(),
+            Some("https://feeds.example.net/favicon.ico")
+        );
+        assert_eq!(
+            rss.favicon_url("https://feeds.example.net/rss").as_deref(),
+            Some("https://example.com/favicon.ico")
+        );
+    }
+
+    #[test]
+    fn reads_suggested_ttl() {
+        let rss = |inner: &str| {
+            Feed::from_str(&format!(
+                r#"<rss version="2.0" xmlns:sy="http://purl.org/rss/1.0/modules/syndication/"><channel><title>t</title><link>l</link><description>d</description>{inner}</channel></rss>"#
+            ))
+            .unwrap()
+        };
+        assert_eq!(rss("<ttl>60</ttl>").suggested_ttl(), Some(Duration::from_secs(60 * 60)));
+        assert_eq!(
+            rss("<sy:updatePeriod>hourly</sy:updatePeriod>").suggested_ttl(),
+            Some(Duration::from_secs(60 * 60))
+        );
+        assert_eq!(
+            rss("<sy:updatePeriod>daily</sy:updatePeriod><sy:updateFrequency>4</sy:updateFrequency>")
+                .suggested_ttl(),
+            Some(Duration::from_secs(6 * 60 * 60))
+        );
+        assert_eq!(rss("").suggested_ttl(), None);
+
+        let atom = Feed::from_str(
+            r#"<feed xmlns="http://www.w3.org/2005/Atom" xmlns:sy="http://purl.org/rss/1.0/modules/syndication/"><title>t</title><id>i</id><updated>2023-01-01T00:00:00Z</updated><sy:updatePeriod>hourly</sy:updatePeriod><sy:updateFrequency>2</sy:updateFrequency></feed>"#,
+        )
+        .unwrap();
+        assert_eq!(atom.suggested_ttl(), Some(Duration::from_secs(30 * 60)));
+    }
+
+    #[test]
+    fn reads_language() {
+        let rss = Feed::from_str(
+            r#"<rss version="2.0"><channel><title>t</title><link>l</link><description>d</description><language>he-IL</language></channel></rss>"#,
+        )
+        .unwrap();
+        assert_eq!(rss.language().as_deref(), Some("he-IL"));
+        let atom = Feed::from_str(
+            r#"<feed xmlns="http://www.w3.org/2005/Atom" xml:lang="en"><title>t</title><id>i</id><updated>2023-01-01T00:00:00Z</updated></feed>"#,
+        )
+        .unwrap();
+        assert_eq!(atom.language().as_deref(), Some("en"));
+        let unlabeled = Feed::from_str(
+            r#"<rss version="2.0"><channel><title>t</title><link>l</link><description>d</description></channel></rss>"#,
+        )
+        .unwrap();
+        assert_eq!(unlabeled.language(), None);
+    }
+
+    #[test]
+    fn decodes_declared_encodings() {
+        let title = |bytes: &[u8], charset| match Feed::from_str(&decode_feed(bytes, charset)).unwrap() {
+            Feed::RSS(channel) => channel.title().to_string(),
+            Feed::Atom(_) => panic!("Parsed RSS as Atom"),
+        };
+        let (body, _, _) = encoding_rs::WINDOWS_1252.encode(
+            "<rss version=\"2.0\"><channel><title>Caf\u{e9} cr\u{e8}me \u{2013} \u{20ac}5</title><link>l</link><description>d</description></channel></rss>",
+        );
+        let declared = [
+            br#"<?xml version="1.0" encoding="windows-1252"?>"#.as_slice(),
+            &body,
+        ]
+        .concat();
+        assert_eq!(title(&declared, None), "Caf\u{e9} cr\u{e8}me \u{2013} \u{20ac}5");
+        // ISO-8859-1 is really Windows-1252, as far as the web is concerned.
+        let latin1 = [br#"<?xml version='1.0' encoding='ISO-8859-1' ?>"#.as_slice(), &body].concat();
+        assert_eq!(title(&latin1, None), "Caf\u{e9} cr\u{e8}me \u{2013} \u{20ac}5");
+        // The HTTP charset wins over the declaration.
+        assert_eq!(title(&body, Some("windows-1252")), "Caf\u{e9} cr\u{e8}me \u{2013} \u{20ac}5");
+        let utf8 = "<?xml version=\"1.0\" encoding=\"ISO-8859-1\"?><rss version=\"2.0\"><channel><title>Caf\u{e9}</title><link>l</link><description>d</description></channel></rss>";
+        assert_eq!(title(utf8.as_bytes(), Some("utf-8")), "Caf\u{e9}");
+        // Undeclared and invalid UTF-8 still parses, with the bad bytes replaced.
+        assert_eq!(title(&body, None), "Caf\u{fffd} cr\u{fffd}me \u{fffd} \u{fffd}5");
+    }
+
+    #[test]
+    fn maps_languages_to_directions() {
+        for rtl in ["ar", "he-IL", "fa_IR", "UR", "az-Arab"] {
+            assert_eq!(TextDirection::of_language(rtl), TextDirection::RightToLeft, "{rtl}");
+        }
+        for ltr in ["en", "en-us", "ja", "ku-Latn", "", "xx-unknown"] {
+            assert_eq!(TextDirection::of_language(ltr), TextDirection::LeftToRight, "{ltr}");
+        }
+    }
+}
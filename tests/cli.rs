@@ -0,0 +1,40 @@
+//! Drives the headless subcommands of the `winter` binary.
+#![cfg(feature = "gui")]
+
+use std::process::Command;
+
+fn winter(args: &[&str]) -> String {
+	let output = Command::new(env!("CARGO_BIN_EXE_winter"))
+		.args(args)
+		.output()
+		.expect("Failed to run winter");
+	assert!(
+		output.status.success(),
+		"winter {args:?} failed: {}",
+		String::from_utf8_lossy(&output.stderr)
+	);
+	String::from_utf8(output.stdout).expect("Output isn't UTF-8")
+}
+
+#[test]
+fn add_list_and_export() {
+	let tmp = tempdir::TempDir::new("winter_cli_test").unwrap();
+	let feed = tmp.path().join("feed.xml");
+	std::fs::write(
+		&feed,
+		r#"<?xml version="1.0"?>
+		<rss version="2.0"><channel><title>Mock Feed</title><link>https://example.com</link><description>.</description>
+			<item><title>One</title><guid>one</guid></item>
+		</channel></rss>"#,
+	)
+	.unwrap();
+	let url = format!("file://{}", feed.display());
+	let db = tmp.path().join("db");
+	let db = db.to_str().unwrap();
+
+	assert_eq!(winter(&[db, "add", &url]), format!("Subscribed to {url}\n"));
+	assert_eq!(winter(&[db, "list"]), format!("{url}\tMock Feed\n"));
+	assert!(winter(&[db, "export-opml"]).contains(&format!(
+		"<outline type=\"rss\" text=\"Mock Feed\" title=\"Mock Feed\" xmlUrl=\"{url}\"/>"
+	)));
+}